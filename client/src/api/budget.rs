@@ -0,0 +1,419 @@
+use crate::api::tools::MAX_TOOL_STEPS;
+use crate::api::usage::{self, UsageLedger, UsageRecord};
+use crate::api::ApiState;
+use crate::config;
+use crate::models::{SendMessageRequest, Usage};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, State};
+
+// Rough size of a token in English text
+pub(crate) const CHARS_PER_TOKEN: usize = 4;
+// Assumed reply length when the request doesn't set max_tokens
+const DEFAULT_OUTPUT_ESTIMATE: u64 = 1000;
+const SECONDS_PER_DAY: u64 = 86_400;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BudgetPeriod {
+    Daily,
+    Monthly,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BudgetAction {
+    #[default]
+    Block,
+    Warn,
+}
+
+/// A spending cap, stored under `budgets` in `config.json`. Periods follow
+/// UTC calendar days and months.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Budget {
+    // Applies to every provider combined when not set
+    #[serde(default)]
+    pub provider: Option<String>,
+    pub period: BudgetPeriod,
+    // USD
+    pub limit: f64,
+    #[serde(default)]
+    pub action: BudgetAction,
+    // Refuse models without a price too, rather than only warning
+    #[serde(default)]
+    pub block_unpriced: bool,
+}
+
+impl Budget {
+    fn covers(&self, provider: &str) -> bool {
+        self.provider.as_deref().map_or(true, |id| id == provider)
+    }
+
+    fn spent(&self, records: &[UsageRecord], now: u64) -> f64 {
+        let start = period_start(self.period, now);
+        records
+            .iter()
+            .filter(|record| record.timestamp >= start && self.covers(&record.provider))
+            .filter_map(|record| record.cost)
+            .sum()
+    }
+}
+
+/// A budget a request would go over, returned with the response.
+#[derive(Serialize, Clone, Debug)]
+pub struct BudgetAlert {
+    pub budget: Budget,
+    pub spent: f64,
+    pub estimate: f64,
+    // Set when the model has no price, so the request couldn't be costed
+    pub unpriced: bool,
+    // Whether the request was refused rather than sent with a warning
+    pub blocked: bool,
+}
+
+/// Where a budget stands for the current period.
+#[derive(Serialize, Debug)]
+pub struct BudgetStatus {
+    pub budget: Budget,
+    pub spent: f64,
+    pub remaining: f64,
+    // Unix time in seconds when the period starts over
+    pub resets_at: u64,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or_default()
+}
+
+fn period_start(period: BudgetPeriod, now: u64) -> u64 {
+    let day_start = now - now % SECONDS_PER_DAY;
+    match period {
+        BudgetPeriod::Daily => day_start,
+        BudgetPeriod::Monthly => {
+            let (_, _, day) = usage::date_from_days((now / SECONDS_PER_DAY) as i64);
+            day_start - u64::from(day - 1) * SECONDS_PER_DAY
+        }
+    }
+}
+
+fn period_end(period: BudgetPeriod, now: u64) -> u64 {
+    let start = period_start(period, now);
+    match period {
+        BudgetPeriod::Daily => start + SECONDS_PER_DAY,
+        // 32 days on from the 1st always lands in the next month
+        BudgetPeriod::Monthly => period_start(period, start + 32 * SECONDS_PER_DAY),
+    }
+}
+
+/// Budgets from `config.json`. Entries that don't parse are logged and
+/// skipped.
+pub fn budgets(config: &Value) -> Vec<Budget> {
+    let entries = match config["budgets"].as_array() {
+        Some(entries) => entries,
+        None => return Vec::new(),
+    };
+
+    entries
+        .iter()
+        .filter_map(
+            |entry| match serde_json::from_value::<Budget>(entry.clone()) {
+                Ok(budget) => Some(budget),
+                Err(e) => {
+                    println!("Skipping invalid budget {}: {}", entry, e);
+                    None
+                }
+            },
+        )
+        .collect()
+}
+
+/// Upper-end guess at what a request will cost, from the size of the prompt
+/// and the requested output limit. `None` when the model has no price.
+pub fn estimate_cost(config: &Value, provider: &str, request: &SendMessageRequest) -> Option<f64> {
+    let prices = usage::pricing(config);
+    let price = usage::price_for(&prices, provider, &request.model)?;
+    Some(price.cost(&estimate_usage(request)))
+}
+
+/// Tokens a request is likely to use, guessed from the length of the prompt
+/// and attachment. Requests offering tools may make a call per step of the
+/// tool loop, each resending the prompt.
+pub fn estimate_usage(request: &SendMessageRequest) -> Usage {
+    let prompt_chars: usize = request
+        .conversation()
+        .iter()
+        .map(|message| message.content.len())
+        .sum::<usize>()
+        + request.file_content.as_deref().map_or(0, str::len);
+
+    let output = request
+        .params
+        .max_tokens
+        .map_or(DEFAULT_OUTPUT_ESTIMATE, u64::from)
+        + request.params.thinking_budget.map_or(0, u64::from);
+    // The steps that may call tools plus the final answer
+    let calls = if request.offers_tools() {
+        MAX_TOOL_STEPS as u64 + 1
+    } else {
+        1
+    };
+
+    Usage {
+        input_tokens: Some((prompt_chars / CHARS_PER_TOKEN) as u64 * calls),
+        output_tokens: Some(output * calls),
+        ..Default::default()
+    }
+}
+
+/// Budgets that sending `request` to `provider` would go over. A model
+/// without a price can't be checked, so it gets a warning from every budget
+/// that covers it instead of counting as free. Only budgets with
+/// `block_unpriced` set refuse it.
+pub fn check(
+    config: &Value,
+    ledger: &UsageLedger,
+    provider: &str,
+    request: &SendMessageRequest,
+) -> Result<Vec<BudgetAlert>, String> {
+    if !budgets(config).iter().any(|budget| budget.covers(provider)) {
+        return Ok(Vec::new());
+    }
+
+    let now = now();
+    let records = ledger.records(Some(period_start(BudgetPeriod::Monthly, now)), None)?;
+    Ok(alerts(config, &records, provider, request, now))
+}
+
+fn alerts(
+    config: &Value,
+    records: &[UsageRecord],
+    provider: &str,
+    request: &SendMessageRequest,
+    now: u64,
+) -> Vec<BudgetAlert> {
+    let estimate = estimate_cost(config, provider, request);
+
+    budgets(config)
+        .into_iter()
+        .filter(|budget| budget.covers(provider))
+        .filter_map(|budget| {
+            let spent = budget.spent(records, now);
+            if estimate.map_or(false, |estimate| spent + estimate <= budget.limit) {
+                return None;
+            }
+
+            Some(BudgetAlert {
+                blocked: budget.action == BudgetAction::Block
+                    && (estimate.is_some() || budget.block_unpriced),
+                budget,
+                spent,
+                estimate: estimate.unwrap_or(0.0),
+                unpriced: estimate.is_none(),
+            })
+        })
+        .collect()
+}
+
+pub fn describe(alert: &BudgetAlert) -> String {
+    let period = match alert.budget.period {
+        BudgetPeriod::Daily => "daily",
+        BudgetPeriod::Monthly => "monthly",
+    };
+    if alert.unpriced {
+        return format!(
+            "This model has no price, so the {} {} budget of ${:.2} can't be checked. Add a price for it under pricing.",
+            alert.budget.provider.as_deref().unwrap_or("overall"),
+            period,
+            alert.budget.limit
+        );
+    }
+    format!(
+        "This request would exceed the {} {} budget of ${:.2} (${:.2} spent, about ${:.4} for this request).",
+        alert.budget.provider.as_deref().unwrap_or("overall"),
+        period,
+        alert.budget.limit,
+        alert.spent,
+        alert.estimate
+    )
+}
+
+#[tauri::command]
+pub fn budget_status(
+    app_handle: AppHandle,
+    state: State<'_, ApiState>,
+) -> Result<Vec<BudgetStatus>, String> {
+    let budgets = budgets(&config::load_stored_keys(&app_handle)?);
+    if budgets.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let now = now();
+    // The month so far covers every period
+    let records = state
+        .usage
+        .records(Some(period_start(BudgetPeriod::Monthly, now)), None)?;
+
+    Ok(budgets
+        .into_iter()
+        .map(|budget| {
+            let spent = budget.spent(&records, now);
+            BudgetStatus {
+                remaining: (budget.limit - spent).max(0.0),
+                resets_at: period_end(budget.period, now),
+                spent,
+                budget,
+            }
+        })
+        .collect())
+}
+
+#[tauri::command]
+pub fn set_budgets(app_handle: AppHandle, budgets: Vec<Budget>) -> Result<(), String> {
+    let mut config = config::load_stored_keys(&app_handle)?;
+    let config_obj = config.as_object_mut().ok_or("Invalid stored keys format")?;
+
+    if budgets.is_empty() {
+        config_obj.remove("budgets");
+    } else {
+        config_obj.insert(
+            "budgets".to_string(),
+            serde_json::to_value(budgets).map_err(|e| e.to_string())?,
+        );
+    }
+    config::save_keys(&app_handle, &config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // 2024-03-15 12:00 UTC
+    const NOW: u64 = 1_710_504_000;
+
+    fn request(body: Value) -> SendMessageRequest {
+        serde_json::from_value(body).unwrap()
+    }
+
+    fn spend(provider: &str, timestamp: u64, cost: f64) -> UsageRecord {
+        UsageRecord {
+            timestamp,
+            cost: Some(cost),
+            ..UsageRecord::new(provider, "gpt-4o", "r", Usage::default())
+        }
+    }
+
+    #[test]
+    fn periods_follow_utc_days_and_months() {
+        assert_eq!(period_start(BudgetPeriod::Daily, NOW), 1_710_460_800);
+        assert_eq!(
+            period_end(BudgetPeriod::Daily, NOW),
+            1_710_460_800 + SECONDS_PER_DAY
+        );
+        assert_eq!(period_start(BudgetPeriod::Monthly, NOW), 1_709_251_200);
+        assert_eq!(period_end(BudgetPeriod::Monthly, NOW), 1_711_929_600);
+        // 2024-12-31 05:00 rolls over into the next year
+        assert_eq!(
+            period_end(BudgetPeriod::Monthly, 1_735_621_200),
+            1_735_689_600
+        );
+    }
+
+    #[test]
+    fn estimates_cover_thinking_and_tool_steps() {
+        let plain = estimate_usage(&request(json!({
+            "message": "a".repeat(400),
+            "model": "claude-sonnet-4",
+            "provider": "anthropic",
+            "max_tokens": 500,
+            "thinking_budget": 2000
+        })));
+        assert_eq!(plain.input_tokens, Some(100));
+        assert_eq!(plain.output_tokens, Some(2500));
+
+        let with_tools = estimate_usage(&request(json!({
+            "message": "a".repeat(400),
+            "model": "gpt-4o",
+            "provider": "openai",
+            "enabled_tools": ["web_search"]
+        })));
+        let calls = MAX_TOOL_STEPS as u64 + 1;
+        assert_eq!(with_tools.input_tokens, Some(100 * calls));
+        assert_eq!(
+            with_tools.output_tokens,
+            Some(DEFAULT_OUTPUT_ESTIMATE * calls)
+        );
+    }
+
+    #[test]
+    fn alerts_for_budgets_the_request_would_exceed() {
+        let config = json!({ "budgets": [
+            { "period": "daily", "limit": 1.0 },
+            { "provider": "openai", "period": "monthly", "limit": 10.0, "action": "warn" },
+            { "provider": "xai", "period": "daily", "limit": 0.0 }
+        ]});
+        let records = [
+            // Yesterday, so only in the monthly total
+            spend("openai", NOW - SECONDS_PER_DAY, 9.5),
+            spend("openai", NOW - 60, 0.9),
+            spend("xai", NOW - 60, 5.0),
+        ];
+        let request = request(json!({
+            "message": "hello",
+            "model": "gpt-4o",
+            "provider": "openai",
+            "max_tokens": 10_000
+        }));
+
+        let alerts = alerts(&config, &records, "openai", &request, NOW);
+        assert_eq!(alerts.len(), 2);
+        assert!(alerts[0].blocked);
+        // The overall budget counts every provider
+        assert!((alerts[0].spent - 5.9).abs() < 1e-9);
+        assert!(!alerts[1].blocked);
+        assert!((alerts[1].spent - 10.4).abs() < 1e-9);
+        assert!(alerts
+            .iter()
+            .all(|alert| alert.estimate > 0.09 && !alert.unpriced));
+
+        let cheap = SendMessageRequest {
+            params: serde_json::from_value(json!({ "max_tokens": 100 })).unwrap(),
+            ..request.clone()
+        };
+        let alerts = super::alerts(&config, &records[..2], "openai", &cheap, NOW);
+        assert_eq!(alerts.len(), 1);
+        assert!(!alerts[0].blocked);
+    }
+
+    #[test]
+    fn unpriced_models_are_not_free() {
+        let config = json!({ "budgets": [{ "period": "daily", "limit": 100.0 }] });
+        let request = request(json!({
+            "message": "hello",
+            "model": "ft:my-model",
+            "provider": "openai"
+        }));
+
+        let alerts = alerts(&config, &[], "openai", &request, NOW);
+        assert_eq!(alerts.len(), 1);
+        assert!(alerts[0].unpriced);
+        assert!(!alerts[0].blocked);
+        assert!(describe(&alerts[0]).contains("no price"));
+
+        let strict = json!({ "budgets": [
+            { "period": "daily", "limit": 100.0, "block_unpriced": true }
+        ]});
+        assert!(super::alerts(&strict, &[], "openai", &request, NOW)[0].blocked);
+
+        let local = SendMessageRequest {
+            provider: "local".to_string(),
+            ..request
+        };
+        assert!(super::alerts(&config, &[], "local", &local, NOW).is_empty());
+    }
+}
@@ -1,1012 +1,650 @@
-use crate::api::ApiKeys;
-use crate::models::{ApiResponse, SendMessageRequest};
+use crate::api::budget::{self, BudgetAlert};
+use crate::api::fallback;
+use crate::api::http;
+use crate::api::in_flight::InFlight;
+use crate::api::providers::retry::{self, RetryListener, RetryNotice};
+use crate::api::providers::{ChunkSink, Provider, ProviderError, StreamChunk};
+use crate::api::router;
+use crate::api::structured;
+use crate::api::tools::{self, ToolContext};
+use crate::api::usage::{self, UsageMeter, UsageRecord};
+use crate::api::ApiState;
+use crate::models::{ApiResponse, FallbackNotice, RetryProgress, SendMessageRequest, StreamEvent};
 use reqwest;
 use std::env;
-use tauri::{AppHandle, State};
+use std::sync::Arc;
+use tauri::ipc::Channel;
+use tauri::{AppHandle, Emitter, State};
+
+/// Looks up the key for a provider: stored config first, then the keys held
+/// in state, then the provider's environment variable.
+pub fn resolve_api_key(
+    app_handle: &AppHandle,
+    state: &ApiState,
+    provider: &dyn Provider,
+) -> Result<String, String> {
+    let stored_keys = crate::config::load_stored_keys(app_handle)?;
+    if let Some(key) = crate::config::stored_key(&stored_keys, provider.id()) {
+        return Ok(key);
+    }
+
+    match state.keys.get_key(provider.id()) {
+        Some(key) if !key.is_empty() => Ok(key),
+        _ => Ok(env::var(provider.env_var()).unwrap_or_default()),
+    }
+}
+
+fn ignored_params(provider: &dyn Provider, request: &SendMessageRequest) -> Option<Vec<String>> {
+    let supported = provider.supported_params(request);
+    let ignored: Vec<String> = request
+        .params
+        .requested()
+        .into_iter()
+        .filter(|name| !supported.contains(name))
+        .map(String::from)
+        .collect();
+
+    if ignored.is_empty() {
+        None
+    } else {
+        println!("{} ignores parameters: {:?}", provider.id(), ignored);
+        Some(ignored)
+    }
+}
+
+fn retry_progress(request_id: &str, notice: RetryNotice) -> RetryProgress {
+    RetryProgress {
+        request_id: request_id.to_string(),
+        attempt: notice.attempt,
+        max_attempts: notice.max_attempts,
+        delay_ms: notice.delay.as_millis() as u64,
+        reason: notice.reason,
+    }
+}
+
+/// Prices the response's usage and adds it to the ledger. Failing to record
+/// is logged rather than failing a request that already got its answer.
+fn record_usage(
+    app_handle: &AppHandle,
+    state: &ApiState,
+    provider: &dyn Provider,
+    request: &SendMessageRequest,
+    request_id: &str,
+    response: &mut ApiResponse,
+) {
+    let usage = match &response.usage {
+        Some(usage) => usage.clone(),
+        None => return,
+    };
+
+    let config = crate::config::load_stored_keys(app_handle).unwrap_or_default();
+    let prices = usage::pricing(&config);
+    response.cost =
+        usage::price_for(&prices, provider.id(), &request.model).map(|price| price.cost(&usage));
+
+    let record = UsageRecord {
+        thread_id: request.thread_id.clone(),
+        cost: response.cost,
+        ..UsageRecord::new(provider.id(), &request.model, request_id, usage)
+    };
+    if let Err(e) = state.usage.append(&record) {
+        println!("Failed to record usage: {}", e);
+    }
+}
+
+/// Finds the provider for a request and the key to call it with, checks
+/// the request against the spending budgets and reads the text of a PDF or
+/// EPUB attachment. Failures come back as a response that can be handed
+/// straight to the frontend.
+async fn prepare(
+    request: &mut SendMessageRequest,
+    app_handle: &AppHandle,
+    state: &ApiState,
+) -> Result<(Arc<dyn Provider>, String, Vec<BudgetAlert>), ApiResponse> {
+    let provider = state
+        .providers
+        .get(&request.provider)
+        .ok_or_else(|| ApiResponse::error(format!("Unsupported provider: {}", request.provider)))?;
+
+    let api_key =
+        resolve_api_key(app_handle, state, provider.as_ref()).map_err(ApiResponse::error)?;
+    if api_key.is_empty() && provider.requires_key() {
+        return Err(ApiResponse::failed(ProviderError::missing_key(
+            provider.display_name(),
+        )));
+    }
+
+    let config = crate::config::load_stored_keys(app_handle).unwrap_or_default();
+    let alerts = budget::check(&config, &state.usage, provider.id(), request).unwrap_or_else(|e| {
+        println!("Failed to check budgets: {}", e);
+        Vec::new()
+    });
+    for alert in &alerts {
+        println!("{}", budget::describe(alert));
+    }
+    if let Some(alert) = alerts.iter().find(|alert| alert.blocked) {
+        return Err(ApiResponse {
+            budget_alerts: Some(alerts.clone()),
+            ..ApiResponse::error(budget::describe(alert))
+        });
+    }
+
+    // Once here rather than on every step of the tool loop
+    if let Some(attachment) = request.attachment() {
+        request.attachment_text = attachment.extract_text().await;
+    }
+
+    println!(
+        "Sending message to {} with model: {}",
+        provider.id(),
+        request.model
+    );
+    Ok((provider, api_key, alerts))
+}
+
+/// Sends the request through the tool loop, or through schema validation
+/// when it asks for structured output. Structured requests aren't offered
+/// tools.
+#[allow(clippy::too_many_arguments)]
+async fn dispatch(
+    provider: &dyn Provider,
+    client: &reqwest::Client,
+    api_key: &str,
+    request: &SendMessageRequest,
+    state: &ApiState,
+    context: &ToolContext,
+    spent: &UsageMeter,
+    on_chunk: Option<ChunkSink<'_>>,
+    on_event: &(dyn Fn(StreamEvent) + Send + Sync),
+) -> Result<ApiResponse, ProviderError> {
+    if request.response_schema.is_some() {
+        structured::run(provider, client, api_key, request.clone(), spent, on_chunk).await
+    } else {
+        tools::run(
+            provider,
+            client,
+            api_key,
+            request.clone(),
+            &state.tools,
+            context,
+            spent,
+            on_chunk,
+            on_event,
+        )
+        .await
+    }
+}
 
 #[tauri::command]
 pub async fn send_message(
-    request: SendMessageRequest,
+    mut request: SendMessageRequest,
     app_handle: AppHandle,
-    state: State<'_, ApiKeys>,
+    state: State<'_, ApiState>,
 ) -> Result<ApiResponse, String> {
-    let client = reqwest::Client::new();
-    let message = request.message.clone();
-
-    match request.provider.as_str() {
-        "anthropic" => {
-            let stored_keys = crate::config::load_stored_keys(&app_handle)?;
-            let api_key = if let Some(key) = stored_keys["anthropic"].as_str() {
-                key.to_string()
-            } else {
-                let state_key = state.anthropic.lock().unwrap();
-                match state_key.clone() {
-                    Some(key) if !key.is_empty() => key,
-                    _ => env::var("ANTHROPIC_API_KEY").unwrap_or_default(),
-                }
-            };
-
-            if api_key.is_empty() {
-                return Ok(ApiResponse {
-                    content: None,
-                    error: Some(
-                        "Anthropic API key not configured. Please add your API key in settings."
-                            .to_string(),
-                    ),
-                    citations: None,
-                    images: None,
-                    related_questions: None,
-                });
-            }
-
-            let request_body = serde_json::json!({
-                "model": request.model,
-                "messages": [{
-                    "role": "user",
-                    "content": message
-                }],
-                "max_tokens": 1024,
-                "system": "You are a helpful AI assistant."
-            });
-
-            let response = client
-                .post("https://api.anthropic.com/v1/messages")
-                .header("x-api-key", api_key)
-                .header("anthropic-version", "2023-06-01")
-                .header("content-type", "application/json")
-                .json(&request_body)
-                .send()
-                .await
-                .map_err(|e| e.to_string())?;
-
-            let status = response.status();
-            let response_text = response.text().await.map_err(|e| e.to_string())?;
-
-            if status.is_success() {
-                let json: serde_json::Value =
-                    serde_json::from_str(&response_text).map_err(|e| e.to_string())?;
-
-                Ok(ApiResponse {
-                    content: Some(
-                        json["content"][0]["text"]
-                            .as_str()
-                            .unwrap_or_default()
-                            .to_string(),
-                    ),
-                    error: None,
-                    citations: None,
-                    images: None,
-                    related_questions: None,
-                })
-            } else {
-                Ok(ApiResponse {
-                    content: None,
-                    error: Some(format!("API Error: {}", response_text)),
-                    citations: None,
-                    images: None,
-                    related_questions: None,
-                })
-            }
+    // Cancellable from here on, through every fallback attempt
+    let request_id = request.request_id();
+    request.request_id = Some(request_id.clone());
+    let in_flight = state.in_flight.register(&request_id);
+
+    let routed = router::route(request, &app_handle, &state).and_then(|(request, route)| {
+        let targets = fallback::targets(&app_handle, &request)?;
+        Ok((request, route, targets))
+    });
+    let (request, route, targets) = match routed {
+        Ok(routed) => routed,
+        Err(e) => return Ok(ApiResponse::error(e)),
+    };
+    let on_fallback = |notice: FallbackNotice| {
+        if let Err(e) = app_handle.emit("model-fallback", notice) {
+            eprintln!("Failed to emit model fallback: {:?}", e);
         }
-        "openai" => {
-            println!("Starting OpenAI API request...");
-            let stored_keys = crate::config::load_stored_keys(&app_handle)?;
-            let api_key = if let Some(key) = stored_keys["openai"].as_str() {
-                println!("Using stored API key");
-                key.to_string()
-            } else {
-                let state_key = state.openai.lock().unwrap();
-                match state_key.clone() {
-                    Some(key) if !key.is_empty() => {
-                        println!("Using state API key");
-                        key
-                    }
-                    _ => {
-                        println!("Falling back to env API key");
-                        env::var("OPENAI_API_KEY").unwrap_or_default()
-                    }
-                }
-            };
-
-            if api_key.is_empty() {
-                println!("No OpenAI API key found");
-                return Ok(ApiResponse {
-                    content: None,
-                    error: Some(
-                        "OpenAI API key not configured. Please add your API key in settings."
-                            .to_string(),
-                    ),
-                    citations: None,
-                    images: None,
-                    related_questions: None,
-                });
-            }
-
-            println!("Preparing request with model: {}", request.model);
-            let request_body = serde_json::json!({
-                "model": request.model,
-                "messages": [{
-                    "role": "user",
-                    "content": message
-                }],
-                "temperature": 0.7,
-                "stream": false
-            });
+    };
+
+    let mut response = fallback::run(
+        request,
+        targets,
+        |attempt| send_once(attempt, &in_flight, &app_handle, &state),
+        on_fallback,
+    )
+    .await;
+    response.route = route;
+    Ok(response)
+}
 
-            println!("Sending request to OpenAI API...");
-            let response = client
-                .post("https://api.openai.com/v1/chat/completions")
-                .header("Authorization", format!("Bearer {}", api_key))
-                .header("Content-Type", "application/json")
-                .header("OpenAI-Beta", "assistants=v1")
-                .json(&request_body)
-                .send()
-                .await;
-
-            match response {
-                Ok(resp) => {
-                    let status = resp.status();
-                    println!("Received response with status: {}", status);
-
-                    let response_text = resp.text().await.map_err(|e| {
-                        println!("Failed to read response text: {}", e);
-                        e.to_string()
-                    })?;
-                    println!("Response text: {}", response_text);
-
-                    if status.is_success() {
-                        let json: serde_json::Value = serde_json::from_str(&response_text)
-                            .map_err(|e| {
-                                println!("Failed to parse JSON response: {}", e);
-                                e.to_string()
-                            })?;
-                        println!("Successfully parsed response JSON");
-
-                        Ok(ApiResponse {
-                            content: Some(
-                                json["choices"][0]["message"]["content"]
-                                    .as_str()
-                                    .unwrap_or_default()
-                                    .to_string(),
-                            ),
-                            error: None,
-                            citations: None,
-                            images: None,
-                            related_questions: None,
-                        })
-                    } else {
-                        let error_json: serde_json::Value =
-                            serde_json::from_str(&response_text).unwrap_or_default();
-                        let error_message = error_json["error"]["message"]
-                            .as_str()
-                            .unwrap_or("Unknown error occurred")
-                            .to_string();
-
-                        println!("API error: {}", error_message);
-                        Ok(ApiResponse {
-                            content: None,
-                            error: Some(format!("OpenAI API Error: {}", error_message)),
-                            citations: None,
-                            images: None,
-                            related_questions: None,
-                        })
-                    }
-                }
-                Err(e) => {
-                    println!("Failed to send request: {}", e);
-                    Ok(ApiResponse {
-                        content: None,
-                        error: Some(format!("Failed to send request to OpenAI API: {}", e)),
-                        citations: None,
-                        images: None,
-                        related_questions: None,
-                    })
-                }
+// Sends the request to its own model, without falling back
+async fn send_once(
+    mut request: SendMessageRequest,
+    in_flight: &InFlight<'_>,
+    app_handle: &AppHandle,
+    state: &ApiState,
+) -> ApiResponse {
+    let (provider, api_key, alerts) = match prepare(&mut request, app_handle, state).await {
+        Ok(prepared) => prepared,
+        Err(response) => return response,
+    };
+    let client = state.http.for_provider(provider.id());
+    let request_id = request.request_id();
+
+    let on_retry: RetryListener = {
+        let request_id = request_id.clone();
+        let app_handle = app_handle.clone();
+        Arc::new(move |notice| {
+            let progress = retry_progress(&request_id, notice);
+            if let Err(e) = app_handle.emit("retry-progress", progress) {
+                eprintln!("Failed to emit retry progress: {:?}", e);
             }
+        })
+    };
+
+    let context = ToolContext {
+        request_id: request_id.clone(),
+        app_handle: app_handle.clone(),
+    };
+    let on_tool_event = |event: StreamEvent| {
+        if let Err(e) = app_handle.emit("tool-event", event) {
+            eprintln!("Failed to emit tool event: {:?}", e);
         }
-        "perplexity" => {
-            let stored_keys = crate::config::load_stored_keys(&app_handle)?;
-            let api_key = if let Some(key) = stored_keys["perplexity"].as_str() {
-                key.to_string()
-            } else {
-                let state_key = state.perplexity.lock().unwrap();
-                match state_key.clone() {
-                    Some(key) if !key.is_empty() => key,
-                    _ => env::var("PERPLEXITY_API_KEY").unwrap_or_default(),
-                }
-            };
-
-            if api_key.is_empty() {
-                return Ok(ApiResponse {
-                    content: None,
-                    error: Some(
-                        "Perplexity API key not configured. Please add your API key in settings."
-                            .to_string(),
-                    ),
-                    citations: None,
-                    images: None,
-                    related_questions: None,
-                });
-            }
-
-            let request_body = serde_json::json!({
-                "model": request.model,
-                "messages": [{
-                    "role": "user",
-                    "content": message
-                }]
-            });
-
-            let response = client
-                .post("https://api.perplexity.ai/chat/completions")
-                .header("Authorization", format!("Bearer {}", api_key))
-                .header("Content-Type", "application/json")
-                .json(&request_body)
-                .send()
-                .await;
-
-            match response {
-                Ok(mut resp) => {
-                    let status = resp.status();
-                    let response_text = resp.text().await.map_err(|e| e.to_string())?;
-
-                    if status.is_success() {
-                        let json: serde_json::Value =
-                            serde_json::from_str(&response_text).map_err(|e| e.to_string())?;
-                        Ok(ApiResponse {
-                            content: Some(
-                                json["choices"][0]["message"]["content"]
-                                    .as_str()
-                                    .unwrap_or_default()
-                                    .to_string(),
-                            ),
-                            error: None,
-                            citations: None,
-                            images: None,
-                            related_questions: None,
-                        })
-                    } else {
-                        Ok(ApiResponse {
-                            content: None,
-                            error: Some(format!("API Error: {}", response_text)),
-                            citations: None,
-                            images: None,
-                            related_questions: None,
-                        })
-                    }
-                }
-                Err(e) => Ok(ApiResponse {
-                    content: None,
-                    error: Some(format!("Failed to send request to Perplexity API: {}", e)),
-                    citations: None,
-                    images: None,
-                    related_questions: None,
-                }),
+    };
+
+    // Steps that finished before a failure or a cancel were still paid for
+    let spent = UsageMeter::default();
+    // A cancel that came in while preparing stops it here
+    let result = in_flight
+        .run(http::with_read_timeout(
+            state.http.read_timeout(provider.id()),
+            retry::with_listener(
+                on_retry,
+                dispatch(
+                    provider.as_ref(),
+                    &client,
+                    &api_key,
+                    &request,
+                    state,
+                    &context,
+                    &spent,
+                    None,
+                    &on_tool_event,
+                ),
+            ),
+        ))
+        .await;
+
+    let mut response = match result {
+        Some(Ok(mut response)) => {
+            response.ignored_params = ignored_params(provider.as_ref(), &request);
+            response.budget_alerts = (!alerts.is_empty()).then_some(alerts);
+            response
+        }
+        Some(Err(e)) => {
+            println!("{} request failed: {}", provider.display_name(), e);
+            ApiResponse {
+                usage: spent.total(),
+                ..ApiResponse::failed(e)
             }
         }
-        "google" => {
-            println!("Starting Gemini API request processing");
-
-            let stored_keys = crate::config::load_stored_keys(&app_handle)?;
-            let api_key = if let Some(key) = stored_keys["google"].as_str() {
-                key.to_string()
-            } else {
-                let state_key = state.google.lock().unwrap();
-                match state_key.clone() {
-                    Some(key) if !key.is_empty() => key,
-                    _ => env::var("GOOGLE_API_KEY").unwrap_or_default(),
-                }
-            };
-
-            println!("API Key present: {}", !api_key.is_empty());
-
-            if api_key.is_empty() {
-                return Ok(ApiResponse {
-                    content: None,
-                    error: Some(
-                        "Google API key not configured. Please add your API key in settings."
-                            .to_string(),
-                    ),
-                    citations: None,
-                    images: None,
-                    related_questions: None,
-                });
+        None => {
+            println!("Request {} was cancelled", request_id);
+            ApiResponse {
+                usage: spent.total(),
+                ..ApiResponse::cancelled(provider.display_name())
             }
+        }
+    };
+    record_usage(
+        app_handle,
+        state,
+        provider.as_ref(),
+        &request,
+        &request_id,
+        &mut response,
+    );
+    response
+}
 
-            // Basic request structure following Gemini API docs
-            let request_body = serde_json::json!({
-                "contents": [{
-                    "role": "user",
-                    "parts": [{
-                        "text": message
-                    }]
-                }],
-                "generationConfig": {
-                    "temperature": 0.7,
-                    "topP": 0.8,
-                    "topK": 40,
-                    "maxOutputTokens": 2048
-                },
-                "safetySettings": [
-                    {
-                        "category": "HARM_CATEGORY_HARASSMENT",
-                        "threshold": "BLOCK_MEDIUM_AND_ABOVE"
-                    },
-                    {
-                        "category": "HARM_CATEGORY_HATE_SPEECH",
-                        "threshold": "BLOCK_MEDIUM_AND_ABOVE"
-                    },
-                    {
-                        "category": "HARM_CATEGORY_SEXUALLY_EXPLICIT",
-                        "threshold": "BLOCK_MEDIUM_AND_ABOVE"
-                    },
-                    {
-                        "category": "HARM_CATEGORY_DANGEROUS_CONTENT",
-                        "threshold": "BLOCK_MEDIUM_AND_ABOVE"
-                    }
-                ]
-            });
+/// Streams a response as `StreamEvent`s on `on_event`. The full response is
+/// still returned once the stream has finished.
+#[tauri::command]
+pub async fn stream_message(
+    request: SendMessageRequest,
+    on_event: Channel<StreamEvent>,
+    app_handle: AppHandle,
+    state: State<'_, ApiState>,
+) -> Result<ApiResponse, String> {
+    Ok(stream(request, on_event, &app_handle, &state).await)
+}
 
-            println!("Request model: {}", request.model);
-            println!(
-                "Request body: {}",
-                serde_json::to_string_pretty(&request_body).unwrap()
-            );
+/// Streams one request's events to `on_event`, tagged with its request id,
+/// and returns the final response. Failures end up in the response.
+pub(crate) async fn stream(
+    mut request: SendMessageRequest,
+    on_event: Channel<StreamEvent>,
+    app_handle: &AppHandle,
+    state: &ApiState,
+) -> ApiResponse {
+    let request_id = request.request_id();
+    request.request_id = Some(request_id.clone());
+    // Cancellable from here on, through every fallback attempt
+    let in_flight = state.in_flight.register(&request_id);
+
+    let send_event = |event: StreamEvent| {
+        if let Err(e) = on_event.send(event) {
+            eprintln!("Failed to emit stream event: {:?}", e);
+        }
+    };
+
+    let routed = router::route(request, app_handle, state).and_then(|(request, route)| {
+        let targets = fallback::targets(app_handle, &request)?;
+        Ok((request, route, targets))
+    });
+    let response = match routed {
+        Ok((request, route, targets)) => {
+            let mut response = fallback::run(
+                request,
+                targets,
+                |attempt| stream_once(attempt, on_event.clone(), &in_flight, app_handle, state),
+                |notice| send_event(StreamEvent::Fallback(notice)),
+            )
+            .await;
+            response.route = route;
+            response
+        }
+        Err(e) => ApiResponse::error(e),
+    };
+
+    if let Some(message) = &response.error {
+        send_event(StreamEvent::Error {
+            request_id,
+            message: message.clone(),
+            error: response.provider_error.clone(),
+        });
+    }
+    response
+}
 
-            let url = format!(
-                "https://generativelanguage.googleapis.com/v1beta/models/{}:generateContent?key={}",
-                request.model, api_key
-            );
-            println!("Request URL: {}", url.replace(&api_key, "API_KEY_HIDDEN"));
-
-            let response = client
-                .post(&url)
-                .header("Content-Type", "application/json")
-                .json(&request_body)
-                .send()
-                .await;
-
-            match response {
-                Ok(mut resp) => {
-                    let status = resp.status();
-                    println!("Response status: {}", status);
-
-                    let response_text = resp.text().await.map_err(|e| e.to_string())?;
-                    println!("Raw response: {}", response_text);
-
-                    if status.is_success() {
-                        match serde_json::from_str::<serde_json::Value>(&response_text) {
-                            Ok(json) => {
-                                println!("Successfully parsed JSON response");
-
-                                // Check for error field first
-                                if json.get("error").is_some() {
-                                    let error_msg = json["error"]["message"]
-                                        .as_str()
-                                        .unwrap_or("Unknown error");
-                                    println!("Found error in response: {}", error_msg);
-
-                                    // Handle rate limit error specifically
-                                    if error_msg.contains("rate limit exceeded")
-                                        || error_msg.contains("resource exhausted")
-                                    {
-                                        return Ok(ApiResponse {
-                                            content: None,
-                                            error: Some("Rate limit exceeded for Gemini API. Please try again in about an hour.".to_string()),
-                                            citations: None,
-                                            images: None,
-                                            related_questions: None,
-                                        });
-                                    }
-
-                                    return Ok(ApiResponse {
-                                        content: None,
-                                        error: Some(format!("API Error: {}", error_msg)),
-                                        citations: None,
-                                        images: None,
-                                        related_questions: None,
-                                    });
-                                }
-
-                                // Try to get the response text
-                                if let Some(candidates) = json.get("candidates") {
-                                    println!("Found candidates in response");
-                                    if let Some(first) = candidates.get(0) {
-                                        println!("Processing first candidate");
-                                        if let Some(content) = first.get("content") {
-                                            println!("Found content in candidate");
-                                            if let Some(parts) = content.get("parts") {
-                                                println!("Found parts in content");
-                                                if let Some(first_part) = parts.get(0) {
-                                                    println!("Processing first part");
-                                                    if let Some(text) = first_part.get("text") {
-                                                        if let Some(text_str) = text.as_str() {
-                                                            println!("Successfully extracted response text");
-                                                            return Ok(ApiResponse {
-                                                                content: Some(text_str.to_string()),
-                                                                error: None,
-                                                                citations: None,
-                                                                images: None,
-                                                                related_questions: None,
-                                                            });
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-
-                                println!("Could not find expected response structure");
-                                Ok(ApiResponse {
-                                    content: None,
-                                    error: Some(
-                                        "Could not parse Gemini response structure".to_string(),
-                                    ),
-                                    citations: None,
-                                    images: None,
-                                    related_questions: None,
-                                })
-                            }
-                            Err(e) => {
-                                println!("Failed to parse JSON response: {}", e);
-                                Ok(ApiResponse {
-                                    content: None,
-                                    error: Some(format!("Failed to parse Gemini response: {}", e)),
-                                    citations: None,
-                                    images: None,
-                                    related_questions: None,
-                                })
-                            }
-                        }
-                    } else {
-                        println!("Request failed with status: {}", status);
-
-                        // Try to parse error response
-                        match serde_json::from_str::<serde_json::Value>(&response_text) {
-                            Ok(error_json) => {
-                                if let Some(error) = error_json.get("error") {
-                                    let error_msg =
-                                        error["message"].as_str().unwrap_or("Unknown error");
-
-                                    // Handle rate limit error specifically
-                                    if error_msg.contains("rate limit exceeded")
-                                        || error_msg.contains("resource exhausted")
-                                    {
-                                        return Ok(ApiResponse {
-                                            content: None,
-                                            error: Some("Rate limit exceeded for Gemini API. Please try again in about an hour.".to_string()),
-                                            citations: None,
-                                            images: None,
-                                            related_questions: None,
-                                        });
-                                    }
-
-                                    return Ok(ApiResponse {
-                                        content: None,
-                                        error: Some(format!("Gemini API Error: {}", error_msg)),
-                                        citations: None,
-                                        images: None,
-                                        related_questions: None,
-                                    });
-                                }
-                            }
-                            Err(_) => {}
-                        }
-
-                        Ok(ApiResponse {
-                            content: None,
-                            error: Some(format!(
-                                "Gemini API request failed with status {}: {}",
-                                status, response_text
-                            )),
-                            citations: None,
-                            images: None,
-                            related_questions: None,
-                        })
-                    }
-                }
-                Err(e) => {
-                    println!("Request failed: {}", e);
-                    Ok(ApiResponse {
-                        content: None,
-                        error: Some(format!("Failed to send request to Gemini API: {}", e)),
-                        citations: None,
-                        images: None,
-                        related_questions: None,
-                    })
-                }
-            }
+// Streams the request from its own model, without falling back. Errors are
+// left to the caller to report, since another model may still answer.
+async fn stream_once(
+    mut request: SendMessageRequest,
+    on_event: Channel<StreamEvent>,
+    in_flight: &InFlight<'_>,
+    app_handle: &AppHandle,
+    state: &ApiState,
+) -> ApiResponse {
+    let request_id = request.request_id();
+
+    let send_event = |event: StreamEvent| {
+        if let Err(e) = on_event.send(event) {
+            eprintln!("Failed to emit stream event: {:?}", e);
         }
-        "xai" => {
-            let stored_keys = crate::config::load_stored_keys(&app_handle)?;
-            let api_key = if let Some(key) = stored_keys["xai"].as_str() {
-                key.to_string()
-            } else {
-                let state_key = state.xai.lock().unwrap();
-                match state_key.clone() {
-                    Some(key) if !key.is_empty() => key,
-                    _ => env::var("XAI_API_KEY").unwrap_or_default(),
-                }
-            };
-
-            if api_key.is_empty() {
-                return Ok(ApiResponse {
-                    content: None,
-                    error: Some(
-                        "xAI API key not configured. Please add your API key in settings."
-                            .to_string(),
-                    ),
-                    citations: None,
-                    images: None,
-                    related_questions: None,
-                });
+    };
+
+    let (provider, api_key, alerts) = match prepare(&mut request, app_handle, state).await {
+        Ok(prepared) => prepared,
+        Err(response) => return response,
+    };
+    let client = state.http.for_provider(provider.id());
+
+    let on_chunk = |chunk: StreamChunk| {
+        let request_id = request_id.clone();
+        send_event(match chunk {
+            StreamChunk::Text(text) => StreamEvent::Delta { request_id, text },
+            StreamChunk::Reasoning(text) => StreamEvent::Reasoning { request_id, text },
+            StreamChunk::Usage(usage) => StreamEvent::Usage { request_id, usage },
+        });
+    };
+
+    let on_retry: RetryListener = {
+        let request_id = request_id.clone();
+        let on_event = on_event.clone();
+        Arc::new(move |notice| {
+            let event = StreamEvent::Retry(retry_progress(&request_id, notice));
+            if let Err(e) = on_event.send(event) {
+                eprintln!("Failed to emit stream event: {:?}", e);
             }
-
-            let request_body = serde_json::json!({
-                "messages": [{
-                    "role": "user",
-                    "content": message
-                }],
-                "model": request.model,
-                "max_tokens": 1024
+        })
+    };
+
+    let context = ToolContext {
+        request_id: request_id.clone(),
+        app_handle: app_handle.clone(),
+    };
+
+    // Steps that finished before a failure or a cancel were still paid for
+    let spent = UsageMeter::default();
+    // A cancel that came in while preparing stops it here
+    let result = in_flight
+        .run(http::with_read_timeout(
+            state.http.read_timeout(provider.id()),
+            retry::with_listener(
+                on_retry,
+                dispatch(
+                    provider.as_ref(),
+                    &client,
+                    &api_key,
+                    &request,
+                    state,
+                    &context,
+                    &spent,
+                    Some(&on_chunk),
+                    &send_event,
+                ),
+            ),
+        ))
+        .await;
+
+    let mut response = match result {
+        Some(Ok(mut response)) => {
+            response.ignored_params = ignored_params(provider.as_ref(), &request);
+            response.budget_alerts = (!alerts.is_empty()).then_some(alerts);
+            send_event(StreamEvent::Done {
+                request_id: request_id.clone(),
             });
-
-            let response = client
-                .post("https://api.grok.x.ai/v1/chat/completions")
-                .header("Authorization", format!("Bearer {}", api_key))
-                .header("Content-Type", "application/json")
-                .json(&request_body)
-                .send()
-                .await;
-
-            match response {
-                Ok(resp) => {
-                    let status = resp.status();
-                    let response_text = resp.text().await.map_err(|e| e.to_string())?;
-
-                    if status.is_success() {
-                        let json: serde_json::Value =
-                            serde_json::from_str(&response_text).map_err(|e| e.to_string())?;
-
-                        // Check for rate limit error in success response
-                        if let Some(error) = json["error"].as_object() {
-                            if error["type"].as_str() == Some("rate_limit_exceeded") {
-                                return Ok(ApiResponse {
-                                    content: None,
-                                    error: Some(
-                                        "Rate limit exceeded for Grok. Please try again later."
-                                            .to_string(),
-                                    ),
-                                    citations: None,
-                                    images: None,
-                                    related_questions: None,
-                                });
-                            }
-                        }
-
-                        Ok(ApiResponse {
-                            content: Some(
-                                json["choices"][0]["message"]["content"]
-                                    .as_str()
-                                    .unwrap_or_default()
-                                    .to_string(),
-                            ),
-                            error: None,
-                            citations: None,
-                            images: None,
-                            related_questions: None,
-                        })
-                    } else {
-                        let error_json: Result<serde_json::Value, _> =
-                            serde_json::from_str(&response_text);
-
-                        if let Ok(error_value) = error_json {
-                            if let Some(error) = error_value["error"].as_object() {
-                                if error["type"].as_str() == Some("rate_limit_exceeded") {
-                                    return Ok(ApiResponse {
-                                        content: None,
-                                        error: Some(
-                                            "Rate limit exceeded for Grok. Please try again later."
-                                                .to_string(),
-                                        ),
-                                        citations: None,
-                                        images: None,
-                                        related_questions: None,
-                                    });
-                                }
-                            }
-                        }
-
-                        Ok(ApiResponse {
-                            content: None,
-                            error: Some(format!("API Error: {}", response_text)),
-                            citations: None,
-                            images: None,
-                            related_questions: None,
-                        })
-                    }
-                }
-                Err(e) => Ok(ApiResponse {
-                    content: None,
-                    error: Some(format!("Failed to send request to Grok API: {}", e)),
-                    citations: None,
-                    images: None,
-                    related_questions: None,
-                }),
-            }
+            response
         }
-        "elevenlabs" => {
-            let stored_keys = crate::config::load_stored_keys(&app_handle)?;
-            let api_key = if let Some(key) = stored_keys["elevenlabs"].as_str() {
-                key.to_string()
-            } else {
-                let state_key = state.elevenlabs.lock().unwrap();
-                match state_key.clone() {
-                    Some(key) if !key.is_empty() => key,
-                    _ => env::var("ELEVENLABS_API_KEY").unwrap_or_default(),
-                }
-            };
-
-            if api_key.is_empty() {
-                return Ok(ApiResponse {
-                    content: None,
-                    error: Some(
-                        "ElevenLabs API key not configured. Please add your API key in settings."
-                            .to_string(),
-                    ),
-                    citations: None,
-                    images: None,
-                    related_questions: None,
-                });
+        Some(Err(e)) => {
+            println!("{} stream failed: {}", provider.display_name(), e);
+            ApiResponse {
+                usage: spent.total(),
+                ..ApiResponse::failed(e)
             }
-
-            // Placeholder for ElevenLabs implementation
-            Ok(ApiResponse {
-                content: Some("Text-to-speech conversion not yet implemented".to_string()),
-                error: None,
-                citations: None,
-                images: None,
-                related_questions: None,
-            })
         }
-        "grok" => {
-            let stored_keys = crate::config::load_stored_keys(&app_handle)?;
-            let api_key = if let Some(key) = stored_keys["xai"].as_str() {
-                key.to_string()
-            } else {
-                let state_key = state.xai.lock().unwrap();
-                match state_key.clone() {
-                    Some(key) if !key.is_empty() => key,
-                    _ => env::var("GROK_API_KEY").unwrap_or_default(),
-                }
-            };
-
-            if api_key.is_empty() {
-                return Ok(ApiResponse {
-                    content: None,
-                    error: Some(
-                        "Grok API key not configured. Please add your API key in settings."
-                            .to_string(),
-                    ),
-                    citations: None,
-                    images: None,
-                    related_questions: None,
-                });
+        None => {
+            println!("Request {} was cancelled", request_id);
+            send_event(StreamEvent::Cancelled {
+                request_id: request_id.clone(),
+            });
+            ApiResponse {
+                usage: spent.total(),
+                ..ApiResponse::cancelled(provider.display_name())
             }
+        }
+    };
+    record_usage(
+        app_handle,
+        state,
+        provider.as_ref(),
+        &request,
+        &request_id,
+        &mut response,
+    );
+    response
+}
 
-            let request_body = serde_json::json!({
-                "messages": [{
-                    "role": "user",
-                    "content": message
-                }],
-                "model": request.model,
-                "temperature": 0.7,
-                "max_tokens": 1024,
-                "stream": false
-            });
+/// Aborts a running `send_message` or `stream_message` call. Returns false
+/// when nothing with that id is in flight.
+#[tauri::command]
+pub fn cancel_message(request_id: String, state: State<'_, ApiState>) -> bool {
+    let cancelled = state.in_flight.cancel(&request_id);
+    println!("Cancel request {}: {}", request_id, cancelled);
+    cancelled
+}
 
+#[tauri::command]
+pub async fn verify_api_key(
+    provider: &str,
+    key: &str,
+    state: State<'_, ApiState>,
+) -> Result<serde_json::Value, String> {
+    let provider = match state.providers.get(provider) {
+        Some(provider) => provider,
+        None => return Ok(serde_json::json!({ "error": "Unsupported provider" })),
+    };
+
+    println!("Verifying {} API key...", provider.display_name());
+    let client = state.http.for_provider(provider.id());
+
+    let verified = http::with_read_timeout(
+        state.http.read_timeout(provider.id()),
+        provider.verify_key(&client, key),
+    )
+    .await;
+    match verified {
+        Ok(()) => Ok(serde_json::json!({})),
+        Err(error) => {
             println!(
-                "Request body: {}",
-                serde_json::to_string_pretty(&request_body).unwrap()
+                "{} API key verification failed: {}",
+                provider.display_name(),
+                error
             );
-
-            let response = client
-                .post("https://api.grok.x.ai/v1/chat/completions")
-                .header("Authorization", format!("Bearer {}", api_key))
-                .header("Content-Type", "application/json")
-                .json(&request_body)
-                .send()
-                .await;
-
-            match response {
-                Ok(mut resp) => {
-                    let status = resp.status();
-                    println!("Response status: {}", status);
-                    let response_text = resp.text().await.map_err(|e| e.to_string())?;
-                    println!("Raw response: {}", response_text);
-
-                    if status.is_success() {
-                        let json: serde_json::Value =
-                            serde_json::from_str(&response_text).map_err(|e| e.to_string())?;
-                        Ok(ApiResponse {
-                            content: Some(
-                                json["choices"][0]["message"]["content"]
-                                    .as_str()
-                                    .unwrap_or_default()
-                                    .to_string(),
-                            ),
-                            error: None,
-                            citations: None,
-                            images: None,
-                            related_questions: None,
-                        })
-                    } else {
-                        Ok(ApiResponse {
-                            content: None,
-                            error: Some(format!("API Error: {}", response_text)),
-                            citations: None,
-                            images: None,
-                            related_questions: None,
-                        })
-                    }
-                }
-                Err(e) => Ok(ApiResponse {
-                    content: None,
-                    error: Some(format!("Failed to send request to Grok API: {}", e)),
-                    citations: None,
-                    images: None,
-                    related_questions: None,
-                }),
-            }
+            Ok(serde_json::json!({ "error": error.to_string(), "details": error }))
         }
-        _ => Ok(ApiResponse {
-            content: None,
-            error: Some(format!("Unsupported provider: {}", request.provider)),
-            citations: None,
-            images: None,
-            related_questions: None,
-        }),
     }
 }
 
-#[tauri::command]
-pub async fn verify_api_key(provider: &str, key: &str) -> Result<serde_json::Value, String> {
-    let client = reqwest::Client::new();
-
-    match provider {
-        "gemini" => {
-            let request_body = serde_json::json!({
-                "contents": [{
-                    "role": "user",
-                    "parts": [{
-                        "text": "test"
-                    }]
-                }],
-                "generationConfig": {
-                    "temperature": 0.7,
-                    "topP": 0.8,
-                    "topK": 40,
-                    "maxOutputTokens": 2048
-                },
-                "safetySettings": [
-                    {
-                        "category": "HARM_CATEGORY_HARASSMENT",
-                        "threshold": "BLOCK_MEDIUM_AND_ABOVE"
-                    },
-                    {
-                        "category": "HARM_CATEGORY_HATE_SPEECH",
-                        "threshold": "BLOCK_MEDIUM_AND_ABOVE"
-                    },
-                    {
-                        "category": "HARM_CATEGORY_SEXUALLY_EXPLICIT",
-                        "threshold": "BLOCK_MEDIUM_AND_ABOVE"
-                    },
-                    {
-                        "category": "HARM_CATEGORY_DANGEROUS_CONTENT",
-                        "threshold": "BLOCK_MEDIUM_AND_ABOVE"
-                    }
-                ]
-            });
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::providers::{anthropic, google, openai, perplexity, xai};
+    use serde_json::json;
 
-            let url = format!(
-                "https://generativelanguage.googleapis.com/v1beta/models/gemini-pro:generateContent?key={}",
-                key
-            );
+    fn request(body: serde_json::Value) -> SendMessageRequest {
+        serde_json::from_value(body).unwrap()
+    }
 
-            println!("Verifying Gemini API key...");
-            println!("Request URL: {}", url.replace(key, "API_KEY_HIDDEN"));
-            println!(
-                "Request body: {}",
-                serde_json::to_string_pretty(&request_body).unwrap()
-            );
+    #[test]
+    fn reports_params_the_provider_does_not_pass_on() {
+        let request = request(json!({
+            "model": "sonar",
+            "provider": "perplexity",
+            "message": "Hi",
+            "temperature": 0.2,
+            "stop": ["END"],
+            "seed": 7
+        }));
+        assert_eq!(
+            ignored_params(&perplexity::PerplexityProvider, &request),
+            Some(vec!["stop".to_string(), "seed".to_string()])
+        );
+    }
 
-            let response = client
-                .post(&url)
-                .header("Content-Type", "application/json")
-                .json(&request_body)
-                .send()
-                .await
-                .map_err(|e| e.to_string())?;
-
-            let status = response.status();
-            println!("Response status: {}", status);
-
-            if status.is_success() {
-                println!("Gemini API key is valid");
-                Ok(serde_json::json!({}))
-            } else {
-                let error = response.text().await.map_err(|e| e.to_string())?;
-                println!("Gemini API key verification failed: {}", error);
-                Ok(serde_json::json!({ "error": error }))
-            }
-        }
-        "anthropic" => {
-            let response = client
-                .get("https://api.anthropic.com/v1/models")
-                .header("x-api-key", key)
-                .header("anthropic-version", "2023-06-01")
-                .send()
-                .await
-                .map_err(|e| e.to_string())?;
-
-            if response.status().is_success() {
-                Ok(serde_json::json!({}))
-            } else {
-                let error = response.text().await.map_err(|e| e.to_string())?;
-                Ok(serde_json::json!({ "error": error }))
-            }
-        }
-        "perplexity" => {
-            let response = client
-                .post("https://api.perplexity.ai/chat/completions")
-                .header("Authorization", format!("Bearer {}", key))
-                .json(&serde_json::json!({
-                    "model": "sonar-small-chat",
-                    "messages": [{
-                        "role": "user",
-                        "content": "Test message for API key verification"
-                    }],
-                    "max_tokens": 1
-                }))
-                .send()
-                .await
-                .map_err(|e| e.to_string())?;
-
-            if response.status().is_success() {
-                Ok(serde_json::json!({}))
-            } else {
-                let error = response.text().await.map_err(|e| e.to_string())?;
-                Ok(serde_json::json!({ "error": error }))
-            }
-        }
-        "openai" => {
-            let response = client
-                .post("https://api.openai.com/v1/chat/completions")
-                .header("Authorization", format!("Bearer {}", key))
-                .json(&serde_json::json!({
-                    "model": "gpt-4",
-                    "messages": [{
-                        "role": "user",
-                        "content": "Test message for API key verification"
-                    }],
-                    "max_tokens": 1
-                }))
-                .send()
-                .await
-                .map_err(|e| e.to_string())?;
-
-            if response.status().is_success() {
-                Ok(serde_json::json!({}))
-            } else {
-                let error = response.text().await.map_err(|e| e.to_string())?;
-                Ok(serde_json::json!({ "error": error }))
-            }
-        }
-        "xai" => {
-            let response = client
-                .post("https://api.x.ai/v1/chat/completions")
-                .header("Authorization", format!("Bearer {}", key))
-                .json(&serde_json::json!({
-                    "messages": [{
-                        "role": "user",
-                        "content": "Test message for API key verification"
-                    }],
-                    "max_tokens": 1
-                }))
-                .send()
-                .await;
-
-            match response {
-                Ok(resp) => {
-                    if resp.status().is_success() {
-                        Ok(serde_json::json!({}))
-                    } else {
-                        let error = resp.text().await.map_err(|e| e.to_string())?;
-                        Ok(serde_json::json!({ "error": error }))
-                    }
-                }
-                Err(e) => Ok(serde_json::json!({ "error": e.to_string() })),
-            }
-        }
-        "google" => {
-            let response = client
-                .post(format!(
-                    "https://generativelanguage.googleapis.com/v1beta/models/gemini-1.5-flash:generateContent?key={}",
-                    key
-                ))
-                .header("Content-Type", "application/json")
-                .json(&serde_json::json!({
-                    "contents": [{
-                        "parts":[{
-                            "text": "Test message for API key verification"
-                        }]
-                    }]
-                }))
-                .send()
-                .await;
-
-            match response {
-                Ok(resp) => {
-                    if resp.status().is_success() {
-                        Ok(serde_json::json!({}))
-                    } else {
-                        let error = resp.text().await.map_err(|e| e.to_string())?;
-                        Ok(serde_json::json!({ "error": error }))
-                    }
-                }
-                Err(e) => Ok(serde_json::json!({ "error": e.to_string() })),
-            }
-        }
-        "elevenlabs" => {
-            let response = client
-                .get("https://api.elevenlabs.io/v1/voices")
-                .header("xi-api-key", key)
-                .send()
-                .await
-                .map_err(|e| e.to_string())?;
-
-            if response.status().is_success() {
-                Ok(serde_json::json!({}))
-            } else {
-                let error = response.text().await.map_err(|e| e.to_string())?;
-                Ok(serde_json::json!({ "error": error }))
-            }
-        }
-        "grok" => {
-            let request_body = serde_json::json!({
-                "messages": [{
-                    "role": "user",
-                    "content": "test"
-                }],
-                "model": "grok-1",
-                "temperature": 0.7,
-                "max_tokens": 1024,
-                "stream": false
-            });
+    #[test]
+    fn reports_sampling_params_reasoning_models_drop() {
+        let request = request(json!({
+            "model": "o3-mini",
+            "provider": "openai",
+            "message": "Hi",
+            "temperature": 0.2,
+            "max_tokens": 100
+        }));
+        assert_eq!(
+            ignored_params(&openai::OpenAiProvider, &request),
+            Some(vec!["temperature".to_string()])
+        );
+    }
 
-            println!("Verifying Grok API key...");
-            let response = client
-                .post("https://api.grok.x.ai/v1/chat/completions")
-                .header("Authorization", format!("Bearer {}", key))
-                .header("Content-Type", "application/json")
-                .json(&request_body)
-                .send()
-                .await
-                .map_err(|e| e.to_string())?;
-
-            let status = response.status();
-            println!("Response status: {}", status);
-
-            if status.is_success() {
-                println!("Grok API key is valid");
-                Ok(serde_json::json!({}))
-            } else {
-                let error = response.text().await.map_err(|e| e.to_string())?;
-                println!("Grok API key verification failed: {}", error);
-                Ok(serde_json::json!({ "error": error }))
-            }
-        }
-        _ => Ok(serde_json::json!({ "error": "Unsupported provider" })),
+    #[test]
+    fn reports_sampling_params_dropped_while_thinking() {
+        let mut request = request(json!({
+            "model": "claude-sonnet-4-5",
+            "provider": "anthropic",
+            "message": "Hi",
+            "top_p": 0.9,
+            "thinking_budget": 2048
+        }));
+        assert_eq!(
+            ignored_params(&anthropic::AnthropicProvider, &request),
+            Some(vec!["top_p".to_string()])
+        );
+
+        // Structured output turns thinking off instead
+        request.response_schema = Some(json!({"type": "object"}));
+        assert_eq!(
+            ignored_params(&anthropic::AnthropicProvider, &request),
+            Some(vec!["thinking_budget".to_string()])
+        );
+    }
+
+    #[test]
+    fn reports_thinking_budget_for_claude_models_that_dont_think() {
+        let mut request = request(json!({
+            "model": "claude-3-5-sonnet-latest",
+            "provider": "anthropic",
+            "message": "Hi",
+            "temperature": 0.5,
+            "thinking_budget": 2048
+        }));
+        assert_eq!(
+            ignored_params(&anthropic::AnthropicProvider, &request),
+            Some(vec!["thinking_budget".to_string()])
+        );
+        let body = anthropic::request_body(&request, false).unwrap();
+        assert!(body.get("thinking").is_none());
+        assert_eq!(body["temperature"], 0.5);
+
+        request.model = "claude-3-7-sonnet-latest".to_string();
+        let body = anthropic::request_body(&request, false).unwrap();
+        assert_eq!(body["thinking"]["budget_tokens"], 2048);
+    }
+
+    #[test]
+    fn reports_reasoning_effort_for_other_grok_models() {
+        let mut request = request(json!({
+            "model": "grok-4",
+            "provider": "xai",
+            "message": "Hi",
+            "reasoning_effort": "high"
+        }));
+        assert_eq!(
+            ignored_params(&xai::XaiProvider, &request),
+            Some(vec!["reasoning_effort".to_string()])
+        );
+        request.model = "grok-3-mini".to_string();
+        assert_eq!(ignored_params(&xai::XaiProvider, &request), None);
+    }
+
+    #[test]
+    fn reports_reasoning_effort_for_models_that_dont_reason() {
+        let mut request = request(json!({
+            "model": "gpt-4o",
+            "provider": "openai",
+            "message": "Hi",
+            "reasoning_effort": "low"
+        }));
+        assert_eq!(
+            ignored_params(&openai::OpenAiProvider, &request),
+            Some(vec!["reasoning_effort".to_string()])
+        );
+        request.model = "o4-mini".to_string();
+        assert_eq!(ignored_params(&openai::OpenAiProvider, &request), None);
+    }
+
+    #[test]
+    fn reports_thinking_budget_for_models_that_dont_think() {
+        let mut request = request(json!({
+            "model": "gemini-2.0-flash",
+            "provider": "google",
+            "message": "Hi",
+            "thinking_budget": 1024
+        }));
+        let thinking = Some(vec!["thinking_budget".to_string()]);
+        assert_eq!(ignored_params(&google::GoogleProvider, &request), thinking);
+        assert!(google::request_body(&request).unwrap()["generationConfig"]
+            .get("thinkingConfig")
+            .is_none());
+        assert_eq!(ignored_params(&openai::OpenAiProvider, &request), thinking);
+        assert_eq!(ignored_params(&xai::XaiProvider, &request), thinking);
+
+        request.model = "gemini-2.5-flash".to_string();
+        assert_eq!(ignored_params(&google::GoogleProvider, &request), None);
     }
 }
@@ -0,0 +1,107 @@
+use crate::api::chat;
+use crate::api::ApiState;
+use crate::models::{ApiResponse, ModelTarget, SendMessageRequest, StreamEvent};
+use futures_util::future::join_all;
+use serde::Serialize;
+use std::time::Instant;
+use tauri::ipc::Channel;
+use tauri::{AppHandle, State};
+
+// More than this is a mistake rather than a comparison
+const MAX_TARGETS: usize = 8;
+
+/// How one model did in a comparison. Usage, cost and any error are in
+/// `response`.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompareResult {
+    pub provider: String,
+    pub model: String,
+    // Tags this model's stream events; cancel it on its own with `cancel_message`
+    pub request_id: String,
+    pub latency_ms: u64,
+    pub response: ApiResponse,
+}
+
+/// Request id for the target at `index`, derived from the comparison's id so
+/// the frontend can tell the streams apart before any result is back.
+pub fn target_request_id(request_id: &str, index: usize) -> String {
+    format!("{}-{}", request_id, index)
+}
+
+/// Sends the same request to every target at once. Each model streams on
+/// `on_event` under its own request id, and one failing doesn't stop the
+/// others. Results come back in the order of `targets`. Cancelling the
+/// comparison's request id cancels every model.
+#[tauri::command]
+pub async fn compare_models(
+    request: SendMessageRequest,
+    targets: Vec<ModelTarget>,
+    on_event: Channel<StreamEvent>,
+    app_handle: AppHandle,
+    state: State<'_, ApiState>,
+) -> Result<Vec<CompareResult>, String> {
+    if targets.is_empty() {
+        return Err("No models to compare".to_string());
+    }
+    if targets.len() > MAX_TARGETS {
+        return Err(format!(
+            "Can't compare more than {} models at once",
+            MAX_TARGETS
+        ));
+    }
+
+    let request_id = request.request_id();
+    println!(
+        "Comparing {} models for request {}",
+        targets.len(),
+        request_id
+    );
+
+    let ids = (0..targets.len())
+        .map(|index| target_request_id(&request_id, index))
+        .collect();
+    let _group = state.in_flight.group(&request_id, ids);
+
+    let runs = targets.into_iter().enumerate().map(|(index, target)| {
+        let request = SendMessageRequest {
+            provider: target.provider.clone(),
+            model: target.model.clone(),
+            request_id: Some(target_request_id(&request_id, index)),
+            // Each result should come from the model it's labelled with
+            fallback_chain: None,
+            ..request.clone()
+        };
+        let on_event = on_event.clone();
+        let app_handle = &app_handle;
+        let state = &*state;
+
+        async move {
+            let request_id = request.request_id();
+            let started = Instant::now();
+            let response = chat::stream(request, on_event, app_handle, state).await;
+            let latency_ms = started.elapsed().as_millis() as u64;
+            println!(
+                "{} {} finished in {}ms{}",
+                target.provider,
+                target.model,
+                latency_ms,
+                if response.error.is_some() {
+                    " with an error"
+                } else {
+                    ""
+                }
+            );
+
+            CompareResult {
+                provider: target.provider,
+                model: target.model,
+                request_id,
+                latency_ms,
+                response,
+            }
+        }
+    });
+
+    Ok(join_all(runs).await)
+}
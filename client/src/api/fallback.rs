@@ -0,0 +1,295 @@
+use crate::config;
+use crate::models::{ApiResponse, FallbackNotice, ModelTarget, SendMessageRequest, SkippedModel};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::future::Future;
+use tauri::AppHandle;
+
+pub type FallbackChains = BTreeMap<String, Vec<ModelTarget>>;
+
+/// Named chains from `fallback_chains` in config.json, each a list of
+/// `{"provider", "model"}` entries to try in order.
+pub fn chains(config: &Value) -> FallbackChains {
+    let entries = match config["fallback_chains"].as_object() {
+        Some(entries) => entries,
+        None => return FallbackChains::new(),
+    };
+
+    entries
+        .iter()
+        .filter_map(|(name, chain)| {
+            match serde_json::from_value::<Vec<ModelTarget>>(chain.clone()) {
+                Ok(chain) if !chain.is_empty() => Some((name.clone(), chain)),
+                Ok(_) => {
+                    println!("Skipping empty fallback chain {}", name);
+                    None
+                }
+                Err(e) => {
+                    println!("Skipping invalid fallback chain {}: {}", name, e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// The models to try for `request` in order: its own model, then the rest
+/// of its fallback chain.
+pub fn targets(
+    app_handle: &AppHandle,
+    request: &SendMessageRequest,
+) -> Result<Vec<ModelTarget>, String> {
+    let mut targets = vec![ModelTarget {
+        provider: request.provider.clone(),
+        model: request.model.clone(),
+    }];
+    let name = match &request.fallback_chain {
+        Some(name) => name,
+        None => return Ok(targets),
+    };
+
+    let config = config::load_stored_keys(app_handle)?;
+    let chain = chains(&config)
+        .remove(name)
+        .ok_or_else(|| format!("Unknown fallback chain: {}", name))?;
+    for target in chain {
+        if !targets.contains(&target) {
+            targets.push(target);
+        }
+    }
+    Ok(targets)
+}
+
+/// Sends `request` to each of `targets` in turn until one answers. Only
+/// retryable failures move on to the next model; any other failure, or the
+/// last model's, is returned as is.
+pub async fn run<F, Fut>(
+    mut request: SendMessageRequest,
+    targets: Vec<ModelTarget>,
+    mut send: F,
+    on_fallback: impl Fn(FallbackNotice),
+) -> ApiResponse
+where
+    F: FnMut(SendMessageRequest) -> Fut,
+    Fut: Future<Output = ApiResponse>,
+{
+    // Every attempt runs under the same id so one cancel stops the chain
+    request.request_id = Some(request.request_id());
+    let chained = request.fallback_chain.is_some();
+    let mut skipped = Vec::new();
+
+    for (index, target) in targets.iter().enumerate() {
+        let attempt = SendMessageRequest {
+            provider: target.provider.clone(),
+            model: target.model.clone(),
+            ..request.clone()
+        };
+        let mut response = send(attempt).await;
+
+        let retryable = response
+            .provider_error
+            .as_ref()
+            .filter(|error| error.is_retryable());
+        if let (Some(error), Some(next)) = (retryable, targets.get(index + 1)) {
+            println!(
+                "{} {} failed, falling back to {} {}: {}",
+                target.provider, target.model, next.provider, next.model, error
+            );
+            let model = SkippedModel {
+                provider: target.provider.clone(),
+                model: target.model.clone(),
+                error: error.clone(),
+            };
+            on_fallback(FallbackNotice {
+                request_id: request.request_id(),
+                skipped: model.clone(),
+                next: next.clone(),
+            });
+            skipped.push(model);
+            continue;
+        }
+
+        if chained {
+            if response.error.is_none() && !response.cancelled {
+                response.answered_by = Some(target.clone());
+            }
+            response.skipped_models = (!skipped.is_empty()).then_some(skipped);
+        }
+        return response;
+    }
+
+    ApiResponse::error("No models to send the request to")
+}
+
+#[tauri::command]
+pub fn get_fallback_chains(app_handle: AppHandle) -> Result<FallbackChains, String> {
+    let config = config::load_stored_keys(&app_handle)?;
+    Ok(chains(&config))
+}
+
+#[tauri::command]
+pub fn set_fallback_chains(app_handle: AppHandle, chains: FallbackChains) -> Result<(), String> {
+    let mut config = config::load_stored_keys(&app_handle)?;
+    let config_obj = config.as_object_mut().ok_or("Invalid stored keys format")?;
+
+    if chains.is_empty() {
+        config_obj.remove("fallback_chains");
+    } else {
+        config_obj.insert(
+            "fallback_chains".to_string(),
+            serde_json::to_value(chains).map_err(|e| e.to_string())?,
+        );
+    }
+    config::save_keys(&app_handle, &config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::providers::ProviderError;
+    use serde_json::json;
+    use std::cell::RefCell;
+    use std::time::Duration;
+
+    fn target(provider: &str, model: &str) -> ModelTarget {
+        ModelTarget {
+            provider: provider.to_string(),
+            model: model.to_string(),
+        }
+    }
+
+    fn request(chain: Option<&str>) -> SendMessageRequest {
+        serde_json::from_value(json!({
+            "message": "hi",
+            "provider": "openai",
+            "model": "gpt-4o",
+            "request_id": "r1",
+            "fallback_chain": chain
+        }))
+        .unwrap()
+    }
+
+    fn answer(text: &str) -> ApiResponse {
+        ApiResponse {
+            content: Some(text.to_string()),
+            ..Default::default()
+        }
+    }
+
+    // Answers with `responses` in turn and records which models were asked
+    async fn run_with(
+        request: SendMessageRequest,
+        targets: Vec<ModelTarget>,
+        responses: Vec<ApiResponse>,
+    ) -> (ApiResponse, Vec<SendMessageRequest>, Vec<FallbackNotice>) {
+        let mut responses = responses.into_iter();
+        let mut sent = Vec::new();
+        let notices = RefCell::new(Vec::new());
+        let response = run(
+            request,
+            targets,
+            |attempt| {
+                sent.push(attempt);
+                let response = responses.next().expect("no more responses");
+                async move { response }
+            },
+            |notice| notices.borrow_mut().push(notice),
+        )
+        .await;
+        (response, sent, notices.into_inner())
+    }
+
+    #[tokio::test]
+    async fn retryable_failures_fall_back_to_the_next_model() {
+        let targets = vec![target("openai", "gpt-4o"), target("anthropic", "claude")];
+        let timed_out = ProviderError::timed_out("OpenAI", Duration::from_secs(5));
+        let (response, sent, notices) = run_with(
+            request(Some("main")),
+            targets.clone(),
+            vec![ApiResponse::failed(timed_out.clone()), answer("hello")],
+        )
+        .await;
+
+        assert_eq!(response.content.as_deref(), Some("hello"));
+        assert_eq!(response.answered_by, Some(targets[1].clone()));
+        let skipped = response.skipped_models.unwrap();
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].model, "gpt-4o");
+
+        let asked: Vec<&str> = sent.iter().map(|request| request.model.as_str()).collect();
+        assert_eq!(asked, ["gpt-4o", "claude"]);
+        assert_eq!(sent[1].provider, "anthropic");
+        // One id for the whole chain so a cancel stops it
+        assert!(sent.iter().all(|request| request.request_id() == "r1"));
+
+        assert_eq!(notices.len(), 1);
+        assert_eq!(notices[0].request_id, "r1");
+        assert_eq!(notices[0].next, targets[1]);
+    }
+
+    #[tokio::test]
+    async fn other_failures_are_returned_as_is() {
+        let targets = vec![target("openai", "gpt-4o"), target("anthropic", "claude")];
+        let (response, sent, notices) = run_with(
+            request(Some("main")),
+            targets,
+            vec![ApiResponse::failed(ProviderError::missing_key("OpenAI"))],
+        )
+        .await;
+
+        assert!(response.error.is_some());
+        assert_eq!(response.answered_by, None);
+        assert!(response.skipped_models.is_none());
+        assert_eq!(sent.len(), 1);
+        assert!(notices.is_empty());
+    }
+
+    #[tokio::test]
+    async fn last_models_failure_is_returned_with_the_skipped_ones() {
+        let targets = vec![target("openai", "gpt-4o"), target("anthropic", "claude")];
+        let timed_out =
+            || ApiResponse::failed(ProviderError::timed_out("x", Duration::from_secs(5)));
+        let (response, sent, notices) = run_with(
+            request(Some("main")),
+            targets,
+            vec![timed_out(), timed_out()],
+        )
+        .await;
+
+        assert!(response.provider_error.unwrap().is_retryable());
+        assert_eq!(response.answered_by, None);
+        assert_eq!(response.skipped_models.unwrap().len(), 1);
+        assert_eq!(sent.len(), 2);
+        assert_eq!(notices.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn unchained_requests_are_not_labelled() {
+        let (response, _, _) = run_with(
+            request(None),
+            vec![target("openai", "gpt-4o")],
+            vec![answer("hello")],
+        )
+        .await;
+        assert_eq!(response.answered_by, None);
+        assert!(response.skipped_models.is_none());
+
+        let (response, sent, _) = run_with(request(None), Vec::new(), Vec::new()).await;
+        assert!(response.error.is_some());
+        assert!(sent.is_empty());
+    }
+
+    #[test]
+    fn invalid_and_empty_chains_are_skipped() {
+        let config = json!({
+            "fallback_chains": {
+                "main": [{ "provider": "openai", "model": "gpt-4o" }],
+                "empty": [],
+                "broken": [{ "provider": "openai" }]
+            }
+        });
+        let chains = chains(&config);
+        assert_eq!(chains.keys().collect::<Vec<_>>(), ["main"]);
+        assert!(super::chains(&json!({})).is_empty());
+    }
+}
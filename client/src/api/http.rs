@@ -0,0 +1,368 @@
+use crate::api::ApiState;
+use crate::config;
+use reqwest::{Certificate, Client, NoProxy, Proxy};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::RwLock;
+use std::time::Duration;
+use tauri::{AppHandle, State};
+
+const DEFAULT_CONNECT_TIMEOUT: u64 = 10;
+// Reasoning models can think for minutes before their first token
+const DEFAULT_READ_TIMEOUT: u64 = 300;
+// The local provider shouldn't go through a proxy meant for the internet
+const DEFAULT_NO_PROXY: &str = "localhost,127.0.0.1,::1";
+
+/// Timeouts in seconds. Unset ones fall back to the defaults.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Timeouts {
+    // Until the TCP and TLS handshakes are done, proxy included
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect_secs: Option<u64>,
+    // Longest wait for the response or for the next chunk of a stream
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_secs: Option<u64>,
+}
+
+/// Network settings, stored under `http` in `config.json`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct HttpConfig {
+    // http://, https://, socks5:// or socks5h://, with user:password@ for
+    // proxies that need a login
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    // Comma separated hosts, domains and IP ranges that skip the proxy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub no_proxy: Option<String>,
+    #[serde(default)]
+    pub timeouts: Timeouts,
+    // Keyed by provider id
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub provider_timeouts: HashMap<String, Timeouts>,
+    // Paths of PEM files to trust on top of the system roots
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ca_certificates: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+}
+
+impl HttpConfig {
+    fn connect_timeout(&self, provider: Option<&str>) -> Duration {
+        let secs = provider
+            .and_then(|id| self.provider_timeouts.get(id))
+            .and_then(|timeouts| timeouts.connect_secs)
+            .or(self.timeouts.connect_secs)
+            .unwrap_or(DEFAULT_CONNECT_TIMEOUT);
+        Duration::from_secs(secs)
+    }
+
+    fn read_timeout(&self, provider: Option<&str>) -> Duration {
+        let secs = provider
+            .and_then(|id| self.provider_timeouts.get(id))
+            .and_then(|timeouts| timeouts.read_secs)
+            .or(self.timeouts.read_secs)
+            .unwrap_or(DEFAULT_READ_TIMEOUT);
+        Duration::from_secs(secs)
+    }
+}
+
+pub fn http_config(config: &Value) -> HttpConfig {
+    if config["http"].is_null() {
+        return HttpConfig::default();
+    }
+    serde_json::from_value(config["http"].clone()).unwrap_or_else(|e| {
+        println!("Ignoring invalid http config: {}", e);
+        HttpConfig::default()
+    })
+}
+
+fn load_certificates(paths: &[String]) -> Result<Vec<Certificate>, String> {
+    let mut certificates = Vec::new();
+    for path in paths {
+        let pem = std::fs::read(path)
+            .map_err(|e| format!("Failed to read CA certificate {}: {}", path, e))?;
+        let bundle = Certificate::from_pem_bundle(&pem)
+            .map_err(|e| format!("Invalid CA certificate {}: {}", path, e))?;
+        if bundle.is_empty() {
+            return Err(format!("No certificates found in {}", path));
+        }
+        certificates.extend(bundle);
+    }
+    Ok(certificates)
+}
+
+fn build_client(
+    config: &HttpConfig,
+    certificates: &[Certificate],
+    connect_timeout: Duration,
+) -> Result<Client, String> {
+    let user_agent = config
+        .user_agent
+        .clone()
+        .unwrap_or_else(|| format!("Corner/{}", env!("CARGO_PKG_VERSION")));
+    let mut builder = Client::builder()
+        .user_agent(user_agent)
+        .connect_timeout(connect_timeout);
+
+    if let Some(url) = config.proxy.as_deref().filter(|url| !url.trim().is_empty()) {
+        let no_proxy = config.no_proxy.as_deref().unwrap_or(DEFAULT_NO_PROXY);
+        let proxy = Proxy::all(url.trim())
+            .map_err(|e| format!("Invalid proxy URL: {}", e))?
+            .no_proxy(NoProxy::from_string(no_proxy));
+        builder = builder.proxy(proxy);
+    }
+    for certificate in certificates {
+        builder = builder.add_root_certificate(certificate.clone());
+    }
+
+    builder
+        .build()
+        .map_err(|e| format!("Failed to build HTTP client: {}", e))
+}
+
+struct Clients {
+    config: HttpConfig,
+    default: Client,
+    // Only for providers whose connect timeout differs from the default
+    providers: HashMap<String, Client>,
+}
+
+impl Clients {
+    fn build(config: &HttpConfig) -> Result<Self, String> {
+        let certificates = load_certificates(&config.ca_certificates)?;
+        let default = build_client(config, &certificates, config.connect_timeout(None))?;
+
+        let mut providers = HashMap::new();
+        for id in config.provider_timeouts.keys() {
+            let connect_timeout = config.connect_timeout(Some(id));
+            if connect_timeout != config.connect_timeout(None) {
+                providers.insert(
+                    id.clone(),
+                    build_client(config, &certificates, connect_timeout)?,
+                );
+            }
+        }
+
+        Ok(Self {
+            config: config.clone(),
+            default,
+            providers,
+        })
+    }
+}
+
+/// The HTTP clients every request goes through, so they share connection
+/// pools and the proxy, certificate and timeout settings.
+pub struct HttpClients {
+    clients: RwLock<Clients>,
+}
+
+impl HttpClients {
+    pub fn new() -> Self {
+        let clients = Clients::build(&HttpConfig::default()).expect("Failed to build HTTP client");
+        Self {
+            clients: RwLock::new(clients),
+        }
+    }
+
+    /// Rebuilds the clients from `config`. The current ones are kept when
+    /// it doesn't work, e.g. because a certificate can't be read.
+    pub fn configure(&self, config: &HttpConfig) -> Result<(), String> {
+        let clients = Clients::build(config)?;
+        if let Ok(mut current) = self.clients.write() {
+            *current = clients;
+        }
+        println!(
+            "HTTP client configured{}",
+            if config.proxy.is_some() {
+                " with a proxy"
+            } else {
+                ""
+            }
+        );
+        Ok(())
+    }
+
+    /// Client for anything that isn't a model provider.
+    pub fn client(&self) -> Client {
+        self.clients
+            .read()
+            .map(|clients| clients.default.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn for_provider(&self, id: &str) -> Client {
+        self.clients
+            .read()
+            .map(|clients| {
+                clients
+                    .providers
+                    .get(id)
+                    .unwrap_or(&clients.default)
+                    .clone()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn read_timeout(&self, id: &str) -> Duration {
+        self.clients
+            .read()
+            .map(|clients| clients.config.read_timeout(Some(id)))
+            .unwrap_or(Duration::from_secs(DEFAULT_READ_TIMEOUT))
+    }
+}
+
+impl Default for HttpClients {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+tokio::task_local! {
+    static READ_TIMEOUT: Duration;
+}
+
+/// Runs `future` with every provider read inside it limited to `timeout`.
+/// reqwest only has a timeout for the whole request, which would cut off
+/// long streams, so the reads check this instead.
+pub async fn with_read_timeout<F: Future>(timeout: Duration, future: F) -> F::Output {
+    READ_TIMEOUT.scope(timeout, future).await
+}
+
+/// Waits for one read from the server for at most the current read timeout,
+/// returning the timeout when it ran out.
+pub(crate) async fn read<F: Future>(future: F) -> Result<F::Output, Duration> {
+    match READ_TIMEOUT.try_with(|timeout| *timeout) {
+        Ok(timeout) => tokio::time::timeout(timeout, future)
+            .await
+            .map_err(|_| timeout),
+        Err(_) => Ok(future.await),
+    }
+}
+
+#[tauri::command]
+pub fn get_http_config(app_handle: AppHandle) -> Result<HttpConfig, String> {
+    let config = config::load_stored_keys(&app_handle)?;
+    Ok(http_config(&config))
+}
+
+/// Saves the settings once clients have been built from them, so a bad
+/// proxy URL or certificate path is reported instead of stored.
+#[tauri::command]
+pub fn set_http_config(
+    app_handle: AppHandle,
+    http: HttpConfig,
+    state: State<'_, ApiState>,
+) -> Result<(), String> {
+    state.http.configure(&http)?;
+
+    let mut config = config::load_stored_keys(&app_handle)?;
+    let config_obj = config.as_object_mut().ok_or("Invalid stored keys format")?;
+    config_obj.insert(
+        "http".to_string(),
+        serde_json::to_value(http).map_err(|e| e.to_string())?,
+    );
+    config::save_keys(&app_handle, &config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::future::pending;
+
+    #[test]
+    fn timeouts_fall_back_to_general_then_defaults() {
+        let config = http_config(&json!({
+            "http": {
+                "timeouts": { "read_secs": 60 },
+                "provider_timeouts": {
+                    "local": { "connect_secs": 2 },
+                    "openai": { "read_secs": 600 }
+                }
+            }
+        }));
+
+        assert_eq!(
+            config.connect_timeout(None),
+            Duration::from_secs(DEFAULT_CONNECT_TIMEOUT)
+        );
+        assert_eq!(
+            config.connect_timeout(Some("local")),
+            Duration::from_secs(2)
+        );
+        assert_eq!(config.read_timeout(Some("local")), Duration::from_secs(60));
+        assert_eq!(
+            config.read_timeout(Some("openai")),
+            Duration::from_secs(600)
+        );
+        assert_eq!(config.read_timeout(Some("xai")), Duration::from_secs(60));
+        assert_eq!(
+            HttpConfig::default().read_timeout(None),
+            Duration::from_secs(DEFAULT_READ_TIMEOUT)
+        );
+    }
+
+    #[test]
+    fn invalid_config_is_ignored() {
+        let config = http_config(&json!({ "http": { "timeouts": "soon" } }));
+        assert_eq!(config.timeouts, Timeouts::default());
+        assert!(http_config(&json!({})).proxy.is_none());
+    }
+
+    #[test]
+    fn providers_only_get_their_own_client_for_a_different_connect_timeout() {
+        let config = http_config(&json!({
+            "http": {
+                "timeouts": { "connect_secs": 5 },
+                "provider_timeouts": {
+                    "local": { "connect_secs": 1 },
+                    "openai": { "connect_secs": 5, "read_secs": 30 }
+                }
+            }
+        }));
+        let clients = Clients::build(&config).unwrap();
+        assert_eq!(clients.providers.keys().collect::<Vec<_>>(), ["local"]);
+    }
+
+    #[test]
+    fn bad_settings_keep_the_current_clients() {
+        let clients = HttpClients::new();
+        let configured = HttpConfig {
+            provider_timeouts: HashMap::from([(
+                "openai".to_string(),
+                Timeouts {
+                    read_secs: Some(30),
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        };
+        clients.configure(&configured).unwrap();
+
+        let broken = HttpConfig {
+            ca_certificates: vec!["/nonexistent/ca.pem".to_string()],
+            ..Default::default()
+        };
+        assert!(clients.configure(&broken).is_err());
+        let broken = HttpConfig {
+            proxy: Some("not a url".to_string()),
+            ..Default::default()
+        };
+        assert!(clients.configure(&broken).is_err());
+        assert_eq!(clients.read_timeout("openai"), Duration::from_secs(30));
+    }
+
+    #[tokio::test]
+    async fn reads_are_limited_only_inside_a_timeout_scope() {
+        let timeout = Duration::from_millis(10);
+        assert_eq!(
+            with_read_timeout(timeout, read(pending::<()>())).await,
+            Err(timeout)
+        );
+        assert_eq!(with_read_timeout(timeout, read(async { 1 })).await, Ok(1));
+        assert_eq!(read(async { 1 }).await, Ok(1));
+    }
+}
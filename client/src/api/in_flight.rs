@@ -0,0 +1,290 @@
+use futures_util::future::{AbortHandle, Abortable};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+
+/// Model requests that are still running, keyed by the request id the
+/// frontend sent, so they can be cancelled.
+#[derive(Default)]
+pub struct InFlightRequests {
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    requests: HashMap<String, Entry>,
+    // Numbered so a finished request can't remove a newer one with its id
+    next: u64,
+    // Ids that cancel several requests at once, such as a comparison's
+    groups: HashMap<String, Group>,
+}
+
+struct Entry {
+    number: u64,
+    // Set while a call to the provider is running
+    handle: Option<AbortHandle>,
+    cancelled: bool,
+}
+
+impl Entry {
+    fn cancel(&mut self) {
+        self.cancelled = true;
+        if let Some(handle) = self.handle.take() {
+            handle.abort();
+        }
+    }
+}
+
+struct Group {
+    members: Vec<String>,
+    cancelled: bool,
+}
+
+impl State {
+    fn in_cancelled_group(&self, request_id: &str) -> bool {
+        self.groups
+            .values()
+            .any(|group| group.cancelled && group.members.iter().any(|id| id == request_id))
+    }
+}
+
+/// A registered request id. A cancel that comes in before `run`, such as
+/// while the request is being prepared or between fallback attempts, is
+/// remembered and stops the next `run` from starting. The id is forgotten
+/// when this is dropped.
+pub struct InFlight<'a> {
+    requests: &'a InFlightRequests,
+    request_id: String,
+    number: u64,
+}
+
+impl InFlight<'_> {
+    /// Runs `future` until it finishes or the request is cancelled. Returns
+    /// `None` when it was cancelled, without polling `future` if that
+    /// happened before the call.
+    pub async fn run<F: Future>(&self, future: F) -> Option<F::Output> {
+        let (handle, registration) = AbortHandle::new_pair();
+        if let Ok(mut state) = self.requests.state.lock() {
+            match state.requests.get_mut(&self.request_id) {
+                Some(entry) if entry.number == self.number && !entry.cancelled => {
+                    entry.handle = Some(handle);
+                }
+                _ => return None,
+            }
+        }
+        Abortable::new(future, registration).await.ok()
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.requests.state.lock() {
+            if state
+                .requests
+                .get(&self.request_id)
+                .map_or(false, |entry| entry.number == self.number)
+            {
+                state.requests.remove(&self.request_id);
+            }
+        }
+    }
+}
+
+/// Keeps a group cancellable until dropped.
+pub struct GroupRegistration<'a> {
+    requests: &'a InFlightRequests,
+    group_id: String,
+}
+
+impl Drop for GroupRegistration<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.requests.state.lock() {
+            state.groups.remove(&self.group_id);
+        }
+    }
+}
+
+impl InFlightRequests {
+    /// Makes `request_id` cancellable from now on. Register before any slow
+    /// preparation so an early cancel isn't missed.
+    pub fn register(&self, request_id: &str) -> InFlight<'_> {
+        let mut number = 0;
+        if let Ok(mut state) = self.state.lock() {
+            state.next += 1;
+            number = state.next;
+            let entry = Entry {
+                number,
+                handle: None,
+                cancelled: state.in_cancelled_group(request_id),
+            };
+            // A reused id cancels whatever was still running under it
+            if let Some(mut previous) = state.requests.insert(request_id.to_string(), entry) {
+                previous.cancel();
+            }
+        }
+        InFlight {
+            requests: self,
+            request_id: request_id.to_string(),
+            number,
+        }
+    }
+
+    /// Runs `future` until it finishes or `cancel` is called with the same
+    /// id, or with the id of a group it belongs to. Returns `None` when the
+    /// request was cancelled.
+    pub async fn run<F: Future>(&self, request_id: &str, future: F) -> Option<F::Output> {
+        self.register(request_id).run(future).await
+    }
+
+    /// Lets `cancel` with `group_id` stop every request in `members`, even
+    /// ones that haven't started yet.
+    pub fn group(&self, group_id: &str, members: Vec<String>) -> GroupRegistration<'_> {
+        if let Ok(mut state) = self.state.lock() {
+            state.groups.insert(
+                group_id.to_string(),
+                Group {
+                    members,
+                    cancelled: false,
+                },
+            );
+        }
+        GroupRegistration {
+            requests: self,
+            group_id: group_id.to_string(),
+        }
+    }
+
+    pub fn cancel(&self, request_id: &str) -> bool {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return false,
+        };
+
+        let mut ids = vec![request_id.to_string()];
+        if let Some(group) = state.groups.get_mut(request_id) {
+            group.cancelled = true;
+            ids.extend(group.members.iter().cloned());
+        }
+        let is_group = ids.len() > 1;
+
+        let mut cancelled = false;
+        for id in &ids {
+            if let Some(entry) = state.requests.get_mut(id) {
+                cancelled |= !entry.cancelled;
+                entry.cancel();
+            }
+        }
+        is_group || cancelled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::future::pending;
+    use tokio::task::yield_now;
+
+    #[tokio::test]
+    async fn cancel_aborts_running_request() {
+        let requests = InFlightRequests::default();
+        let cancel = async {
+            yield_now().await;
+            requests.cancel("a")
+        };
+        let (result, cancelled) = tokio::join!(requests.run("a", pending::<()>()), cancel);
+        assert!(result.is_none());
+        assert!(cancelled);
+        assert!(!requests.cancel("a"));
+    }
+
+    #[tokio::test]
+    async fn finished_request_is_forgotten() {
+        let requests = InFlightRequests::default();
+        assert_eq!(requests.run("a", async { 1 }).await, Some(1));
+        assert!(!requests.cancel("a"));
+    }
+
+    #[tokio::test]
+    async fn reused_id_cancels_previous_request() {
+        let requests = InFlightRequests::default();
+        let second = async {
+            yield_now().await;
+            requests.run("a", async { 2 }).await
+        };
+        let (first, second) = tokio::join!(requests.run("a", pending::<i32>()), second);
+        assert_eq!(first, None);
+        assert_eq!(second, Some(2));
+        assert!(!requests.cancel("a"));
+    }
+
+    #[tokio::test]
+    async fn finished_request_leaves_newer_one_with_its_id() {
+        let requests = InFlightRequests::default();
+        let second = async {
+            yield_now().await;
+            requests.run("a", pending::<i32>()).await
+        };
+        let cancel = async {
+            yield_now().await;
+            yield_now().await;
+            // The first request's cleanup ran after the second registered
+            requests.cancel("a")
+        };
+        let (first, second, cancelled) =
+            tokio::join!(requests.run("a", pending::<i32>()), second, cancel);
+        assert_eq!(first, None);
+        assert_eq!(second, None);
+        assert!(cancelled);
+    }
+
+    #[tokio::test]
+    async fn cancelling_a_group_aborts_every_member() {
+        let requests = InFlightRequests::default();
+        let _group = requests.group("cmp", vec!["cmp-0".to_string(), "cmp-1".to_string()]);
+        let cancel = async {
+            yield_now().await;
+            requests.cancel("cmp")
+        };
+        let (first, cancelled) = tokio::join!(requests.run("cmp-0", pending::<()>()), cancel);
+        assert!(first.is_none());
+        assert!(cancelled);
+
+        // A member that only starts now is cancelled too
+        assert_eq!(requests.run("cmp-1", async { 1 }).await, None);
+        assert_eq!(requests.run("other", async { 1 }).await, Some(1));
+    }
+
+    #[tokio::test]
+    async fn dropped_group_is_forgotten() {
+        let requests = InFlightRequests::default();
+        drop(requests.group("cmp", vec!["cmp-0".to_string()]));
+        assert!(!requests.cancel("cmp"));
+        assert_eq!(requests.run("cmp-0", async { 1 }).await, Some(1));
+    }
+
+    #[tokio::test]
+    async fn cancel_before_running_stops_the_request_from_starting() {
+        let requests = InFlightRequests::default();
+        let in_flight = requests.register("a");
+        assert!(requests.cancel("a"));
+        // Already cancelled, so a second cancel finds nothing to stop
+        assert!(!requests.cancel("a"));
+
+        let started = Mutex::new(false);
+        let run = in_flight.run(async { *started.lock().unwrap() = true });
+        assert_eq!(run.await, None);
+        assert!(!*started.lock().unwrap());
+        drop(in_flight);
+        assert!(!requests.cancel("a"));
+    }
+
+    #[tokio::test]
+    async fn one_registration_covers_several_runs() {
+        let requests = InFlightRequests::default();
+        let in_flight = requests.register("a");
+        assert_eq!(in_flight.run(async { 1 }).await, Some(1));
+        // Between attempts the id can still be cancelled
+        assert!(requests.cancel("a"));
+        assert_eq!(in_flight.run(async { 2 }).await, None);
+    }
+}
@@ -0,0 +1,501 @@
+use super::{McpLogs, McpServerConfig};
+use crate::api::providers::sse::read_events;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, Command};
+use tokio::sync::oneshot;
+
+// Newest protocol revision we speak. Servers answer with the one they pick.
+const PROTOCOL_VERSION: &str = "2025-03-26";
+const SESSION_HEADER: &str = "Mcp-Session-Id";
+// JSON-RPC code for methods we don't implement, such as sampling
+const METHOD_NOT_FOUND: i64 = -32601;
+
+type Replies = Mutex<HashMap<u64, oneshot::Sender<Result<Value, String>>>>;
+
+enum Transport {
+    Stdio {
+        stdin: Arc<tokio::sync::Mutex<ChildStdin>>,
+        child: Mutex<Option<Child>>,
+        replies: Arc<Replies>,
+        closed: Arc<AtomicBool>,
+    },
+    Http {
+        client: Client,
+        url: String,
+        headers: HashMap<String, String>,
+        session_id: Mutex<Option<String>>,
+    },
+}
+
+/// A JSON-RPC connection to one MCP server, over a child process's stdio or
+/// the streamable HTTP transport.
+pub struct McpClient {
+    name: String,
+    transport: Transport,
+    next_id: AtomicU64,
+    logs: Arc<McpLogs>,
+}
+
+fn rpc_result(name: &str, message: &Value) -> Result<Value, String> {
+    match message.get("error") {
+        Some(error) => Err(format!(
+            "{} error: {} ({})",
+            name,
+            error["message"].as_str().unwrap_or("unknown error"),
+            error["code"]
+        )),
+        None => Ok(message.get("result").cloned().unwrap_or(Value::Null)),
+    }
+}
+
+fn is_reply(message: &Value) -> bool {
+    message.get("method").is_none() && message.get("id").is_some()
+}
+
+// Notifications only matter for the log; log messages get their own level
+fn log_notification(logs: &McpLogs, message: &Value) {
+    let method = message["method"].as_str().unwrap_or_default();
+    if method == "notifications/message" {
+        let params = &message["params"];
+        let data = match &params["data"] {
+            Value::String(text) => text.clone(),
+            data => data.to_string(),
+        };
+        logs.push(params["level"].as_str().unwrap_or("info"), data);
+    } else {
+        logs.push("debug", format!("Notification {}", method));
+    }
+}
+
+async fn write_message(
+    stdin: &tokio::sync::Mutex<ChildStdin>,
+    message: &Value,
+) -> Result<(), String> {
+    let mut line = message.to_string();
+    line.push('\n');
+
+    let mut stdin = stdin.lock().await;
+    stdin
+        .write_all(line.as_bytes())
+        .await
+        .map_err(|e| e.to_string())?;
+    stdin.flush().await.map_err(|e| e.to_string())
+}
+
+impl McpClient {
+    /// Launches or connects to the server. Call `initialize` before anything
+    /// else. HTTP servers are reached through `http`.
+    pub async fn connect(
+        config: &McpServerConfig,
+        logs: Arc<McpLogs>,
+        http: Client,
+    ) -> Result<Self, String> {
+        let name = format!("MCP server {}", config.id);
+        let transport = match (&config.command, &config.url) {
+            (Some(command), _) => Self::spawn(config, command, &name, &logs)?,
+            (None, Some(url)) => Transport::Http {
+                client: http,
+                url: url.clone(),
+                headers: config.headers.clone(),
+                session_id: Mutex::new(None),
+            },
+            (None, None) => return Err(format!("{} has neither a command nor a url", name)),
+        };
+
+        Ok(Self {
+            name,
+            transport,
+            next_id: AtomicU64::new(1),
+            logs,
+        })
+    }
+
+    fn spawn(
+        config: &McpServerConfig,
+        command: &str,
+        name: &str,
+        logs: &Arc<McpLogs>,
+    ) -> Result<Transport, String> {
+        let mut process = Command::new(command);
+        process
+            .args(&config.args)
+            .envs(&config.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(cwd) = &config.cwd {
+            process.current_dir(cwd);
+        }
+
+        let mut child = process
+            .spawn()
+            .map_err(|e| format!("Failed to start {}: {}", name, e))?;
+        let stdin = Arc::new(tokio::sync::Mutex::new(
+            child.stdin.take().ok_or("Missing stdin")?,
+        ));
+        let stdout = child.stdout.take().ok_or("Missing stdout")?;
+        let stderr = child.stderr.take().ok_or("Missing stderr")?;
+        logs.push(
+            "info",
+            format!("Started {} {}", command, config.args.join(" ")),
+        );
+
+        let replies: Arc<Replies> = Arc::default();
+        let closed = Arc::new(AtomicBool::new(false));
+
+        // Servers write anything they want the user to see on stderr
+        let stderr_logs = logs.clone();
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                stderr_logs.push("stderr", line);
+            }
+        });
+
+        let reader = StdioReader {
+            name: name.to_string(),
+            stdin: stdin.clone(),
+            replies: replies.clone(),
+            closed: closed.clone(),
+            logs: logs.clone(),
+        };
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stdout).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                reader.handle_line(&line).await;
+            }
+            reader.close();
+        });
+
+        Ok(Transport::Stdio {
+            stdin,
+            child: Mutex::new(Some(child)),
+            replies,
+            closed,
+        })
+    }
+
+    /// Runs the initialize handshake and returns the server's answer, which
+    /// holds its name, version and capabilities.
+    pub async fn initialize(&self) -> Result<Value, String> {
+        let result = self
+            .request(
+                "initialize",
+                json!({
+                    "protocolVersion": PROTOCOL_VERSION,
+                    "capabilities": {},
+                    "clientInfo": {
+                        "name": "Corner",
+                        "version": env!("CARGO_PKG_VERSION")
+                    }
+                }),
+                Duration::from_secs(30),
+            )
+            .await?;
+
+        self.notify("notifications/initialized", json!({})).await?;
+        Ok(result)
+    }
+
+    /// Collects every page of a `*/list` method's `field`.
+    pub async fn list_all(&self, method: &str, field: &str) -> Result<Vec<Value>, String> {
+        let mut items = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = match &cursor {
+                Some(cursor) => json!({ "cursor": cursor }),
+                None => json!({}),
+            };
+            let result = self
+                .request(method, params, Duration::from_secs(30))
+                .await?;
+            if let Some(page) = result[field].as_array() {
+                items.extend(page.iter().cloned());
+            }
+
+            match result["nextCursor"].as_str() {
+                Some(next) if !next.is_empty() => cursor = Some(next.to_string()),
+                _ => return Ok(items),
+            }
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        match &self.transport {
+            Transport::Stdio { closed, .. } => closed.load(Ordering::SeqCst),
+            Transport::Http { .. } => false,
+        }
+    }
+
+    pub async fn request(
+        &self,
+        method: &str,
+        params: Value,
+        timeout: Duration,
+    ) -> Result<Value, String> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let message = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params
+        });
+
+        let outcome = match &self.transport {
+            Transport::Stdio { .. } => {
+                tokio::time::timeout(timeout, self.stdio_request(id, &message)).await
+            }
+            Transport::Http { .. } => {
+                tokio::time::timeout(timeout, self.http_request(id, &message)).await
+            }
+        };
+
+        match outcome {
+            Ok(result) => result,
+            Err(_) => {
+                if let Transport::Stdio { replies, .. } = &self.transport {
+                    if let Ok(mut replies) = replies.lock() {
+                        replies.remove(&id);
+                    }
+                }
+                let _ = self
+                    .notify(
+                        "notifications/cancelled",
+                        json!({ "requestId": id, "reason": "Timed out" }),
+                    )
+                    .await;
+                Err(format!("{} timed out on {}", self.name, method))
+            }
+        }
+    }
+
+    pub async fn notify(&self, method: &str, params: Value) -> Result<(), String> {
+        let message = json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params
+        });
+
+        match &self.transport {
+            Transport::Stdio { stdin, .. } => write_message(stdin, &message).await,
+            Transport::Http { .. } => self.post(&message).await.map(|_| ()),
+        }
+    }
+
+    async fn stdio_request(&self, id: u64, message: &Value) -> Result<Value, String> {
+        let (stdin, replies) = match &self.transport {
+            Transport::Stdio { stdin, replies, .. } => (stdin, replies),
+            Transport::Http { .. } => unreachable!(),
+        };
+        if self.is_closed() {
+            return Err(format!("{} is not running", self.name));
+        }
+
+        let (sender, receiver) = oneshot::channel();
+        if let Ok(mut replies) = replies.lock() {
+            replies.insert(id, sender);
+        }
+        write_message(stdin, message)
+            .await
+            .map_err(|e| format!("Failed to write to {}: {}", self.name, e))?;
+
+        receiver
+            .await
+            .unwrap_or_else(|_| Err(format!("{} closed the connection", self.name)))
+    }
+
+    async fn post(&self, message: &Value) -> Result<reqwest::Response, String> {
+        let (client, url, headers, session_id) = match &self.transport {
+            Transport::Http {
+                client,
+                url,
+                headers,
+                session_id,
+            } => (client, url, headers, session_id),
+            Transport::Stdio { .. } => unreachable!(),
+        };
+
+        let mut request = client
+            .post(url)
+            .header("Accept", "application/json, text/event-stream")
+            .json(message);
+        for (name, value) in headers {
+            request = request.header(name.as_str(), value.as_str());
+        }
+        if let Some(session) = session_id.lock().ok().and_then(|id| id.clone()) {
+            request = request.header(SESSION_HEADER, session);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| format!("Failed to reach {}: {}", self.name, e))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("{} returned {}: {}", self.name, status, body));
+        }
+
+        // The server hands out a session id with its answer to initialize
+        if let Some(session) = response
+            .headers()
+            .get(SESSION_HEADER)
+            .and_then(|value| value.to_str().ok())
+        {
+            if let Ok(mut id) = session_id.lock() {
+                *id = Some(session.to_string());
+            }
+        }
+        Ok(response)
+    }
+
+    // Answers come back either as plain JSON or as an event stream that
+    // may carry notifications ahead of the reply
+    async fn http_request(&self, id: u64, message: &Value) -> Result<Value, String> {
+        let response = self.post(message).await?;
+        let is_stream = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map_or(false, |value| value.starts_with("text/event-stream"));
+
+        if !is_stream {
+            let json: Value = response
+                .json()
+                .await
+                .map_err(|e| format!("Invalid response from {}: {}", self.name, e))?;
+            return rpc_result(&self.name, &json);
+        }
+
+        let mut reply = None;
+        read_events(response, &self.name, |event| {
+            let message: Value = match serde_json::from_str(&event.data) {
+                Ok(message) => message,
+                Err(_) => return Ok(true),
+            };
+            if is_reply(&message) && message["id"] == id {
+                reply = Some(message);
+                return Ok(false);
+            }
+            if message.get("method").is_some() {
+                log_notification(&self.logs, &message);
+            }
+            Ok(true)
+        })
+        .await
+        .map_err(|e| e.to_string())?;
+
+        match reply {
+            Some(reply) => rpc_result(&self.name, &reply),
+            None => Err(format!("{} ended the stream without answering", self.name)),
+        }
+    }
+
+    /// Stops the server process, or ends the HTTP session.
+    pub async fn shutdown(&self) {
+        match &self.transport {
+            Transport::Stdio { child, .. } => {
+                let child = child.lock().ok().and_then(|mut child| child.take());
+                if let Some(mut child) = child {
+                    if let Err(e) = child.kill().await {
+                        println!("Failed to stop {}: {}", self.name, e);
+                    }
+                }
+            }
+            Transport::Http {
+                client,
+                url,
+                headers,
+                session_id,
+            } => {
+                let session = session_id.lock().ok().and_then(|mut id| id.take());
+                if let Some(session) = session {
+                    let mut request = client.delete(url).header(SESSION_HEADER, session);
+                    for (name, value) in headers {
+                        request = request.header(name.as_str(), value.as_str());
+                    }
+                    let _ = request.send().await;
+                }
+            }
+        }
+        self.logs.push("info", "Stopped".to_string());
+    }
+}
+
+// Reads what a stdio server writes, routing replies to the requests
+// waiting on them
+struct StdioReader {
+    name: String,
+    stdin: Arc<tokio::sync::Mutex<ChildStdin>>,
+    replies: Arc<Replies>,
+    closed: Arc<AtomicBool>,
+    logs: Arc<McpLogs>,
+}
+
+impl StdioReader {
+    async fn handle_line(&self, line: &str) {
+        let message: Value = match serde_json::from_str(line) {
+            Ok(message) => message,
+            Err(_) => {
+                // Servers aren't meant to print anything else, but some do
+                if !line.trim().is_empty() {
+                    self.logs.push("stdout", line.to_string());
+                }
+                return;
+            }
+        };
+
+        if is_reply(&message) {
+            let waiting = message["id"].as_u64().and_then(|id| {
+                self.replies
+                    .lock()
+                    .ok()
+                    .and_then(|mut replies| replies.remove(&id))
+            });
+            if let Some(sender) = waiting {
+                let _ = sender.send(rpc_result(&self.name, &message));
+            }
+        } else if message.get("id").is_some() {
+            self.answer(&message).await;
+        } else {
+            log_notification(&self.logs, &message);
+        }
+    }
+
+    // Requests from the server. Only ping is supported.
+    async fn answer(&self, request: &Value) {
+        let reply = match request["method"].as_str() {
+            Some("ping") => json!({ "jsonrpc": "2.0", "id": request["id"], "result": {} }),
+            method => json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "error": {
+                    "code": METHOD_NOT_FOUND,
+                    "message": format!("Unsupported method: {}", method.unwrap_or_default())
+                }
+            }),
+        };
+        if let Err(e) = write_message(&self.stdin, &reply).await {
+            self.logs
+                .push("error", format!("Failed to answer server: {}", e));
+        }
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        // Dropping the senders fails every request still waiting
+        if let Ok(mut replies) = self.replies.lock() {
+            replies.clear();
+        }
+        self.logs.push("info", "Server exited".to_string());
+    }
+}
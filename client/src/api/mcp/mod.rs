@@ -0,0 +1,856 @@
+use crate::api::tools::approval::{ApprovalRequest, ToolApprovals};
+use crate::api::tools::{Tool, ToolContext, ToolRegistry};
+use crate::api::ApiState;
+use crate::config;
+use crate::models::{ChatMessage, Role};
+use async_trait::async_trait;
+use client::McpClient;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, State};
+
+pub mod client;
+
+// Log lines kept per server
+const MAX_LOG_ENTRIES: usize = 500;
+// Tool calls can run much longer than the list and handshake requests
+const TOOL_CALL_TIMEOUT: Duration = Duration::from_secs(300);
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+// Providers cap tool names at 64 characters
+const MAX_TOOL_NAME_LEN: usize = 64;
+// Resources named in the `read_resource` tool description
+const MAX_LISTED_RESOURCES: usize = 50;
+// Leaves most of the 64 characters to the tool's own name
+const MAX_SERVER_ID_LEN: usize = 32;
+
+fn enabled_default() -> bool {
+    true
+}
+
+/// One entry of `mcp_servers` in `config.json`. Set `command` to launch a
+/// stdio server, or `url` to connect to one over streamable HTTP.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct McpServerConfig {
+    pub id: String,
+    #[serde(default)]
+    pub command: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    #[serde(default)]
+    pub cwd: Option<String>,
+    #[serde(default)]
+    pub url: Option<String>,
+    // Sent with every HTTP request, e.g. for auth
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    // Disabled servers aren't started with the app
+    #[serde(default = "enabled_default")]
+    pub enabled: bool,
+    // Runs the server's tools without asking the user first
+    #[serde(default)]
+    pub auto_approve: bool,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum McpServerStatus {
+    Stopped,
+    Starting,
+    Running,
+    Failed { error: String },
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct McpLogEntry {
+    // Unix time in seconds
+    pub timestamp: u64,
+    pub level: String,
+    pub message: String,
+}
+
+/// Recent output and lifecycle events of one server.
+#[derive(Default)]
+pub struct McpLogs {
+    entries: Mutex<VecDeque<McpLogEntry>>,
+}
+
+impl McpLogs {
+    pub fn push(&self, level: &str, message: String) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_secs())
+            .unwrap_or_default();
+
+        if let Ok(mut entries) = self.entries.lock() {
+            if entries.len() == MAX_LOG_ENTRIES {
+                entries.pop_front();
+            }
+            entries.push_back(McpLogEntry {
+                timestamp,
+                level: level.to_string(),
+                message,
+            });
+        }
+    }
+
+    fn entries(&self) -> Vec<McpLogEntry> {
+        self.entries
+            .lock()
+            .map(|entries| entries.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct McpResource {
+    pub uri: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub mime_type: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct McpPromptArgument {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct McpPrompt {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub arguments: Vec<McpPromptArgument>,
+}
+
+/// What `list_mcp_servers` reports for each server.
+#[derive(Serialize, Debug)]
+pub struct McpServerInfo {
+    pub config: McpServerConfig,
+    pub status: McpServerStatus,
+    // From the server's answer to initialize
+    pub server_info: Option<Value>,
+    // Names the server's tools are registered under
+    pub tools: Vec<String>,
+    pub resources: Vec<McpResource>,
+    pub prompts: Vec<McpPrompt>,
+}
+
+// What a running server offered when it started
+struct Session {
+    client: Arc<McpClient>,
+    server_info: Value,
+    tools: Vec<String>,
+    resources: Vec<McpResource>,
+    prompts: Vec<McpPrompt>,
+}
+
+struct McpServer {
+    config: McpServerConfig,
+    status: Mutex<McpServerStatus>,
+    logs: Arc<McpLogs>,
+    session: Mutex<Option<Arc<Session>>>,
+}
+
+impl McpServer {
+    fn set_status(&self, status: McpServerStatus) {
+        if let Ok(mut current) = self.status.lock() {
+            *current = status;
+        }
+    }
+
+    fn session(&self) -> Option<Arc<Session>> {
+        self.session.lock().ok()?.clone()
+    }
+
+    fn info(&self) -> McpServerInfo {
+        let session = self.session();
+        let mut status = self
+            .status
+            .lock()
+            .map(|status| status.clone())
+            .unwrap_or(McpServerStatus::Stopped);
+        if session
+            .as_ref()
+            .map_or(false, |session| session.client.is_closed())
+        {
+            status = McpServerStatus::Failed {
+                error: "Server exited".to_string(),
+            };
+        }
+
+        McpServerInfo {
+            config: self.config.clone(),
+            status,
+            server_info: session.as_ref().map(|session| session.server_info.clone()),
+            tools: session
+                .as_ref()
+                .map(|session| session.tools.clone())
+                .unwrap_or_default(),
+            resources: session
+                .as_ref()
+                .map(|session| session.resources.clone())
+                .unwrap_or_default(),
+            prompts: session
+                .as_ref()
+                .map(|session| session.prompts.clone())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Servers declared under `mcp_servers`. Entries that don't parse, or
+/// whose id can't be told apart from an earlier one's, are logged and
+/// skipped.
+pub fn servers(config: &Value) -> Vec<McpServerConfig> {
+    let entries = match config["mcp_servers"].as_array() {
+        Some(entries) => entries,
+        None => return Vec::new(),
+    };
+
+    let mut prefixes = HashSet::new();
+    entries
+        .iter()
+        .filter_map(
+            |entry| match serde_json::from_value::<McpServerConfig>(entry.clone()) {
+                Ok(server) => match check_server_id(&server.id, &mut prefixes) {
+                    Ok(()) => Some(server),
+                    Err(e) => {
+                        println!("Skipping MCP server {}: {}", server.id, e);
+                        None
+                    }
+                },
+                Err(e) => {
+                    println!("Skipping invalid MCP server {}: {}", entry, e);
+                    None
+                }
+            },
+        )
+        .collect()
+}
+
+// Tool names start with the sanitized id and `__`, so two servers must not
+// end up with the same one, or one that runs into the separator
+fn check_server_id(id: &str, prefixes: &mut HashSet<String>) -> Result<(), String> {
+    let prefix = sanitize(id);
+    if prefix.is_empty() || prefix.len() > MAX_SERVER_ID_LEN {
+        return Err(format!(
+            "server ids need 1 to {} characters",
+            MAX_SERVER_ID_LEN
+        ));
+    }
+    if prefix.contains("__") || prefix.ends_with('_') {
+        return Err("server ids can't contain __ or end in _".to_string());
+    }
+    if !prefixes.insert(prefix.clone()) {
+        return Err(format!(
+            "another server's tools are already named {}__...",
+            prefix
+        ));
+    }
+    Ok(())
+}
+
+// Tool names may only hold letters, digits, `_` and `-`
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+// Server tools are registered as `<server>__<tool>` so servers can't clash
+// with each other or the built-in tools. Tools of one server that come out
+// the same after sanitizing and truncating get a number on the end.
+fn tool_name(server_id: &str, name: &str, taken: &mut HashSet<String>) -> String {
+    let mut base = format!("{}__{}", sanitize(server_id), sanitize(name));
+    base.truncate(MAX_TOOL_NAME_LEN);
+
+    let mut name = base.clone();
+    let mut count = 1;
+    while !taken.insert(name.clone()) {
+        count += 1;
+        let suffix = format!("_{}", count);
+        let mut start = base.clone();
+        start.truncate(MAX_TOOL_NAME_LEN - suffix.len());
+        name = start + &suffix;
+    }
+    name
+}
+
+// Tool results and prompt messages share the same content blocks
+fn content_text(content: &Value) -> String {
+    match content["type"].as_str().unwrap_or_default() {
+        "text" => content["text"].as_str().unwrap_or_default().to_string(),
+        "resource" => resource_text(&content["resource"]),
+        kind => format!(
+            "[{} content, {}]",
+            kind,
+            content["mimeType"].as_str().unwrap_or("unknown type")
+        ),
+    }
+}
+
+fn resource_text(resource: &Value) -> String {
+    match resource["text"].as_str() {
+        Some(text) => text.to_string(),
+        None => format!(
+            "[binary resource {}, {}]",
+            resource["uri"].as_str().unwrap_or_default(),
+            resource["mimeType"].as_str().unwrap_or("unknown type")
+        ),
+    }
+}
+
+/// A tool offered by an MCP server. Every call is put to the user unless
+/// the server is set to `auto_approve`.
+struct McpTool {
+    name: String,
+    remote_name: String,
+    server_id: String,
+    description: String,
+    parameters: Value,
+    client: Arc<McpClient>,
+    approvals: Option<Arc<ToolApprovals>>,
+}
+
+#[async_trait]
+impl Tool for McpTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn parameters(&self) -> Value {
+        self.parameters.clone()
+    }
+
+    async fn invoke(&self, arguments: Value, context: &ToolContext) -> Result<String, String> {
+        if let Some(approvals) = &self.approvals {
+            let summary = format!(
+                "Run {} on the {} MCP server",
+                self.remote_name, self.server_id
+            );
+            let approved = approvals
+                .request(
+                    &context.app_handle,
+                    ApprovalRequest {
+                        approval_id: uuid::Uuid::new_v4().to_string(),
+                        request_id: context.request_id.clone(),
+                        tool: self.name.clone(),
+                        summary: summary.clone(),
+                        detail: serde_json::to_string_pretty(&arguments).ok(),
+                    },
+                )
+                .await;
+            if !approved {
+                return Err(format!("The user declined: {}", summary));
+            }
+        }
+
+        let result = self
+            .client
+            .request(
+                "tools/call",
+                json!({ "name": self.remote_name, "arguments": arguments }),
+                TOOL_CALL_TIMEOUT,
+            )
+            .await?;
+
+        let text = result["content"]
+            .as_array()
+            .map(|blocks| {
+                blocks
+                    .iter()
+                    .map(content_text)
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+            .unwrap_or_default();
+        if result["isError"].as_bool().unwrap_or(false) {
+            Err(text)
+        } else {
+            Ok(text)
+        }
+    }
+}
+
+/// Lets the model read a server's resources by URI.
+struct ReadResourceTool {
+    name: String,
+    description: String,
+    client: Arc<McpClient>,
+}
+
+#[async_trait]
+impl Tool for ReadResourceTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "uri": { "type": "string", "description": "URI of the resource to read" }
+            },
+            "required": ["uri"]
+        })
+    }
+
+    async fn invoke(&self, arguments: Value, _context: &ToolContext) -> Result<String, String> {
+        let uri = arguments["uri"].as_str().ok_or("Missing uri")?;
+        read_resource(&self.client, uri).await
+    }
+}
+
+async fn read_resource(client: &McpClient, uri: &str) -> Result<String, String> {
+    let result = client
+        .request("resources/read", json!({ "uri": uri }), READ_TIMEOUT)
+        .await?;
+    Ok(result["contents"]
+        .as_array()
+        .map(|contents| {
+            contents
+                .iter()
+                .map(resource_text)
+                .collect::<Vec<_>>()
+                .join("\n\n")
+        })
+        .unwrap_or_default())
+}
+
+/// Running MCP servers, keyed by id. Stopped and failed servers stay listed
+/// so their logs can still be read. Tool calls ask through `approvals`.
+pub struct McpManager {
+    servers: RwLock<HashMap<String, Arc<McpServer>>>,
+    approvals: Arc<ToolApprovals>,
+}
+
+impl McpManager {
+    pub fn new(approvals: Arc<ToolApprovals>) -> Self {
+        Self {
+            servers: RwLock::new(HashMap::new()),
+            approvals,
+        }
+    }
+
+    fn server(&self, id: &str) -> Option<Arc<McpServer>> {
+        self.servers.read().ok()?.get(id).cloned()
+    }
+
+    fn session(&self, id: &str) -> Result<Arc<Session>, String> {
+        self.server(id)
+            .and_then(|server| server.session())
+            .ok_or_else(|| format!("MCP server {} is not running", id))
+    }
+
+    /// Starts a server, restarting it if it is already running, and
+    /// registers its tools.
+    pub async fn start(
+        &self,
+        config: McpServerConfig,
+        http: Client,
+        tools: &ToolRegistry,
+    ) -> Result<McpServerInfo, String> {
+        self.stop(&config.id, tools).await;
+
+        let server = Arc::new(McpServer {
+            config: config.clone(),
+            status: Mutex::new(McpServerStatus::Starting),
+            logs: Arc::new(McpLogs::default()),
+            session: Mutex::new(None),
+        });
+        if let Ok(mut servers) = self.servers.write() {
+            servers.insert(config.id.clone(), server.clone());
+        }
+
+        println!("Starting MCP server {}", config.id);
+        let approvals = (!config.auto_approve).then(|| self.approvals.clone());
+        match connect(&config, server.logs.clone(), http, tools, approvals).await {
+            Ok(session) => {
+                println!(
+                    "MCP server {} is running with {} tools",
+                    config.id,
+                    session.tools.len()
+                );
+                if let Ok(mut current) = server.session.lock() {
+                    *current = Some(Arc::new(session));
+                }
+                server.set_status(McpServerStatus::Running);
+                Ok(server.info())
+            }
+            Err(e) => {
+                println!("MCP server {} failed to start: {}", config.id, e);
+                server.logs.push("error", e.clone());
+                server.set_status(McpServerStatus::Failed { error: e.clone() });
+                Err(e)
+            }
+        }
+    }
+
+    /// Stops a server and removes its tools. Does nothing for servers that
+    /// aren't running.
+    pub async fn stop(&self, id: &str, tools: &ToolRegistry) {
+        let server = match self.server(id) {
+            Some(server) => server,
+            None => return,
+        };
+
+        let session = server
+            .session
+            .lock()
+            .ok()
+            .and_then(|mut session| session.take());
+        server.set_status(McpServerStatus::Stopped);
+        if let Some(session) = session {
+            println!("Stopping MCP server {}", id);
+            for name in &session.tools {
+                tools.unregister(name);
+            }
+            session.client.shutdown().await;
+        }
+    }
+
+    /// Starts every enabled server in `config`.
+    pub async fn start_all(&self, config: &Value, http: Client, tools: &ToolRegistry) {
+        for server in servers(config).into_iter().filter(|server| server.enabled) {
+            // Failures are already logged and shown in the server list
+            let _ = self.start(server, http.clone(), tools).await;
+        }
+    }
+
+    pub async fn stop_all(&self, tools: &ToolRegistry) {
+        let ids: Vec<String> = self
+            .servers
+            .read()
+            .map(|servers| servers.keys().cloned().collect())
+            .unwrap_or_default();
+        for id in ids {
+            self.stop(&id, tools).await;
+        }
+    }
+}
+
+async fn connect(
+    config: &McpServerConfig,
+    logs: Arc<McpLogs>,
+    http: Client,
+    tools: &ToolRegistry,
+    approvals: Option<Arc<ToolApprovals>>,
+) -> Result<Session, String> {
+    let client = Arc::new(McpClient::connect(config, logs, http).await?);
+    match discover(config, client.clone(), approvals).await {
+        Ok((session, offered)) => {
+            // Only registered once everything was listed, so a failed start
+            // leaves nothing behind
+            for tool in offered {
+                tools.register(tool);
+            }
+            Ok(session)
+        }
+        Err(e) => {
+            client.shutdown().await;
+            Err(e)
+        }
+    }
+}
+
+// Runs the handshake and lists what the server offers
+async fn discover(
+    config: &McpServerConfig,
+    client: Arc<McpClient>,
+    approvals: Option<Arc<ToolApprovals>>,
+) -> Result<(Session, Vec<Arc<dyn Tool>>), String> {
+    let initialized = client.initialize().await?;
+    let capabilities = &initialized["capabilities"];
+    let mut offered: Vec<Arc<dyn Tool>> = Vec::new();
+    let mut names = HashSet::new();
+
+    if capabilities.get("tools").is_some() {
+        for tool in client.list_all("tools/list", "tools").await? {
+            let remote_name = tool["name"].as_str().unwrap_or_default().to_string();
+            offered.push(Arc::new(McpTool {
+                name: tool_name(&config.id, &remote_name, &mut names),
+                description: tool["description"].as_str().unwrap_or_default().to_string(),
+                parameters: tool
+                    .get("inputSchema")
+                    .cloned()
+                    .unwrap_or_else(|| json!({ "type": "object" })),
+                remote_name,
+                server_id: config.id.clone(),
+                client: client.clone(),
+                approvals: approvals.clone(),
+            }));
+        }
+    }
+
+    let mut resources = Vec::new();
+    if capabilities.get("resources").is_some() {
+        resources = client
+            .list_all("resources/list", "resources")
+            .await?
+            .into_iter()
+            .filter_map(|resource| serde_json::from_value::<McpResource>(resource).ok())
+            .collect();
+    }
+    if !resources.is_empty() {
+        let listed: Vec<String> = resources
+            .iter()
+            .take(MAX_LISTED_RESOURCES)
+            .map(|resource| format!("- {}: {}", resource.uri, resource.name))
+            .collect();
+        offered.push(Arc::new(ReadResourceTool {
+            name: tool_name(&config.id, "read_resource", &mut names),
+            description: format!(
+                "Reads a resource from the {} MCP server. Available resources:\n{}",
+                config.id,
+                listed.join("\n")
+            ),
+            client: client.clone(),
+        }));
+    }
+
+    let mut prompts = Vec::new();
+    if capabilities.get("prompts").is_some() {
+        prompts = client
+            .list_all("prompts/list", "prompts")
+            .await?
+            .into_iter()
+            .filter_map(|prompt| serde_json::from_value::<McpPrompt>(prompt).ok())
+            .collect();
+    }
+
+    let session = Session {
+        server_info: initialized["serverInfo"].clone(),
+        client,
+        tools: offered.iter().map(|tool| tool.name().to_string()).collect(),
+        resources,
+        prompts,
+    };
+    Ok((session, offered))
+}
+
+/// Configured servers with their status, including ones that were never
+/// started.
+#[tauri::command]
+pub fn list_mcp_servers(
+    app_handle: AppHandle,
+    state: State<'_, ApiState>,
+) -> Result<Vec<McpServerInfo>, String> {
+    let configured = servers(&config::load_stored_keys(&app_handle)?);
+    Ok(configured
+        .into_iter()
+        .map(|config| match state.mcp.server(&config.id) {
+            Some(server) => server.info(),
+            None => McpServerInfo {
+                config,
+                status: McpServerStatus::Stopped,
+                server_info: None,
+                tools: Vec::new(),
+                resources: Vec::new(),
+                prompts: Vec::new(),
+            },
+        })
+        .collect())
+}
+
+/// Starts or restarts a configured server.
+#[tauri::command]
+pub async fn start_mcp_server(
+    id: String,
+    app_handle: AppHandle,
+    state: State<'_, ApiState>,
+) -> Result<McpServerInfo, String> {
+    let config = servers(&config::load_stored_keys(&app_handle)?)
+        .into_iter()
+        .find(|server| server.id == id)
+        .ok_or_else(|| format!("Unknown MCP server: {}", id))?;
+    state
+        .mcp
+        .start(config, state.http.client(), &state.tools)
+        .await
+}
+
+#[tauri::command]
+pub async fn stop_mcp_server(id: String, state: State<'_, ApiState>) -> Result<(), String> {
+    state.mcp.stop(&id, &state.tools).await;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn mcp_server_logs(id: String, state: State<'_, ApiState>) -> Result<Vec<McpLogEntry>, String> {
+    state
+        .mcp
+        .server(&id)
+        .map(|server| server.logs.entries())
+        .ok_or_else(|| format!("MCP server {} has not been started", id))
+}
+
+#[tauri::command]
+pub async fn read_mcp_resource(
+    server_id: String,
+    uri: String,
+    state: State<'_, ApiState>,
+) -> Result<String, String> {
+    let session = state.mcp.session(&server_id)?;
+    read_resource(&session.client, &uri).await
+}
+
+/// Fills in a server's prompt template. The messages can be sent as the
+/// `messages` of a request.
+#[tauri::command]
+pub async fn get_mcp_prompt(
+    server_id: String,
+    name: String,
+    arguments: HashMap<String, String>,
+    state: State<'_, ApiState>,
+) -> Result<Vec<ChatMessage>, String> {
+    let session = state.mcp.session(&server_id)?;
+    let result = session
+        .client
+        .request(
+            "prompts/get",
+            json!({ "name": name, "arguments": arguments }),
+            READ_TIMEOUT,
+        )
+        .await?;
+
+    Ok(result["messages"]
+        .as_array()
+        .map(|messages| {
+            messages
+                .iter()
+                .map(|message| {
+                    let role = match message["role"].as_str() {
+                        Some("assistant") => Role::Assistant,
+                        _ => Role::User,
+                    };
+                    ChatMessage::new(role, content_text(&message["content"]))
+                })
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+/// Saves the server list and restarts the servers to match it.
+#[tauri::command]
+pub async fn set_mcp_servers(
+    servers: Vec<McpServerConfig>,
+    app_handle: AppHandle,
+    state: State<'_, ApiState>,
+) -> Result<(), String> {
+    let mut prefixes = HashSet::new();
+    for server in &servers {
+        check_server_id(&server.id, &mut prefixes)
+            .map_err(|e| format!("Invalid MCP server id {}: {}", server.id, e))?;
+    }
+
+    let mut config = config::load_stored_keys(&app_handle)?;
+    let config_obj = config.as_object_mut().ok_or("Invalid stored keys format")?;
+
+    if servers.is_empty() {
+        config_obj.remove("mcp_servers");
+    } else {
+        config_obj.insert(
+            "mcp_servers".to_string(),
+            serde_json::to_value(&servers).map_err(|e| e.to_string())?,
+        );
+    }
+    config::save_keys(&app_handle, &config)?;
+
+    state.mcp.stop_all(&state.tools).await;
+    state
+        .mcp
+        .start_all(&config, state.http.client(), &state.tools)
+        .await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn servers_ask_before_running_tools_by_default() {
+        let config: McpServerConfig =
+            serde_json::from_value(json!({ "id": "files", "command": "mcp-files" })).unwrap();
+        assert!(config.enabled);
+        assert!(!config.auto_approve);
+
+        let config: McpServerConfig = serde_json::from_value(
+            json!({ "id": "files", "url": "http://x", "auto_approve": true }),
+        )
+        .unwrap();
+        assert!(config.auto_approve);
+    }
+
+    #[test]
+    fn servers_whose_tool_names_would_clash_are_skipped() {
+        let config = json!({ "mcp_servers": [
+            { "id": "a.b", "command": "one" },
+            { "id": "a_b", "command": "two" },
+            { "id": "a", "command": "three" },
+            { "id": "a__c", "command": "four" },
+            { "id": "x".repeat(MAX_SERVER_ID_LEN + 1), "command": "five" },
+            { "id": "", "command": "six" }
+        ]});
+        let ids: Vec<String> = servers(&config)
+            .into_iter()
+            .map(|server| server.id)
+            .collect();
+        assert_eq!(ids, ["a.b", "a"]);
+    }
+
+    #[test]
+    fn clashing_tool_names_get_a_number() {
+        let mut taken = HashSet::new();
+        assert_eq!(
+            tool_name("files", "read_resource", &mut taken),
+            "files__read_resource"
+        );
+        assert_eq!(
+            tool_name("files", "read.resource", &mut taken),
+            "files__read_resource_2"
+        );
+        assert_eq!(
+            tool_name("files", "read_resource", &mut taken),
+            "files__read_resource_3"
+        );
+
+        let long = "t".repeat(80);
+        let first = tool_name("files", &long, &mut taken);
+        let second = tool_name("files", &format!("{}-other", long), &mut taken);
+        assert_eq!(first.len(), MAX_TOOL_NAME_LEN);
+        assert_eq!(second.len(), MAX_TOOL_NAME_LEN);
+        assert!(second.ends_with("_2"));
+        assert_ne!(first, second);
+    }
+}
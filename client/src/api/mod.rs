@@ -1,10 +1,17 @@
+use crate::models::ModelInfo;
 use anyhow::Result;
-use reqwest::Client;
+use http::HttpClients;
+use in_flight::InFlightRequests;
+use mcp::McpManager;
+use model_cache::ModelCache;
+use providers::openai::OPENAI_API_BASE;
+use providers::ProviderRegistry;
 use serde_json::{json, Value};
 use std::sync::{Arc, Mutex};
-use tauri::State;
-
-const OPENAI_API_BASE: &str = "https://api.openai.com/v1";
+use tauri::{AppHandle, State};
+use tools::approval::ToolApprovals;
+use tools::ToolRegistry;
+use usage::UsageLedger;
 
 #[derive(Default)]
 pub struct ApiKeys {
@@ -48,14 +55,29 @@ impl ApiKeys {
 
 pub struct ApiState {
     pub keys: ApiKeys,
-    client: Arc<Client>,
+    pub providers: ProviderRegistry,
+    pub in_flight: InFlightRequests,
+    pub models: ModelCache,
+    pub usage: UsageLedger,
+    pub tools: ToolRegistry,
+    pub approvals: Arc<ToolApprovals>,
+    pub mcp: McpManager,
+    pub http: HttpClients,
 }
 
 impl ApiState {
     pub fn new() -> Self {
+        let approvals = Arc::new(ToolApprovals::default());
         Self {
             keys: ApiKeys::default(),
-            client: Arc::new(Client::new()),
+            providers: ProviderRegistry::with_builtin(),
+            in_flight: InFlightRequests::default(),
+            models: ModelCache::default(),
+            usage: UsageLedger::default(),
+            tools: ToolRegistry::with_builtin(approvals.clone()),
+            mcp: McpManager::new(approvals.clone()),
+            approvals,
+            http: HttpClients::new(),
         }
     }
 
@@ -66,7 +88,8 @@ impl ApiState {
         api_key: &str,
     ) -> Result<Value, String> {
         let response = self
-            .client
+            .http
+            .client()
             .post(&format!("{}{}", OPENAI_API_BASE, endpoint))
             .header("Authorization", format!("Bearer {}", api_key))
             .header("Content-Type", "application/json")
@@ -161,29 +184,67 @@ pub async fn get_embeddings(text: String, state: State<'_, ApiState>) -> Result<
         .collect::<Result<Vec<f32>, String>>()
 }
 
+/// Lists the models a provider offers. Results are cached for a few minutes
+/// unless `refresh` is set.
 #[tauri::command]
-pub async fn get_models(state: State<'_, ApiState>) -> Result<Vec<String>, String> {
-    let api_key = state
-        .keys
-        .get_key("openai")
-        .ok_or_else(|| "OpenAI API key not found".to_string())?;
+pub async fn list_models(
+    provider: String,
+    refresh: Option<bool>,
+    app_handle: AppHandle,
+    state: State<'_, ApiState>,
+) -> Result<Vec<ModelInfo>, String> {
+    let provider = state
+        .providers
+        .get(&provider)
+        .ok_or_else(|| format!("Unsupported provider: {}", provider))?;
+
+    if !refresh.unwrap_or(false) {
+        if let Some(models) = state.models.get(provider.id()) {
+            return Ok(models);
+        }
+    }
 
-    let response = state.make_request("/models", json!({}), &api_key).await?;
+    let api_key = chat::resolve_api_key(&app_handle, &state, provider.as_ref())?;
+    println!("Fetching models for {}", provider.id());
+    let models = http::with_read_timeout(
+        state.http.read_timeout(provider.id()),
+        provider.list_models(&state.http.for_provider(provider.id()), &api_key),
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    state.models.insert(provider.id(), models.clone());
+    Ok(models)
+}
 
-    let models = response["data"]
-        .as_array()
-        .ok_or_else(|| "Invalid response format".to_string())?
-        .iter()
-        .filter_map(|model| model["id"].as_str().map(String::from))
-        .filter(|id| id.contains("gpt") || id.contains("text-embedding"))
-        .collect::<Vec<String>>();
+/// Model ids for `provider`, defaulting to OpenAI. Kept for older frontend
+/// builds, `list_models` returns more detail.
+#[tauri::command]
+pub async fn get_models(
+    provider: Option<String>,
+    app_handle: AppHandle,
+    state: State<'_, ApiState>,
+) -> Result<Vec<String>, String> {
+    let provider = provider.unwrap_or_else(|| "openai".to_string());
+    let models = list_models(provider, None, app_handle, state).await?;
 
     if models.is_empty() {
         Err("No compatible models found".to_string())
     } else {
-        Ok(models)
+        Ok(models.into_iter().map(|model| model.id).collect())
     }
 }
 
+pub mod budget;
 pub mod chat;
+pub mod compare;
+pub mod fallback;
+pub mod http;
+pub mod in_flight;
+pub mod mcp;
+pub mod model_cache;
+pub mod providers;
+pub mod router;
 pub mod speech;
+pub mod structured;
+pub mod tools;
+pub mod usage;
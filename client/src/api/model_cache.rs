@@ -0,0 +1,57 @@
+use crate::models::ModelInfo;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Long enough to avoid a request per model picker open, short enough that
+// new releases show up the same session
+const MODEL_CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Results of `list_models`, keyed by provider id.
+#[derive(Default)]
+pub struct ModelCache {
+    entries: Mutex<HashMap<String, (Instant, Vec<ModelInfo>)>>,
+}
+
+impl ModelCache {
+    pub fn get(&self, provider: &str) -> Option<Vec<ModelInfo>> {
+        let entries = self.entries.lock().ok()?;
+        let (fetched_at, models) = entries.get(provider)?;
+        if fetched_at.elapsed() < MODEL_CACHE_TTL {
+            Some(models.clone())
+        } else {
+            None
+        }
+    }
+
+    pub fn insert(&self, provider: &str, models: Vec<ModelInfo>) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.insert(provider.to_string(), (Instant::now(), models));
+        }
+    }
+
+    pub fn invalidate(&self, provider: &str) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.remove(provider);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cached_models_are_kept_per_provider() {
+        let cache = ModelCache::default();
+        assert!(cache.get("openai").is_none());
+
+        cache.insert("openai", vec![ModelInfo::new("gpt-4o")]);
+        let models = cache.get("openai").unwrap();
+        assert_eq!(models[0].id, "gpt-4o");
+        assert!(cache.get("xai").is_none());
+
+        cache.invalidate("openai");
+        assert!(cache.get("openai").is_none());
+    }
+}
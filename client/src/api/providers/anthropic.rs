@@ -0,0 +1,450 @@
+use super::openai::parse_event;
+use super::sse::read_events;
+use super::{
+    insert_some, invalid_format, merge_consecutive, merge_parts, send_check, send_json,
+    send_stream, split_system, turns_with_attachment, ChunkSink, PartialToolCall, Provider,
+    ProviderError, StreamChunk,
+};
+use crate::api::structured::{tool_input_schema, RESPONSE_TOOL};
+use crate::files::Attachment;
+use crate::models::{ApiResponse, ChatMessage, ModelInfo, SendMessageRequest, ToolCall, Usage};
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder, Response};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+const ANTHROPIC_API_BASE: &str = "https://api.anthropic.com/v1";
+pub(crate) const ANTHROPIC_VERSION: &str = "2023-06-01";
+const DEFAULT_SYSTEM_PROMPT: &str = "You are a helpful AI assistant.";
+// Every current Claude model shares this context window
+const CONTEXT_WINDOW: u64 = 200_000;
+// Anthropic requires max_tokens on every request
+const DEFAULT_MAX_TOKENS: u32 = 4096;
+// Smallest thinking budget the API accepts
+const MIN_THINKING_BUDGET: u32 = 1024;
+
+pub struct AnthropicProvider;
+
+fn with_auth(request: RequestBuilder, api_key: &str) -> RequestBuilder {
+    request
+        .header("x-api-key", api_key)
+        .header("anthropic-version", ANTHROPIC_VERSION)
+}
+
+fn messages_request(client: &Client, api_key: &str, body: &Value) -> RequestBuilder {
+    with_auth(
+        client.post(format!("{}/messages", ANTHROPIC_API_BASE)),
+        api_key,
+    )
+    .header("content-type", "application/json")
+    .json(body)
+}
+
+// Images and PDFs are sent as base64 content blocks ahead of the prompt
+fn file_block(file: &Attachment) -> Option<Value> {
+    let block_type = if file.is_image() {
+        "image"
+    } else if file.is_pdf() {
+        "document"
+    } else {
+        return None;
+    };
+
+    match file {
+        Attachment::Binary {
+            mime_type, data, ..
+        } => Some(json!({
+            "type": block_type,
+            "source": {
+                "type": "base64",
+                "media_type": mime_type,
+                "data": data
+            }
+        })),
+        Attachment::Text { .. } => None,
+    }
+}
+
+// Tool results go back as user turns, and calls as `tool_use` blocks after
+// whatever the model said first
+fn message_json(turn: &ChatMessage) -> Value {
+    if let Some(result) = &turn.tool_result {
+        return json!({
+            "role": "user",
+            "content": [{
+                "type": "tool_result",
+                "tool_use_id": result.call_id,
+                "content": result.content,
+                "is_error": result.is_error
+            }]
+        });
+    }
+    if turn.tool_calls.is_empty() {
+        return json!({ "role": turn.role, "content": turn.content });
+    }
+
+    // Thinking has to come back unchanged ahead of the calls it led to
+    let mut blocks = turn.thinking_blocks.clone();
+    if !turn.content.is_empty() {
+        blocks.push(json!({ "type": "text", "text": turn.content }));
+    }
+    for call in &turn.tool_calls {
+        blocks.push(json!({
+            "type": "tool_use",
+            "id": call.id,
+            "name": call.name,
+            "input": call.arguments
+        }));
+    }
+    json!({ "role": turn.role, "content": blocks })
+}
+
+// Claude 3.7 was the first to think; earlier models reject `thinking`
+fn thinks(model: &str) -> bool {
+    [
+        "claude-3-7",
+        "claude-sonnet-4",
+        "claude-opus-4",
+        "claude-haiku-4",
+    ]
+    .iter()
+    .any(|family| model.starts_with(family))
+}
+
+// Thinking can't be combined with the forced tool call structured output
+// relies on
+fn thinking_budget(request: &SendMessageRequest) -> Option<u32> {
+    request
+        .params
+        .thinking_budget
+        .filter(|_| request.response_schema.is_none() && thinks(&request.model))
+        .map(|budget| budget.max(MIN_THINKING_BUDGET))
+}
+
+/// Parameters a Messages API request passes on. Sampling settings can't be
+/// used while thinking.
+pub(crate) fn supported_params(request: &SendMessageRequest) -> &'static [&'static str] {
+    if thinking_budget(request).is_some() {
+        &["max_tokens", "stop", "thinking_budget"]
+    } else if request.response_schema.is_some() || !thinks(&request.model) {
+        &["temperature", "top_p", "max_tokens", "stop"]
+    } else {
+        &[
+            "temperature",
+            "top_p",
+            "max_tokens",
+            "stop",
+            "thinking_budget",
+        ]
+    }
+}
+
+pub(crate) fn request_body(
+    request: &SendMessageRequest,
+    stream: bool,
+) -> Result<Value, ProviderError> {
+    // Anthropic takes the system prompt as a top-level field
+    let (system, turns) = split_system(request.conversation());
+    let messages = turns_with_attachment(
+        merge_consecutive(turns),
+        request.attachment().as_ref(),
+        "Anthropic",
+        message_json,
+        |turn, file| {
+            Some(json!({
+                "role": turn.role,
+                "content": [file_block(file)?, { "type": "text", "text": turn.content }]
+            }))
+        },
+    )?;
+
+    // Several tool results answering one turn have to share a user message
+    let messages = merge_parts(messages, "user", "content");
+
+    let params = &request.params;
+    let mut body = json!({
+        "model": request.model,
+        "messages": messages,
+        "max_tokens": params.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+        "system": system.unwrap_or_else(|| DEFAULT_SYSTEM_PROMPT.to_string()),
+        "stream": stream
+    });
+
+    insert_some(&mut body, "temperature", &params.temperature);
+    insert_some(&mut body, "top_p", &params.top_p);
+    insert_some(&mut body, "stop_sequences", &params.stop);
+
+    if let Some(budget) = thinking_budget(request) {
+        body["thinking"] = json!({ "type": "enabled", "budget_tokens": budget });
+        // max_tokens counts the thinking as well as the answer
+        body["max_tokens"] = json!(budget + params.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS));
+        if let Some(body) = body.as_object_mut() {
+            body.remove("temperature");
+            body.remove("top_p");
+        }
+    }
+    if !request.tools.is_empty() {
+        body["tools"] = request
+            .tools
+            .iter()
+            .map(|tool| {
+                json!({
+                    "name": tool.name,
+                    "description": tool.description,
+                    "input_schema": tool.parameters
+                })
+            })
+            .collect();
+    }
+
+    // There is no JSON mode, but forcing a tool call gets the same result
+    if let Some(schema) = &request.response_schema {
+        body["tools"] = json!([{
+            "name": RESPONSE_TOOL,
+            "description": "Give your answer as this tool's input.",
+            "input_schema": tool_input_schema(schema)
+        }]);
+        body["tool_choice"] = json!({ "type": "tool", "name": RESPONSE_TOOL });
+    }
+    Ok(body)
+}
+
+// Cache reads and writes are billed apart from `input_tokens`, so they are
+// added back in to get the full prompt size
+fn parse_usage(usage: &Value) -> Usage {
+    let cache_read = usage["cache_read_input_tokens"].as_u64();
+    let cache_write = usage["cache_creation_input_tokens"].as_u64();
+
+    Usage {
+        input_tokens: usage["input_tokens"]
+            .as_u64()
+            .map(|tokens| tokens + cache_read.unwrap_or(0) + cache_write.unwrap_or(0)),
+        output_tokens: usage["output_tokens"].as_u64(),
+        cached_tokens: cache_read,
+        cache_write_tokens: cache_write,
+        reasoning_tokens: None,
+    }
+}
+
+pub(crate) fn parse_response(json: &Value, name: &str) -> Result<ApiResponse, ProviderError> {
+    let blocks = json["content"]
+        .as_array()
+        .ok_or_else(|| invalid_format(name, json))?;
+
+    let mut text = String::new();
+    let mut reasoning = String::new();
+    let mut thinking_blocks = Vec::new();
+    let mut calls = Vec::new();
+    for block in blocks {
+        match block["type"].as_str().unwrap_or_default() {
+            "text" => text.push_str(block["text"].as_str().unwrap_or_default()),
+            "thinking" => {
+                reasoning.push_str(block["thinking"].as_str().unwrap_or_default());
+                thinking_blocks.push(block.clone());
+            }
+            // Encrypted by Anthropic, only good for sending back
+            "redacted_thinking" => thinking_blocks.push(block.clone()),
+            "tool_use" => calls.push(ToolCall {
+                id: block["id"].as_str().unwrap_or_default().to_string(),
+                name: block["name"].as_str().unwrap_or_default().to_string(),
+                arguments: block["input"].clone(),
+            }),
+            _ => {}
+        }
+    }
+
+    if json["stop_reason"] == "refusal" && text.is_empty() {
+        return Err(ProviderError::content_filtered(
+            name,
+            "Claude declined to answer",
+            &json.to_string(),
+        ));
+    }
+    Ok(ApiResponse {
+        thinking_blocks,
+        ..ApiResponse::text(text)
+            .with_reasoning(reasoning)
+            .with_usage(Some(parse_usage(&json["usage"])))
+            .with_tool_calls(calls)
+    })
+}
+
+/// Reads a Messages API event stream, forwarding text and thinking deltas
+/// and usage. Tool calls are collected and returned with the response.
+pub(crate) async fn read_stream(
+    response: Response,
+    name: &str,
+    on_chunk: ChunkSink<'_>,
+) -> Result<ApiResponse, ProviderError> {
+    let mut content = String::new();
+    let mut reasoning = String::new();
+    let mut usage = Usage::default();
+    // Keyed by content block index
+    let mut calls: BTreeMap<u64, PartialToolCall> = BTreeMap::new();
+    let mut thinking_blocks: BTreeMap<u64, Value> = BTreeMap::new();
+
+    read_events(response, name, |event| {
+        let json = parse_event(&event.data, name)?;
+
+        match json["type"].as_str().unwrap_or_default() {
+            "message_start" => {
+                usage = parse_usage(&json["message"]["usage"]);
+            }
+            "content_block_start" if json["content_block"]["type"] == "tool_use" => {
+                let block = &json["content_block"];
+                calls.insert(
+                    json["index"].as_u64().unwrap_or_default(),
+                    PartialToolCall {
+                        id: block["id"].as_str().unwrap_or_default().to_string(),
+                        name: block["name"].as_str().unwrap_or_default().to_string(),
+                        arguments: String::new(),
+                    },
+                );
+            }
+            "content_block_start"
+                if matches!(
+                    json["content_block"]["type"].as_str(),
+                    Some("thinking" | "redacted_thinking")
+                ) =>
+            {
+                thinking_blocks.insert(
+                    json["index"].as_u64().unwrap_or_default(),
+                    json["content_block"].clone(),
+                );
+            }
+            "content_block_delta" => {
+                let index = json["index"].as_u64().unwrap_or_default();
+                let delta = &json["delta"];
+                if let Some(text) = delta["text"].as_str() {
+                    content.push_str(text);
+                    on_chunk(StreamChunk::Text(text.to_string()));
+                } else if let Some(partial) = delta["partial_json"].as_str() {
+                    if let Some(call) = calls.get_mut(&index) {
+                        call.arguments.push_str(partial);
+                    }
+                } else if let Some(thinking) = delta["thinking"].as_str() {
+                    reasoning.push_str(thinking);
+                    on_chunk(StreamChunk::Reasoning(thinking.to_string()));
+                    if let Some(block) = thinking_blocks.get_mut(&index) {
+                        let text = block["thinking"].as_str().unwrap_or_default();
+                        block["thinking"] = json!(format!("{}{}", text, thinking));
+                    }
+                } else if let Some(signature) = delta["signature"].as_str() {
+                    if let Some(block) = thinking_blocks.get_mut(&index) {
+                        block["signature"] = json!(signature);
+                    }
+                }
+            }
+            "message_delta" => {
+                usage.output_tokens = json["usage"]["output_tokens"].as_u64();
+            }
+            "message_stop" => return Ok(false),
+            "error" => return Err(ProviderError::from_event(name, &json)),
+            _ => {}
+        }
+
+        Ok(true)
+    })
+    .await?;
+
+    on_chunk(StreamChunk::Usage(usage.clone()));
+    Ok(ApiResponse {
+        thinking_blocks: thinking_blocks.into_values().collect(),
+        ..ApiResponse::text(content)
+            .with_reasoning(reasoning)
+            .with_usage(Some(usage))
+            .with_tool_calls(calls.into_values().map(PartialToolCall::finish).collect())
+    })
+}
+
+#[async_trait]
+impl Provider for AnthropicProvider {
+    fn id(&self) -> &str {
+        "anthropic"
+    }
+
+    fn display_name(&self) -> &str {
+        "Anthropic"
+    }
+
+    fn env_var(&self) -> &str {
+        "ANTHROPIC_API_KEY"
+    }
+
+    fn supported_params(&self, request: &SendMessageRequest) -> &[&str] {
+        supported_params(request)
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    fn supports_response_schema(&self) -> bool {
+        true
+    }
+
+    async fn complete(
+        &self,
+        client: &Client,
+        api_key: &str,
+        request: &SendMessageRequest,
+    ) -> Result<ApiResponse, ProviderError> {
+        let request = messages_request(client, api_key, &request_body(request, false)?);
+        let json = send_json(request, self.display_name()).await?;
+
+        parse_response(&json, self.display_name())
+    }
+
+    async fn stream(
+        &self,
+        client: &Client,
+        api_key: &str,
+        request: &SendMessageRequest,
+        on_chunk: ChunkSink<'_>,
+    ) -> Result<ApiResponse, ProviderError> {
+        let request = messages_request(client, api_key, &request_body(request, true)?);
+        let response = send_stream(request, self.display_name()).await?;
+
+        read_stream(response, self.display_name(), on_chunk).await
+    }
+
+    async fn list_models(
+        &self,
+        client: &Client,
+        api_key: &str,
+    ) -> Result<Vec<ModelInfo>, ProviderError> {
+        let request = with_auth(
+            client.get(format!("{}/models?limit=1000", ANTHROPIC_API_BASE)),
+            api_key,
+        );
+        let json = send_json(request, self.display_name()).await?;
+
+        json["data"]
+            .as_array()
+            .map(|models| {
+                models
+                    .iter()
+                    .filter_map(|model| {
+                        let mut info = ModelInfo::new(model["id"].as_str()?);
+                        if let Some(name) = model["display_name"].as_str() {
+                            info.display_name = name.to_string();
+                        }
+                        info.context_window = Some(CONTEXT_WINDOW);
+                        info.vision = true;
+                        info.documents = true;
+                        Some(info)
+                    })
+                    .collect()
+            })
+            .ok_or_else(|| invalid_format(self.display_name(), &json))
+    }
+
+    async fn verify_key(&self, client: &Client, api_key: &str) -> Result<(), ProviderError> {
+        let request = with_auth(
+            client.get(format!("{}/models", ANTHROPIC_API_BASE)),
+            api_key,
+        );
+
+        send_check(request, self.display_name()).await
+    }
+}
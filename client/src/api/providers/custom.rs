@@ -0,0 +1,228 @@
+use super::openai::compat_request_body;
+use super::{
+    anthropic, google, model_list, openai, send_check, send_json, send_stream, ChunkSink, Provider,
+    ProviderError,
+};
+use crate::models::{ApiResponse, ModelInfo, SendMessageRequest};
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// How the API key is attached to requests.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthStyle {
+    /// `Authorization: Bearer <key>`
+    #[default]
+    Bearer,
+    /// The key as the value of a named header, e.g. `{"header": "api-key"}`
+    Header(String),
+    /// The key as a query parameter, e.g. `{"query": "key"}`
+    Query(String),
+    None,
+}
+
+/// Which built-in API the server copies.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WireFormat {
+    #[default]
+    Openai,
+    Anthropic,
+    Gemini,
+}
+
+/// One entry of `custom_providers` in `config.json`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CustomProviderConfig {
+    pub id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    pub base_url: String,
+    #[serde(default)]
+    pub auth: AuthStyle,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(default)]
+    pub format: WireFormat,
+    // Offered instead of asking the server when set
+    #[serde(default)]
+    pub models: Vec<String>,
+    // Whether the server understands the format's tool calling fields
+    #[serde(default)]
+    pub tools: bool,
+}
+
+pub struct CustomProvider {
+    config: CustomProviderConfig,
+    env_var: String,
+}
+
+impl CustomProvider {
+    pub fn new(mut config: CustomProviderConfig) -> Self {
+        config.base_url = config.base_url.trim().trim_end_matches('/').to_string();
+        let env_var = format!(
+            "{}_API_KEY",
+            config
+                .id
+                .to_uppercase()
+                .replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+        );
+        Self { config, env_var }
+    }
+
+    fn authorized(&self, request: RequestBuilder, api_key: &str) -> RequestBuilder {
+        let mut request = match &self.config.auth {
+            _ if api_key.is_empty() => request,
+            AuthStyle::Bearer => request.header("Authorization", format!("Bearer {}", api_key)),
+            AuthStyle::Header(name) => request.header(name.as_str(), api_key),
+            AuthStyle::Query(name) => request.query(&[(name.as_str(), api_key)]),
+            AuthStyle::None => request,
+        };
+
+        if self.config.format == WireFormat::Anthropic
+            && !self.config.headers.contains_key("anthropic-version")
+        {
+            request = request.header("anthropic-version", anthropic::ANTHROPIC_VERSION);
+        }
+        for (name, value) in &self.config.headers {
+            request = request.header(name.as_str(), value.as_str());
+        }
+        request
+    }
+
+    fn chat_request(
+        &self,
+        client: &Client,
+        api_key: &str,
+        request: &SendMessageRequest,
+        stream: bool,
+    ) -> Result<RequestBuilder, ProviderError> {
+        let base_url = &self.config.base_url;
+        let (url, body) = match self.config.format {
+            WireFormat::Openai => (
+                format!("{}/chat/completions", base_url),
+                compat_request_body(request, self.display_name(), stream)?,
+            ),
+            WireFormat::Anthropic => (
+                format!("{}/messages", base_url),
+                anthropic::request_body(request, stream)?,
+            ),
+            WireFormat::Gemini if stream => (
+                format!(
+                    "{}/models/{}:streamGenerateContent?alt=sse",
+                    base_url, request.model
+                ),
+                google::request_body(request)?,
+            ),
+            WireFormat::Gemini => (
+                format!("{}/models/{}:generateContent", base_url, request.model),
+                google::request_body(request)?,
+            ),
+        };
+
+        Ok(self.authorized(client.post(url), api_key).json(&body))
+    }
+}
+
+#[async_trait]
+impl Provider for CustomProvider {
+    fn id(&self) -> &str {
+        &self.config.id
+    }
+
+    fn display_name(&self) -> &str {
+        self.config.name.as_deref().unwrap_or(&self.config.id)
+    }
+
+    fn env_var(&self) -> &str {
+        &self.env_var
+    }
+
+    fn requires_key(&self) -> bool {
+        !matches!(self.config.auth, AuthStyle::None)
+    }
+
+    fn supported_params(&self, request: &SendMessageRequest) -> &[&str] {
+        match self.config.format {
+            WireFormat::Anthropic => anthropic::supported_params(request),
+            WireFormat::Gemini => google::supported_params(request),
+            WireFormat::Openai => &["temperature", "top_p", "max_tokens", "stop", "seed"],
+        }
+    }
+
+    fn supports_tools(&self) -> bool {
+        self.config.tools
+    }
+
+    async fn complete(
+        &self,
+        client: &Client,
+        api_key: &str,
+        request: &SendMessageRequest,
+    ) -> Result<ApiResponse, ProviderError> {
+        let http_request = self.chat_request(client, api_key, request, false)?;
+        let json = send_json(http_request, self.display_name()).await?;
+
+        match self.config.format {
+            WireFormat::Openai => openai::parse_response(&json, self.display_name()),
+            WireFormat::Anthropic => anthropic::parse_response(&json, self.display_name()),
+            WireFormat::Gemini => google::parse_response(&json, self.display_name()),
+        }
+    }
+
+    async fn stream(
+        &self,
+        client: &Client,
+        api_key: &str,
+        request: &SendMessageRequest,
+        on_chunk: ChunkSink<'_>,
+    ) -> Result<ApiResponse, ProviderError> {
+        let http_request = self.chat_request(client, api_key, request, true)?;
+        let response = send_stream(http_request, self.display_name()).await?;
+
+        match self.config.format {
+            WireFormat::Openai => {
+                openai::read_stream(response, self.display_name(), on_chunk).await
+            }
+            WireFormat::Anthropic => {
+                anthropic::read_stream(response, self.display_name(), on_chunk).await
+            }
+            WireFormat::Gemini => {
+                google::read_stream(response, self.display_name(), on_chunk).await
+            }
+        }
+    }
+
+    async fn list_models(
+        &self,
+        client: &Client,
+        api_key: &str,
+    ) -> Result<Vec<ModelInfo>, ProviderError> {
+        if !self.config.models.is_empty() {
+            return Ok(self.config.models.iter().map(ModelInfo::new).collect());
+        }
+
+        let request = self.authorized(
+            client.get(format!("{}/models", self.config.base_url)),
+            api_key,
+        );
+        let json: Value = send_json(request, self.display_name()).await?;
+
+        match self.config.format {
+            WireFormat::Gemini => google::model_list(&json, self.display_name()),
+            _ => model_list(&json, self.display_name()),
+        }
+    }
+
+    async fn verify_key(&self, client: &Client, api_key: &str) -> Result<(), ProviderError> {
+        let request = self.authorized(
+            client.get(format!("{}/models", self.config.base_url)),
+            api_key,
+        );
+
+        send_check(request, self.display_name()).await
+    }
+}
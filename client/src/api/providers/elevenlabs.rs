@@ -0,0 +1,69 @@
+use super::{invalid_format, send_check, send_json, Provider, ProviderError};
+use crate::models::{ApiResponse, ModelInfo, SendMessageRequest};
+use async_trait::async_trait;
+use reqwest::Client;
+
+pub const ELEVENLABS_API_BASE: &str = "https://api.elevenlabs.io/v1";
+
+pub struct ElevenLabsProvider;
+
+#[async_trait]
+impl Provider for ElevenLabsProvider {
+    fn id(&self) -> &str {
+        "elevenlabs"
+    }
+
+    fn display_name(&self) -> &str {
+        "ElevenLabs"
+    }
+
+    fn env_var(&self) -> &str {
+        "ELEVENLABS_API_KEY"
+    }
+
+    async fn complete(
+        &self,
+        _client: &Client,
+        _api_key: &str,
+        _request: &SendMessageRequest,
+    ) -> Result<ApiResponse, ProviderError> {
+        // Speech goes through `api::speech::text_to_speech` instead
+        Ok(ApiResponse::text(
+            "Text-to-speech conversion not yet implemented",
+        ))
+    }
+
+    async fn list_models(
+        &self,
+        client: &Client,
+        api_key: &str,
+    ) -> Result<Vec<ModelInfo>, ProviderError> {
+        let request = client
+            .get(format!("{}/models", ELEVENLABS_API_BASE))
+            .header("xi-api-key", api_key);
+        let json = send_json(request, self.display_name()).await?;
+
+        json.as_array()
+            .map(|models| {
+                models
+                    .iter()
+                    .filter_map(|model| {
+                        let mut info = ModelInfo::new(model["model_id"].as_str()?);
+                        if let Some(name) = model["name"].as_str() {
+                            info.display_name = name.to_string();
+                        }
+                        Some(info)
+                    })
+                    .collect()
+            })
+            .ok_or_else(|| invalid_format(self.display_name(), &json))
+    }
+
+    async fn verify_key(&self, client: &Client, api_key: &str) -> Result<(), ProviderError> {
+        let request = client
+            .get(format!("{}/voices", ELEVENLABS_API_BASE))
+            .header("xi-api-key", api_key);
+
+        send_check(request, self.display_name()).await
+    }
+}
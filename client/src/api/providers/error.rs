@@ -0,0 +1,493 @@
+use super::retry;
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+use std::time::Duration;
+
+// Enough of an error body to diagnose it without shipping whole HTML pages
+const MAX_BODY_LEN: usize = 4000;
+
+// API keys as they show up in echoed requests, URLs and auth headers
+static SECRET: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"(?i)(bearer\s+)[^\s"',]+|((?:api[_-]?)?key=)[^&\s"',)]+|\b(?:sk|xai|pplx)-[a-z0-9_\-]{8,}|\bAIza[0-9a-z_\-]{20,}"#,
+    )
+    .unwrap()
+});
+
+/// What every provider error carries alongside its kind.
+#[derive(Serialize, Clone, Debug)]
+pub struct ErrorDetails {
+    pub provider: String,
+    // HTTP status, missing for errors that never got a response
+    pub status: Option<u16>,
+    pub message: String,
+    // Response body with anything that looks like a key masked out
+    pub raw_body: Option<String>,
+}
+
+/// A failed provider call, sorted into the cases the frontend can offer a
+/// specific fix for.
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ProviderError {
+    Auth {
+        #[serde(flatten)]
+        details: ErrorDetails,
+    },
+    RateLimited {
+        // Seconds the provider asked us to wait, when it said
+        retry_after: Option<u64>,
+        #[serde(flatten)]
+        details: ErrorDetails,
+    },
+    ContextLengthExceeded {
+        #[serde(flatten)]
+        details: ErrorDetails,
+    },
+    ContentFiltered {
+        #[serde(flatten)]
+        details: ErrorDetails,
+    },
+    QuotaExhausted {
+        #[serde(flatten)]
+        details: ErrorDetails,
+    },
+    // Overloaded or failing servers, after retries ran out
+    Unavailable {
+        #[serde(flatten)]
+        details: ErrorDetails,
+    },
+    Network {
+        #[serde(flatten)]
+        details: ErrorDetails,
+    },
+    // Requests the provider refused for any other reason, or that could not
+    // be built in the first place
+    InvalidRequest {
+        #[serde(flatten)]
+        details: ErrorDetails,
+    },
+    BadResponse {
+        #[serde(flatten)]
+        details: ErrorDetails,
+    },
+    Cancelled {
+        #[serde(flatten)]
+        details: ErrorDetails,
+    },
+}
+
+impl ProviderError {
+    /// Classifies a failed HTTP response.
+    pub(crate) fn from_response(
+        provider: &str,
+        status: StatusCode,
+        headers: &HeaderMap,
+        body: &str,
+    ) -> Self {
+        let retry_after =
+            retry::server_delay(headers).map(|delay| delay.as_secs_f64().ceil() as u64);
+        classify(provider, Some(status), retry_after, body)
+    }
+
+    /// Classifies an error reported inside a stream or a successful response.
+    pub(crate) fn from_event(provider: &str, json: &Value) -> Self {
+        classify(provider, None, None, &json.to_string())
+    }
+
+    pub(crate) fn network(provider: &str, error: &reqwest::Error) -> Self {
+        Self::Network {
+            details: details(
+                provider,
+                error.status().map(|status| status.as_u16()),
+                &error.to_string(),
+                "",
+            ),
+        }
+    }
+
+    /// The server went quiet for longer than the read timeout.
+    pub(crate) fn timed_out(provider: &str, timeout: Duration) -> Self {
+        Self::Network {
+            details: details(
+                provider,
+                None,
+                &format!("No response within {} seconds", timeout.as_secs()),
+                "",
+            ),
+        }
+    }
+
+    pub(crate) fn bad_response(provider: &str, message: impl AsRef<str>, body: &str) -> Self {
+        Self::BadResponse {
+            details: details(provider, None, message.as_ref(), body),
+        }
+    }
+
+    pub(crate) fn invalid_request(provider: &str, message: impl AsRef<str>) -> Self {
+        Self::InvalidRequest {
+            details: details(provider, None, message.as_ref(), ""),
+        }
+    }
+
+    pub(crate) fn content_filtered(provider: &str, message: impl AsRef<str>, body: &str) -> Self {
+        Self::ContentFiltered {
+            details: details(provider, None, message.as_ref(), body),
+        }
+    }
+
+    pub fn missing_key(provider: &str) -> Self {
+        Self::Auth {
+            details: details(
+                provider,
+                None,
+                "API key not configured. Please add your API key in settings.",
+                "",
+            ),
+        }
+    }
+
+    pub fn cancelled(provider: &str) -> Self {
+        Self::Cancelled {
+            details: details(provider, None, "Request cancelled", ""),
+        }
+    }
+
+    pub fn details(&self) -> &ErrorDetails {
+        match self {
+            Self::Auth { details }
+            | Self::RateLimited { details, .. }
+            | Self::ContextLengthExceeded { details }
+            | Self::ContentFiltered { details }
+            | Self::QuotaExhausted { details }
+            | Self::Unavailable { details }
+            | Self::Network { details }
+            | Self::InvalidRequest { details }
+            | Self::BadResponse { details }
+            | Self::Cancelled { details } => details,
+        }
+    }
+
+    /// Whether the same request might succeed if sent again later or to
+    /// another model.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Self::RateLimited { .. } | Self::Unavailable { .. } | Self::Network { .. }
+        )
+    }
+}
+
+impl fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ErrorDetails {
+            provider, message, ..
+        } = self.details();
+
+        match self {
+            Self::Auth { .. } => write!(f, "{} authentication failed: {}", provider, message),
+            Self::RateLimited {
+                retry_after: Some(seconds),
+                ..
+            } => write!(
+                f,
+                "Rate limit exceeded for {}. Please try again in {} seconds.",
+                provider, seconds
+            ),
+            Self::RateLimited { .. } => write!(
+                f,
+                "Rate limit exceeded for {}. Please try again later.",
+                provider
+            ),
+            Self::ContextLengthExceeded { .. } => write!(
+                f,
+                "The conversation is too long for this {} model: {}",
+                provider, message
+            ),
+            Self::ContentFiltered { .. } => {
+                write!(f, "{} blocked the response: {}", provider, message)
+            }
+            Self::QuotaExhausted { .. } => write!(
+                f,
+                "{} quota exhausted. Please check your plan and billing details. ({})",
+                provider, message
+            ),
+            Self::Unavailable { .. } => write!(f, "{} is unavailable: {}", provider, message),
+            Self::Network { .. } => {
+                write!(f, "Failed to send request to {} API: {}", provider, message)
+            }
+            Self::InvalidRequest { .. } => write!(f, "{} API Error: {}", provider, message),
+            Self::BadResponse { .. } => {
+                write!(f, "Unexpected response from {}: {}", provider, message)
+            }
+            Self::Cancelled { .. } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ProviderError {}
+
+fn details(provider: &str, status: Option<u16>, message: &str, body: &str) -> ErrorDetails {
+    ErrorDetails {
+        provider: provider.to_string(),
+        status,
+        message: redact(message),
+        raw_body: if body.trim().is_empty() {
+            None
+        } else {
+            Some(redact(body))
+        },
+    }
+}
+
+/// Masks API keys in `text` and cuts it down to a readable length.
+pub(crate) fn redact(text: &str) -> String {
+    let text = match text.char_indices().nth(MAX_BODY_LEN) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    };
+
+    SECRET
+        .replace_all(&text, |caps: &Captures| {
+            let prefix = caps
+                .get(1)
+                .or_else(|| caps.get(2))
+                .map_or("", |prefix| prefix.as_str());
+            format!("{}[REDACTED]", prefix)
+        })
+        .into_owned()
+}
+
+fn classify(
+    provider: &str,
+    status: Option<StatusCode>,
+    retry_after: Option<u64>,
+    body: &str,
+) -> ProviderError {
+    let json: Value = serde_json::from_str(body).unwrap_or_default();
+    // Gemini streams send errors as a one element array
+    let json = match &json {
+        Value::Array(items) => items.first().cloned().unwrap_or_default(),
+        _ => json,
+    };
+    let error = json.get("error").unwrap_or(&json);
+
+    let message = error["message"]
+        .as_str()
+        .or_else(|| error.as_str())
+        .or_else(|| json["detail"]["message"].as_str())
+        .or_else(|| json["detail"].as_str())
+        .unwrap_or(body);
+
+    // Error codes live under different names depending on the provider
+    let codes = [
+        &error["type"],
+        &error["code"],
+        &error["status"],
+        &json["detail"]["status"],
+    ]
+    .iter()
+    .filter_map(|code| match code {
+        Value::String(code) => Some(code.to_lowercase()),
+        Value::Number(code) => Some(code.to_string()),
+        _ => None,
+    })
+    .collect::<Vec<_>>()
+    .join(" ");
+    let text = message.to_lowercase();
+
+    let code_has = |needles: &[&str]| needles.iter().any(|needle| codes.contains(needle));
+    let text_has = |needles: &[&str]| needles.iter().any(|needle| text.contains(needle));
+    let status_is = |check: fn(StatusCode) -> bool| status.map_or(false, check);
+
+    let details = details(
+        provider,
+        status.map(|status| status.as_u16()),
+        message,
+        body,
+    );
+
+    if code_has(&["context_length"])
+        || text_has(&[
+            "context length",
+            "context window",
+            "prompt is too long",
+            "too many tokens",
+            "maximum number of tokens",
+            "input token count",
+            "reduce the length",
+        ])
+    {
+        ProviderError::ContextLengthExceeded { details }
+    } else if code_has(&["insufficient_quota", "billing"])
+        || text_has(&[
+            "credit balance",
+            "billing",
+            "insufficient credits",
+            "exceeded your current quota",
+        ])
+        || status == Some(StatusCode::PAYMENT_REQUIRED)
+    {
+        ProviderError::QuotaExhausted { details }
+    } else if code_has(&[
+        "authentication_error",
+        "permission_error",
+        "invalid_api_key",
+        "unauthenticated",
+        "permission_denied",
+    ]) || text_has(&[
+        "api key not valid",
+        "invalid api key",
+        "incorrect api key",
+        "invalid x-api-key",
+    ]) || matches!(
+        status,
+        Some(StatusCode::UNAUTHORIZED) | Some(StatusCode::FORBIDDEN)
+    ) {
+        ProviderError::Auth { details }
+    } else if code_has(&["content_filter", "content_policy", "safety"])
+        || text_has(&[
+            "content policy",
+            "safety system",
+            "content management policy",
+        ])
+    {
+        ProviderError::ContentFiltered { details }
+    } else if code_has(&["rate_limit", "resource_exhausted"])
+        || text_has(&[
+            "rate limit",
+            "resource exhausted",
+            "resource has been exhausted",
+        ])
+        || status == Some(StatusCode::TOO_MANY_REQUESTS)
+    {
+        ProviderError::RateLimited {
+            retry_after,
+            details,
+        }
+    } else if code_has(&["overloaded", "unavailable", "server_error", "internal"])
+        || text_has(&["overloaded"])
+        || status_is(|status| status.is_server_error())
+    {
+        ProviderError::Unavailable { details }
+    } else if status_is(|status| status.is_client_error()) {
+        ProviderError::InvalidRequest { details }
+    } else {
+        ProviderError::BadResponse { details }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+    use serde_json::json;
+
+    fn classify_body(status: u16, body: Value) -> ProviderError {
+        classify(
+            "Test",
+            Some(StatusCode::from_u16(status).unwrap()),
+            None,
+            &body.to_string(),
+        )
+    }
+
+    #[test]
+    fn classifies_provider_error_bodies() {
+        let openai = classify_body(
+            400,
+            json!({"error": {"message": "This model's maximum context length is 128000 tokens", "code": "context_length_exceeded"}}),
+        );
+        assert!(matches!(
+            openai,
+            ProviderError::ContextLengthExceeded { .. }
+        ));
+
+        let anthropic = classify_body(
+            400,
+            json!({"type": "error", "error": {"type": "invalid_request_error", "message": "Your credit balance is too low"}}),
+        );
+        assert!(matches!(anthropic, ProviderError::QuotaExhausted { .. }));
+
+        let overloaded = classify_body(
+            529,
+            json!({"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}}),
+        );
+        assert!(matches!(overloaded, ProviderError::Unavailable { .. }));
+
+        let gemini = classify_body(
+            400,
+            json!([{"error": {"code": 400, "message": "API key not valid. Please pass a valid API key.", "status": "INVALID_ARGUMENT"}}]),
+        );
+        assert!(matches!(gemini, ProviderError::Auth { .. }));
+        assert_eq!(
+            gemini.details().message,
+            "API key not valid. Please pass a valid API key."
+        );
+
+        let invalid = classify_body(422, json!({"detail": "Unknown model"}));
+        assert!(matches!(invalid, ProviderError::InvalidRequest { .. }));
+        assert_eq!(invalid.details().status, Some(422));
+    }
+
+    #[test]
+    fn rate_limits_carry_the_server_delay() {
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", HeaderValue::from_static("1.2"));
+        let error = ProviderError::from_response(
+            "Test",
+            StatusCode::TOO_MANY_REQUESTS,
+            &headers,
+            "slow down",
+        );
+        assert!(matches!(
+            error,
+            ProviderError::RateLimited {
+                retry_after: Some(2),
+                ..
+            }
+        ));
+        assert!(error.is_retryable());
+        assert_eq!(error.details().raw_body.as_deref(), Some("slow down"));
+    }
+
+    #[test]
+    fn stream_errors_without_a_status_are_bad_responses() {
+        let error = ProviderError::from_event("Test", &json!({"error": {"message": "boom"}}));
+        assert!(matches!(error, ProviderError::BadResponse { .. }));
+        assert!(!error.is_retryable());
+    }
+
+    #[test]
+    fn redacts_keys() {
+        assert_eq!(
+            redact("Authorization: Bearer abc.def-123, next"),
+            "Authorization: Bearer [REDACTED], next"
+        );
+        assert_eq!(
+            redact("GET /v1/models?key=AIzaSecret123&alt=sse"),
+            "GET /v1/models?key=[REDACTED]&alt=sse"
+        );
+        assert_eq!(
+            redact(r#"{"api_key": "sk-proj-abcdefgh12345"}"#),
+            r#"{"api_key": "[REDACTED]"}"#
+        );
+        assert_eq!(
+            redact("AIzaSyA1234567890abcdefghijkl used"),
+            "[REDACTED] used"
+        );
+        assert_eq!(redact("nothing secret"), "nothing secret");
+    }
+
+    #[test]
+    fn long_bodies_are_cut() {
+        let redacted = redact(&"é".repeat(MAX_BODY_LEN + 10));
+        assert_eq!(redacted.chars().count(), MAX_BODY_LEN + 1);
+        assert!(redacted.ends_with('…'));
+    }
+}
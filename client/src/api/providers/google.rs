@@ -0,0 +1,662 @@
+use super::openai::parse_event;
+use super::sse::read_events;
+use super::{
+    insert_some, invalid_format, merge_consecutive, merge_parts, send_check, send_json,
+    send_stream, split_system, turns_with_attachment, ChunkSink, Provider, ProviderError,
+    StreamChunk,
+};
+use crate::files::Attachment;
+use crate::models::{
+    ApiResponse, ChatMessage, ModelInfo, Role, SendMessageRequest, ToolCall, ToolSpec, Usage,
+};
+use async_trait::async_trait;
+use reqwest::{Client, Response};
+use serde_json::{json, Value};
+
+const GEMINI_API_BASE: &str = "https://generativelanguage.googleapis.com/v1beta";
+
+pub struct GoogleProvider;
+
+fn safety_settings() -> Value {
+    let categories = [
+        "HARM_CATEGORY_HARASSMENT",
+        "HARM_CATEGORY_HATE_SPEECH",
+        "HARM_CATEGORY_SEXUALLY_EXPLICIT",
+        "HARM_CATEGORY_DANGEROUS_CONTENT",
+    ];
+
+    categories
+        .iter()
+        .map(|category| {
+            json!({
+                "category": category,
+                "threshold": "BLOCK_MEDIUM_AND_ABOVE"
+            })
+        })
+        .collect()
+}
+
+// Gemini calls the assistant role "model"
+fn gemini_role(message: &ChatMessage) -> &'static str {
+    match message.role {
+        Role::Assistant => "model",
+        _ => "user",
+    }
+}
+
+// Calls become `functionCall` parts and results `functionResponse` parts.
+// Gemini matches results to calls by name, so the ids stay on our side.
+fn message_parts(message: &ChatMessage) -> Value {
+    if let Some(result) = &message.tool_result {
+        return json!([{
+            "functionResponse": {
+                "name": result.name,
+                "response": { "content": result.content }
+            }
+        }]);
+    }
+
+    let mut parts = Vec::new();
+    if !message.content.is_empty() || message.tool_calls.is_empty() {
+        parts.push(json!({ "text": message.content }));
+    }
+    for call in &message.tool_calls {
+        parts.push(json!({
+            "functionCall": {
+                "name": call.name,
+                "args": call.arguments
+            }
+        }));
+    }
+    Value::Array(parts)
+}
+
+// Deepest chain of `$ref`s followed before a schema is given up on, which
+// also stops self-referencing schemas
+const MAX_SCHEMA_DEPTH: usize = 16;
+
+// Keys of Gemini's OpenAPI subset; everything else, such as `default` and
+// `examples`, is rejected by the API and dropped
+const GEMINI_SCHEMA_KEYS: &[&str] = &[
+    "type",
+    "format",
+    "title",
+    "description",
+    "nullable",
+    "enum",
+    "properties",
+    "required",
+    "items",
+    "minItems",
+    "maxItems",
+    "minimum",
+    "maximum",
+    "anyOf",
+    "propertyOrdering",
+];
+
+/// Converts a JSON Schema to the OpenAPI subset Gemini accepts, inlining
+/// `$ref`s. None when it can't be expressed there.
+fn gemini_schema(schema: &Value) -> Option<Value> {
+    convert_schema(schema, schema, 0)
+}
+
+fn convert_schema(schema: &Value, root: &Value, depth: usize) -> Option<Value> {
+    if depth > MAX_SCHEMA_DEPTH {
+        return None;
+    }
+    let fields = match schema {
+        Value::Object(fields) => fields,
+        // `true` accepts anything, which Gemini has no way to say
+        _ => return None,
+    };
+
+    if let Some(reference) = fields.get("$ref") {
+        let target = reference
+            .as_str()
+            .and_then(|reference| reference.strip_prefix('#'))
+            .and_then(|pointer| root.pointer(pointer))?;
+        let mut resolved = convert_schema(target, root, depth + 1)?;
+        // Siblings of `$ref` only add a description in practice
+        if let Some(description) = fields.get("description") {
+            resolved["description"] = description.clone();
+        }
+        return Some(resolved);
+    }
+
+    let mut converted = serde_json::Map::new();
+    for (key, value) in fields {
+        match key.as_str() {
+            "properties" => {
+                let properties = value
+                    .as_object()?
+                    .iter()
+                    .map(|(name, property)| {
+                        Some((name.clone(), convert_schema(property, root, depth + 1)?))
+                    })
+                    .collect::<Option<serde_json::Map<_, _>>>()?;
+                converted.insert(key.clone(), Value::Object(properties));
+            }
+            "items" => {
+                converted.insert(key.clone(), convert_schema(value, root, depth + 1)?);
+            }
+            "oneOf" | "anyOf" => {
+                let variants = value.as_array()?;
+                // A union of constants is how many generators write an enum
+                let constants: Option<Vec<Value>> = variants
+                    .iter()
+                    .map(|variant| {
+                        variant
+                            .get("const")
+                            .filter(|value| value.is_string())
+                            .cloned()
+                    })
+                    .collect();
+                match constants {
+                    Some(constants) if !constants.is_empty() => {
+                        converted.insert("type".to_string(), json!("string"));
+                        converted.insert("enum".to_string(), Value::Array(constants));
+                    }
+                    _ => {
+                        let variants = variants
+                            .iter()
+                            .map(|variant| convert_schema(variant, root, depth + 1))
+                            .collect::<Option<Vec<_>>>()?;
+                        converted.insert("anyOf".to_string(), Value::Array(variants));
+                    }
+                }
+            }
+            "const" => {
+                // Gemini's enums only hold strings
+                value.as_str()?;
+                converted.insert("type".to_string(), json!("string"));
+                converted.insert("enum".to_string(), json!([value]));
+            }
+            "type" => match value {
+                Value::String(_) => {
+                    converted.insert(key.clone(), value.clone());
+                }
+                // ["string", "null"] becomes a nullable string
+                Value::Array(types) => {
+                    let mut types: Vec<&Value> = types.iter().filter(|t| *t != "null").collect();
+                    if types.len() != 1 {
+                        return None;
+                    }
+                    if types.len() < value.as_array()?.len() {
+                        converted.insert("nullable".to_string(), json!(true));
+                    }
+                    converted.insert(key.clone(), types.remove(0).clone());
+                }
+                _ => return None,
+            },
+            "enum" => {
+                if !value.as_array()?.iter().all(Value::is_string) {
+                    return None;
+                }
+                converted.insert(key.clone(), value.clone());
+            }
+            key if GEMINI_SCHEMA_KEYS.contains(&key) => {
+                converted.insert(key.to_string(), value.clone());
+            }
+            _ => {}
+        }
+    }
+    Some(Value::Object(converted))
+}
+
+// Tools whose parameters Gemini can't take are left out rather than
+// failing the whole request
+fn tools_json(tools: &[ToolSpec]) -> Option<Value> {
+    let declarations: Vec<Value> = tools
+        .iter()
+        .filter_map(|tool| match gemini_schema(&tool.parameters) {
+            Some(parameters) => Some(json!({
+                "name": tool.name,
+                "description": tool.description,
+                "parameters": parameters
+            })),
+            None => {
+                println!(
+                    "Not offering {} to Gemini: its schema can't be converted",
+                    tool.name
+                );
+                None
+            }
+        })
+        .collect();
+    if declarations.is_empty() {
+        return None;
+    }
+    Some(json!([{ "functionDeclarations": declarations }]))
+}
+
+fn gemini_contents(
+    messages: Vec<ChatMessage>,
+    attachment: Option<&Attachment>,
+) -> Result<Value, ProviderError> {
+    turns_with_attachment(
+        messages,
+        attachment,
+        "Gemini",
+        |message| {
+            json!({
+                "role": gemini_role(message),
+                "parts": message_parts(message)
+            })
+        },
+        |message, file| match file {
+            Attachment::Binary {
+                mime_type, data, ..
+            } if file.is_image() || file.is_pdf() => Some(json!({
+                "role": gemini_role(message),
+                "parts": [
+                    {
+                        "inline_data": {
+                            "mime_type": mime_type,
+                            "data": data
+                        }
+                    },
+                    { "text": message.content }
+                ]
+            })),
+            _ => None,
+        },
+    )
+    // Results for several calls have to come back in one turn
+    .map(|contents| merge_parts(contents, "user", "parts"))
+}
+
+// Gemini 2.5 was the first to think; older models reject `thinkingConfig`
+fn thinks(model: &str) -> bool {
+    !["gemini-1", "gemini-2.0"]
+        .iter()
+        .any(|family| model.starts_with(family))
+}
+
+/// Parameters a `generateContent` request passes on.
+pub(crate) fn supported_params(request: &SendMessageRequest) -> &'static [&'static str] {
+    if thinks(&request.model) {
+        &[
+            "temperature",
+            "top_p",
+            "max_tokens",
+            "stop",
+            "seed",
+            "thinking_budget",
+        ]
+    } else {
+        &["temperature", "top_p", "max_tokens", "stop", "seed"]
+    }
+}
+
+pub(crate) fn request_body(request: &SendMessageRequest) -> Result<Value, ProviderError> {
+    let (system, turns) = split_system(request.conversation());
+    let params = &request.params;
+
+    let mut generation_config = json!({
+        "topP": params.top_p.unwrap_or(0.8),
+        "topK": 40,
+        "maxOutputTokens": params.max_tokens.unwrap_or(2048)
+    });
+    insert_some(&mut generation_config, "temperature", &params.temperature);
+    insert_some(&mut generation_config, "stopSequences", &params.stop);
+    insert_some(&mut generation_config, "seed", &params.seed);
+    // Thoughts are only returned when asked for, and only by models that
+    // think, so they aren't requested otherwise
+    if let Some(budget) = params.thinking_budget.filter(|_| thinks(&request.model)) {
+        generation_config["thinkingConfig"] = json!({
+            "thinkingBudget": budget,
+            "includeThoughts": true
+        });
+        // As with Claude, the output limit covers the thinking too
+        generation_config["maxOutputTokens"] = json!(budget + params.max_tokens.unwrap_or(2048));
+    }
+    if let Some(schema) = &request.response_schema {
+        generation_config["responseMimeType"] = json!("application/json");
+        // The answer is still checked against the full schema afterwards
+        if let Some(schema) = gemini_schema(schema) {
+            generation_config["responseSchema"] = schema;
+        }
+    }
+
+    // Basic request structure following Gemini API docs
+    let mut body = json!({
+        "contents": gemini_contents(merge_consecutive(turns), request.attachment().as_ref())?,
+        "generationConfig": generation_config,
+        "safetySettings": safety_settings()
+    });
+    if let Some(tools) = tools_json(&request.tools) {
+        body["tools"] = tools;
+    }
+
+    if let Some(system) = system {
+        body["systemInstruction"] = json!({
+            "parts": [{
+                "text": system
+            }]
+        });
+    }
+
+    Ok(body)
+}
+
+// Thinking tokens are counted apart from the answer, unlike OpenAI
+fn parse_usage(metadata: &Value) -> Usage {
+    let thoughts = metadata["thoughtsTokenCount"].as_u64();
+    let candidates = metadata["candidatesTokenCount"].as_u64();
+
+    Usage {
+        input_tokens: metadata["promptTokenCount"].as_u64(),
+        output_tokens: match (candidates, thoughts) {
+            (None, None) => None,
+            _ => Some(candidates.unwrap_or(0) + thoughts.unwrap_or(0)),
+        },
+        cached_tokens: metadata["cachedContentTokenCount"].as_u64(),
+        cache_write_tokens: None,
+        reasoning_tokens: thoughts,
+    }
+}
+
+// Gemini reports errors and blocked prompts or answers in 200 responses
+fn check_response(json: &Value, name: &str) -> Result<(), ProviderError> {
+    if json.get("error").is_some() {
+        return Err(ProviderError::from_event(name, json));
+    }
+
+    let blocked = json["promptFeedback"]["blockReason"].as_str().or_else(|| {
+        match json["candidates"][0]["finishReason"].as_str() {
+            Some(reason @ ("SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT")) => {
+                Some(reason)
+            }
+            _ => None,
+        }
+    });
+    match blocked {
+        Some(reason) => Err(ProviderError::content_filtered(
+            name,
+            format!("Blocked for {}", reason.to_lowercase()),
+            &json.to_string(),
+        )),
+        None => Ok(()),
+    }
+}
+
+// Thought summaries come as text parts flagged with `thought`
+fn part_texts(parts: &[Value], thought: bool) -> impl Iterator<Item = &str> {
+    parts
+        .iter()
+        .filter(move |part| part["thought"].as_bool().unwrap_or(false) == thought)
+        .filter_map(|part| part["text"].as_str())
+}
+
+fn function_calls(parts: &[Value]) -> impl Iterator<Item = ToolCall> + '_ {
+    parts
+        .iter()
+        .filter_map(|part| part.get("functionCall"))
+        .map(|call| ToolCall {
+            id: format!("call_{}", uuid::Uuid::new_v4().simple()),
+            name: call["name"].as_str().unwrap_or_default().to_string(),
+            arguments: call.get("args").cloned().unwrap_or_else(|| json!({})),
+        })
+}
+
+pub(crate) fn parse_response(json: &Value, name: &str) -> Result<ApiResponse, ProviderError> {
+    check_response(json, name)?;
+
+    let usage = json["usageMetadata"]
+        .is_object()
+        .then(|| parse_usage(&json["usageMetadata"]));
+    let parts = json["candidates"][0]["content"]["parts"]
+        .as_array()
+        .ok_or_else(|| invalid_format(name, json))?;
+
+    Ok(
+        ApiResponse::text(part_texts(parts, false).collect::<String>())
+            .with_reasoning(part_texts(parts, true).collect())
+            .with_usage(usage)
+            .with_tool_calls(function_calls(parts).collect()),
+    )
+}
+
+/// Reads a `streamGenerateContent?alt=sse` body, forwarding text, thoughts
+/// and usage. Function calls arrive whole and are returned with the
+/// response.
+pub(crate) async fn read_stream(
+    response: Response,
+    name: &str,
+    on_chunk: ChunkSink<'_>,
+) -> Result<ApiResponse, ProviderError> {
+    let mut content = String::new();
+    let mut reasoning = String::new();
+    let mut usage = None;
+    let mut calls = Vec::new();
+
+    read_events(response, name, |event| {
+        let json = parse_event(&event.data, name)?;
+        check_response(&json, name)?;
+
+        if let Some(parts) = json["candidates"][0]["content"]["parts"].as_array() {
+            for thought in part_texts(parts, true) {
+                reasoning.push_str(thought);
+                on_chunk(StreamChunk::Reasoning(thought.to_string()));
+            }
+            for text in part_texts(parts, false) {
+                content.push_str(text);
+                on_chunk(StreamChunk::Text(text.to_string()));
+            }
+            calls.extend(function_calls(parts));
+        }
+
+        if json["usageMetadata"].is_object() {
+            usage = Some(parse_usage(&json["usageMetadata"]));
+        }
+
+        Ok(true)
+    })
+    .await?;
+
+    if let Some(usage) = &usage {
+        on_chunk(StreamChunk::Usage(usage.clone()));
+    }
+
+    Ok(ApiResponse::text(content)
+        .with_reasoning(reasoning)
+        .with_usage(usage)
+        .with_tool_calls(calls))
+}
+
+/// Reads a `models.list` response, keeping only models that can chat.
+/// Ids are returned without the `models/` prefix.
+pub(crate) fn model_list(json: &Value, name: &str) -> Result<Vec<ModelInfo>, ProviderError> {
+    let models = json["models"]
+        .as_array()
+        .ok_or_else(|| invalid_format(name, json))?;
+
+    Ok(models
+        .iter()
+        .filter(|model| {
+            model["supportedGenerationMethods"]
+                .as_array()
+                .map_or(true, |methods| {
+                    methods.iter().any(|method| method == "generateContent")
+                })
+        })
+        .filter_map(|model| {
+            let id = model["name"].as_str()?.trim_start_matches("models/");
+            let mut info = ModelInfo::new(id);
+            if let Some(name) = model["displayName"].as_str() {
+                info.display_name = name.to_string();
+            }
+            info.context_window = model["inputTokenLimit"].as_u64();
+            // Gemini models take images, PDFs and audio inline
+            let multimodal = id.starts_with("gemini");
+            info.vision = multimodal;
+            info.documents = multimodal;
+            info.audio = multimodal;
+            Some(info)
+        })
+        .collect())
+}
+
+#[async_trait]
+impl Provider for GoogleProvider {
+    fn id(&self) -> &str {
+        "google"
+    }
+
+    fn display_name(&self) -> &str {
+        "Gemini"
+    }
+
+    fn env_var(&self) -> &str {
+        "GOOGLE_API_KEY"
+    }
+
+    fn supported_params(&self, request: &SendMessageRequest) -> &[&str] {
+        supported_params(request)
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    fn supports_response_schema(&self) -> bool {
+        true
+    }
+
+    async fn complete(
+        &self,
+        client: &Client,
+        api_key: &str,
+        request: &SendMessageRequest,
+    ) -> Result<ApiResponse, ProviderError> {
+        println!("Request model: {}", request.model);
+        let url = format!(
+            "{}/models/{}:generateContent?key={}",
+            GEMINI_API_BASE, request.model, api_key
+        );
+        println!("Request URL: {}", url.replace(api_key, "API_KEY_HIDDEN"));
+
+        let request = client
+            .post(&url)
+            .header("Content-Type", "application/json")
+            .json(&request_body(request)?);
+
+        let json = send_json(request, self.display_name()).await?;
+        parse_response(&json, self.display_name())
+    }
+
+    async fn stream(
+        &self,
+        client: &Client,
+        api_key: &str,
+        request: &SendMessageRequest,
+        on_chunk: ChunkSink<'_>,
+    ) -> Result<ApiResponse, ProviderError> {
+        let url = format!(
+            "{}/models/{}:streamGenerateContent?alt=sse&key={}",
+            GEMINI_API_BASE, request.model, api_key
+        );
+
+        let request = client
+            .post(&url)
+            .header("Content-Type", "application/json")
+            .json(&request_body(request)?);
+        let response = send_stream(request, self.display_name()).await?;
+
+        read_stream(response, self.display_name(), on_chunk).await
+    }
+
+    async fn list_models(
+        &self,
+        client: &Client,
+        api_key: &str,
+    ) -> Result<Vec<ModelInfo>, ProviderError> {
+        let request = client.get(format!(
+            "{}/models?pageSize=1000&key={}",
+            GEMINI_API_BASE, api_key
+        ));
+        let json = send_json(request, self.display_name()).await?;
+        model_list(&json, self.display_name())
+    }
+
+    async fn verify_key(&self, client: &Client, api_key: &str) -> Result<(), ProviderError> {
+        let request = client.get(format!("{}/models?key={}", GEMINI_API_BASE, api_key));
+
+        send_check(request, self.display_name()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schemas_are_converted_to_the_openapi_subset() {
+        let schema = json!({
+            "$schema": "http://json-schema.org/draft-07/schema#",
+            "type": "object",
+            "additionalProperties": false,
+            "properties": {
+                "unit": {
+                    "oneOf": [{ "const": "celsius" }, { "const": "fahrenheit" }],
+                    "default": "celsius"
+                },
+                "place": { "$ref": "#/$defs/place", "description": "Where" },
+                "note": { "type": ["string", "null"], "examples": ["sunny"] }
+            },
+            "required": ["place"],
+            "$defs": {
+                "place": { "type": "object", "properties": { "city": { "type": "string" } } }
+            }
+        });
+
+        assert_eq!(
+            gemini_schema(&schema).unwrap(),
+            json!({
+                "type": "object",
+                "properties": {
+                    "unit": { "type": "string", "enum": ["celsius", "fahrenheit"] },
+                    "place": {
+                        "type": "object",
+                        "properties": { "city": { "type": "string" } },
+                        "description": "Where"
+                    },
+                    "note": { "type": "string", "nullable": true }
+                },
+                "required": ["place"]
+            })
+        );
+    }
+
+    #[test]
+    fn unconvertible_schemas_are_refused() {
+        assert!(gemini_schema(&json!({ "$ref": "#" })).is_none());
+        assert!(gemini_schema(&json!({ "$ref": "#/$defs/missing" })).is_none());
+        assert!(gemini_schema(&json!({ "const": 3 })).is_none());
+        assert!(gemini_schema(&json!({ "type": ["string", "integer"] })).is_none());
+    }
+
+    #[test]
+    fn tools_with_unconvertible_schemas_are_left_out() {
+        let tool = |name: &str, parameters: Value| ToolSpec {
+            name: name.to_string(),
+            description: String::new(),
+            parameters,
+        };
+        let tools = [
+            tool("loop", json!({ "$ref": "#" })),
+            tool("ok", json!({ "type": "object", "properties": {} })),
+        ];
+
+        let declarations = tools_json(&tools).unwrap();
+        let names: Vec<&Value> = declarations[0]["functionDeclarations"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|declaration| &declaration["name"])
+            .collect();
+        assert_eq!(names, [&json!("ok")]);
+        assert!(tools_json(&tools[..1]).is_none());
+    }
+}
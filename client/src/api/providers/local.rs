@@ -0,0 +1,175 @@
+use super::openai::{chat_completion, chat_completion_stream, compat_request_body, parse_response};
+use super::{model_list, send_json, ChunkSink, Provider, ProviderError};
+use crate::models::{ApiResponse, ModelInfo, SendMessageRequest};
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder};
+use serde_json::Value;
+
+/// Ollama's default address. llama.cpp's server listens on port 8080.
+pub const DEFAULT_LOCAL_BASE_URL: &str = "http://localhost:11434";
+
+/// A self-hosted server speaking the OpenAI chat format, such as Ollama or
+/// llama.cpp. Keys are optional and only sent when one is configured.
+pub struct LocalProvider {
+    base_url: String,
+}
+
+impl LocalProvider {
+    pub fn new(base_url: &str) -> Self {
+        // Accept the server root with or without the OpenAI `/v1` prefix
+        let base_url = base_url.trim().trim_end_matches('/');
+        let base_url = base_url.strip_suffix("/v1").unwrap_or(base_url);
+        Self {
+            base_url: base_url.to_string(),
+        }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn api_base(&self) -> String {
+        format!("{}/v1", self.base_url)
+    }
+
+    fn get(&self, client: &Client, path: &str, api_key: &str) -> RequestBuilder {
+        let request = client.get(format!("{}{}", self.base_url, path));
+        if api_key.is_empty() {
+            request
+        } else {
+            request.header("Authorization", format!("Bearer {}", api_key))
+        }
+    }
+}
+
+// Vision models in Ollama ship a CLIP or mllama projector
+fn ollama_model(model: &Value) -> Option<ModelInfo> {
+    let mut info = ModelInfo::new(model["name"].as_str()?);
+    info.vision = model["details"]["families"]
+        .as_array()
+        .map_or(false, |families| {
+            families
+                .iter()
+                .any(|family| family == "clip" || family == "mllama")
+        });
+    Some(info)
+}
+
+#[async_trait]
+impl Provider for LocalProvider {
+    fn id(&self) -> &str {
+        "local"
+    }
+
+    fn display_name(&self) -> &str {
+        "Local model"
+    }
+
+    fn env_var(&self) -> &str {
+        "LOCAL_API_KEY"
+    }
+
+    fn requires_key(&self) -> bool {
+        false
+    }
+
+    fn supported_params(&self, _request: &SendMessageRequest) -> &[&str] {
+        &["temperature", "top_p", "max_tokens", "stop", "seed"]
+    }
+
+    async fn complete(
+        &self,
+        client: &Client,
+        api_key: &str,
+        request: &SendMessageRequest,
+    ) -> Result<ApiResponse, ProviderError> {
+        let json = chat_completion(
+            client,
+            &self.api_base(),
+            api_key,
+            &compat_request_body(request, self.display_name(), false)?,
+            self.display_name(),
+        )
+        .await?;
+
+        parse_response(&json, self.display_name())
+    }
+
+    async fn stream(
+        &self,
+        client: &Client,
+        api_key: &str,
+        request: &SendMessageRequest,
+        on_chunk: ChunkSink<'_>,
+    ) -> Result<ApiResponse, ProviderError> {
+        chat_completion_stream(
+            client,
+            &self.api_base(),
+            api_key,
+            &compat_request_body(request, self.display_name(), true)?,
+            self.display_name(),
+            on_chunk,
+        )
+        .await
+    }
+
+    /// Asks Ollama's `/api/tags` first and falls back to the OpenAI style
+    /// `/v1/models` that llama.cpp and most other servers expose.
+    async fn list_models(
+        &self,
+        client: &Client,
+        api_key: &str,
+    ) -> Result<Vec<ModelInfo>, ProviderError> {
+        let tags = send_json(self.get(client, "/api/tags", api_key), self.display_name()).await;
+        if let Ok(json) = tags {
+            if let Some(models) = json["models"].as_array() {
+                return Ok(models.iter().filter_map(ollama_model).collect());
+            }
+        }
+
+        let json = send_json(self.get(client, "/v1/models", api_key), self.display_name()).await?;
+        model_list(&json, self.display_name())
+    }
+
+    // There may be no key to check, so just make sure the server answers
+    async fn verify_key(&self, client: &Client, api_key: &str) -> Result<(), ProviderError> {
+        self.list_models(client, api_key).await.map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn base_url_is_the_server_root() {
+        assert_eq!(
+            LocalProvider::new("http://localhost:11434/").base_url(),
+            "http://localhost:11434"
+        );
+        assert_eq!(
+            LocalProvider::new(" http://box:8080/v1/ ").base_url(),
+            "http://box:8080"
+        );
+        assert_eq!(
+            LocalProvider::new("http://box:8080/v1").api_base(),
+            "http://box:8080/v1"
+        );
+    }
+
+    #[test]
+    fn ollama_vision_models_are_detected() {
+        let llava = ollama_model(&json!({
+            "name": "llava:7b",
+            "details": { "families": ["llama", "clip"] }
+        }))
+        .unwrap();
+        assert_eq!(llava.id, "llava:7b");
+        assert!(llava.vision);
+
+        let llama = ollama_model(&json!({ "name": "llama3", "details": {} })).unwrap();
+        assert!(!llama.vision);
+        assert!(ollama_model(&json!({ "details": {} })).is_none());
+    }
+}
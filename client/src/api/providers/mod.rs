@@ -0,0 +1,504 @@
+use crate::api::http;
+use crate::files::Attachment;
+use crate::models::{
+    ApiResponse, ChatMessage, ModelInfo, Role, SendMessageRequest, ToolCall, Usage,
+};
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder, Response};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+pub use error::ProviderError;
+
+pub mod anthropic;
+pub mod custom;
+pub mod elevenlabs;
+pub mod error;
+pub mod google;
+pub mod local;
+pub mod openai;
+pub mod perplexity;
+pub mod retry;
+pub(crate) mod sse;
+pub mod xai;
+
+/// A piece of a streamed response.
+pub enum StreamChunk {
+    Text(String),
+    Reasoning(String),
+    Usage(Usage),
+}
+
+pub type ChunkSink<'a> = &'a (dyn Fn(StreamChunk) + Send + Sync);
+
+/// A model backend that `send_message` can dispatch to.
+///
+/// Each implementation owns its endpoints, request format and response
+/// parsing. Key lookup happens before any of these methods are called.
+#[async_trait]
+pub trait Provider: Send + Sync {
+    /// Identifier used by the frontend and as the key name in `config.json`.
+    fn id(&self) -> &str;
+
+    /// Human readable name used in error messages.
+    fn display_name(&self) -> &str;
+
+    /// Environment variable checked when no key has been stored.
+    fn env_var(&self) -> &str;
+
+    /// Whether requests should be refused until a key is configured.
+    fn requires_key(&self) -> bool {
+        true
+    }
+
+    /// Names from `GenerationParams::requested` this provider passes on for
+    /// `request`, which can depend on the model. Anything else the frontend
+    /// sets is reported back as ignored.
+    fn supported_params(&self, _request: &SendMessageRequest) -> &[&str] {
+        &[]
+    }
+
+    /// Whether requests may carry `tools` and responses `tool_calls`.
+    fn supports_tools(&self) -> bool {
+        false
+    }
+
+    /// Whether the API can be held to a `response_schema`. Other providers
+    /// are only asked for matching JSON in the system prompt.
+    fn supports_response_schema(&self) -> bool {
+        false
+    }
+
+    async fn complete(
+        &self,
+        client: &Client,
+        api_key: &str,
+        request: &SendMessageRequest,
+    ) -> Result<ApiResponse, ProviderError>;
+
+    /// Streams the response through `on_chunk` and returns the full result
+    /// once the stream ends. Providers without a streaming API emit the
+    /// whole answer as a single chunk.
+    async fn stream(
+        &self,
+        client: &Client,
+        api_key: &str,
+        request: &SendMessageRequest,
+        on_chunk: ChunkSink<'_>,
+    ) -> Result<ApiResponse, ProviderError> {
+        let response = self.complete(client, api_key, request).await?;
+        if let Some(reasoning) = &response.reasoning {
+            on_chunk(StreamChunk::Reasoning(reasoning.clone()));
+        }
+        if let Some(content) = &response.content {
+            on_chunk(StreamChunk::Text(content.clone()));
+        }
+        Ok(response)
+    }
+
+    async fn list_models(
+        &self,
+        client: &Client,
+        api_key: &str,
+    ) -> Result<Vec<ModelInfo>, ProviderError>;
+
+    async fn verify_key(&self, client: &Client, api_key: &str) -> Result<(), ProviderError>;
+}
+
+pub struct ProviderRegistry {
+    providers: RwLock<HashMap<String, Arc<dyn Provider>>>,
+    aliases: RwLock<HashMap<String, String>>,
+}
+
+impl ProviderRegistry {
+    pub fn new() -> Self {
+        Self {
+            providers: RwLock::new(HashMap::new()),
+            aliases: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_builtin() -> Self {
+        let registry = Self::new();
+        registry.register(Arc::new(anthropic::AnthropicProvider));
+        registry.register(Arc::new(openai::OpenAiProvider));
+        registry.register(Arc::new(perplexity::PerplexityProvider));
+        registry.register(Arc::new(google::GoogleProvider));
+        registry.register(Arc::new(xai::XaiProvider));
+        registry.register(Arc::new(elevenlabs::ElevenLabsProvider));
+        registry.register(Arc::new(local::LocalProvider::new(
+            local::DEFAULT_LOCAL_BASE_URL,
+        )));
+
+        // Older frontend builds still send these names
+        registry.alias("grok", "xai");
+        registry.alias("gemini", "google");
+        registry.alias("ollama", "local");
+        registry
+    }
+
+    pub fn register(&self, provider: Arc<dyn Provider>) {
+        if let Ok(mut providers) = self.providers.write() {
+            providers.insert(provider.id().to_string(), provider);
+        }
+    }
+
+    pub fn alias(&self, alias: &str, provider_id: &str) {
+        if let Ok(mut aliases) = self.aliases.write() {
+            aliases.insert(alias.to_string(), provider_id.to_string());
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<dyn Provider>> {
+        let id = self
+            .aliases
+            .read()
+            .ok()
+            .and_then(|aliases| aliases.get(id).cloned())
+            .unwrap_or_else(|| id.to_string());
+        self.providers.read().ok()?.get(&id).cloned()
+    }
+
+    pub fn ids(&self) -> Vec<String> {
+        self.providers
+            .read()
+            .map(|providers| providers.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+impl Default for ProviderRegistry {
+    fn default() -> Self {
+        Self::with_builtin()
+    }
+}
+
+/// Sends a request and parses the JSON body of a successful response.
+/// Failed responses are classified into a `ProviderError`.
+pub(crate) async fn send_json(request: RequestBuilder, name: &str) -> Result<Value, ProviderError> {
+    let response = send_stream(request, name).await?;
+    let response_text = http::read(response.text())
+        .await
+        .map_err(|timeout| ProviderError::timed_out(name, timeout))?
+        .map_err(|e| ProviderError::network(name, &e))?;
+
+    serde_json::from_str(&response_text).map_err(|e| {
+        ProviderError::bad_response(name, format!("Invalid JSON: {}", e), &response_text)
+    })
+}
+
+/// Sends a streaming request and returns the response once the status has
+/// been checked, leaving the body for the caller to read.
+pub(crate) async fn send_stream(
+    request: RequestBuilder,
+    name: &str,
+) -> Result<Response, ProviderError> {
+    let response = retry::send(request, name).await?;
+
+    let status = response.status();
+    if status.is_success() {
+        Ok(response)
+    } else {
+        let headers = response.headers().clone();
+        let response_text = response
+            .text()
+            .await
+            .map_err(|e| ProviderError::network(name, &e))?;
+        Err(ProviderError::from_response(
+            name,
+            status,
+            &headers,
+            &response_text,
+        ))
+    }
+}
+
+/// Same as `send_json` but only cares about whether the request succeeded.
+pub(crate) async fn send_check(request: RequestBuilder, name: &str) -> Result<(), ProviderError> {
+    send_stream(request, name).await.map(|_| ())
+}
+
+/// Reads an OpenAI style `{"data": [...]}` model list. Only ids are common
+/// to every server, so that is all this fills in.
+pub(crate) fn model_list(json: &Value, name: &str) -> Result<Vec<ModelInfo>, ProviderError> {
+    json["data"]
+        .as_array()
+        .map(|models| {
+            models
+                .iter()
+                .filter_map(|model| model["id"].as_str().map(ModelInfo::new))
+                .collect()
+        })
+        .ok_or_else(|| invalid_format(name, json))
+}
+
+pub(crate) fn invalid_format(name: &str, json: &Value) -> ProviderError {
+    ProviderError::bad_response(name, "Invalid response format", &json.to_string())
+}
+
+/// Pulls system turns out of a conversation for APIs that take the system
+/// prompt separately. Multiple system turns are joined with blank lines.
+pub(crate) fn split_system(messages: Vec<ChatMessage>) -> (Option<String>, Vec<ChatMessage>) {
+    let (system, turns): (Vec<_>, Vec<_>) = messages
+        .into_iter()
+        .partition(|message| message.role == Role::System);
+
+    let system = if system.is_empty() {
+        None
+    } else {
+        Some(
+            system
+                .into_iter()
+                .map(|message| message.content)
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+        )
+    };
+
+    (system, turns)
+}
+
+/// Joins back-to-back turns from the same role, for APIs that insist on
+/// strictly alternating user and assistant messages. Tool turns are left
+/// alone, see `merge_parts`.
+pub(crate) fn merge_consecutive(messages: Vec<ChatMessage>) -> Vec<ChatMessage> {
+    let mut merged: Vec<ChatMessage> = Vec::with_capacity(messages.len());
+    for message in messages {
+        match merged.last_mut() {
+            Some(last)
+                if last.role == message.role
+                    && !last.has_tool_data()
+                    && !message.has_tool_data() =>
+            {
+                last.content.push_str("\n\n");
+                last.content.push_str(&message.content);
+            }
+            _ => merged.push(message),
+        }
+    }
+    merged
+}
+
+/// Joins back-to-back wire messages from `role` whose `field` holds a list
+/// of parts, such as several tool results answering one assistant turn.
+pub(crate) fn merge_parts(messages: Value, role: &str, field: &str) -> Value {
+    let mut merged: Vec<Value> = Vec::new();
+    for message in messages.as_array().cloned().unwrap_or_default() {
+        if let Some(last) = merged.last_mut() {
+            if last["role"] == role && message["role"] == role {
+                if let (Some(parts), Some(more)) =
+                    (last[field].as_array_mut(), message[field].as_array())
+                {
+                    parts.extend(more.iter().cloned());
+                    continue;
+                }
+            }
+        }
+        merged.push(message);
+    }
+    Value::Array(merged)
+}
+
+/// A tool call being assembled from stream deltas.
+#[derive(Default)]
+pub(crate) struct PartialToolCall {
+    pub id: String,
+    pub name: String,
+    pub arguments: String,
+}
+
+impl PartialToolCall {
+    pub fn finish(self) -> ToolCall {
+        ToolCall {
+            id: self.id,
+            name: self.name,
+            arguments: tool_arguments(&self.arguments),
+        }
+    }
+}
+
+/// Parses arguments sent as a JSON string. Calls without arguments get an
+/// empty object, and invalid JSON is passed through for the tool to reject.
+pub(crate) fn tool_arguments(raw: &str) -> Value {
+    if raw.trim().is_empty() {
+        return json!({});
+    }
+    serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string()))
+}
+
+/// Adds `value` to a JSON object under `key` when it is set.
+pub(crate) fn insert_some<T: Serialize>(body: &mut Value, key: &str, value: &Option<T>) {
+    if let Some(value) = value {
+        body[key] = serde_json::to_value(value).unwrap_or_default();
+    }
+}
+
+// The latest user message is the one an attachment belongs to
+fn attachment_turn(turns: &[ChatMessage]) -> Option<usize> {
+    turns.iter().rposition(|turn| turn.role == Role::User)
+}
+
+/// Builds the wire form of each turn with `to_json`, using `with_file` for
+/// the turn the request's attachment belongs to. `with_file` returns `None`
+/// for files the provider can't take natively, which are then inlined ahead
+/// of the prompt as text when the file has any.
+pub(crate) fn turns_with_attachment(
+    mut turns: Vec<ChatMessage>,
+    attachment: Option<&Attachment>,
+    provider: &str,
+    to_json: impl Fn(&ChatMessage) -> Value,
+    with_file: impl Fn(&ChatMessage, &Attachment) -> Option<Value>,
+) -> Result<Value, ProviderError> {
+    let (file, index) = match (attachment, attachment_turn(&turns)) {
+        (Some(file), Some(index)) => (file, index),
+        _ => return Ok(turns.iter().map(to_json).collect()),
+    };
+
+    let native = match file {
+        Attachment::Binary { .. } => with_file(&turns[index], file),
+        Attachment::Text { .. } => None,
+    };
+    if native.is_none() {
+        let text = file
+            .to_text()
+            .map_err(|e| ProviderError::invalid_request(provider, e))?
+            .ok_or_else(|| unsupported_attachment(provider, file))?;
+        let turn = &mut turns[index];
+        turn.content = format!(
+            "File: {}\n```\n{}\n```\n\n{}",
+            file.name(),
+            text,
+            turn.content
+        );
+    }
+
+    Ok(turns
+        .iter()
+        .enumerate()
+        .map(|(position, turn)| match &native {
+            Some(value) if position == index => value.clone(),
+            _ => to_json(turn),
+        })
+        .collect())
+}
+
+pub(crate) fn unsupported_attachment(provider: &str, attachment: &Attachment) -> ProviderError {
+    ProviderError::invalid_request(
+        provider,
+        format!(
+            "{} can't read {} attachments ({})",
+            provider,
+            attachment.mime_type(),
+            attachment.name()
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_resolves_aliases() {
+        let registry = ProviderRegistry::with_builtin();
+        assert_eq!(registry.get("grok").unwrap().id(), "xai");
+        assert_eq!(registry.get("gemini").unwrap().id(), "google");
+        assert_eq!(registry.get("anthropic").unwrap().id(), "anthropic");
+        assert!(registry.get("nope").is_none());
+    }
+
+    #[test]
+    fn register_replaces_provider_with_same_id() {
+        let registry = ProviderRegistry::with_builtin();
+        let count = registry.ids().len();
+        registry.register(Arc::new(local::LocalProvider::new("http://localhost:8080")));
+        assert_eq!(registry.ids().len(), count);
+        assert_eq!(registry.get("ollama").unwrap().id(), "local");
+    }
+
+    #[test]
+    fn model_list_reads_ids() {
+        let json = json!({"data": [{"id": "a"}, {"object": "model"}, {"id": "b"}]});
+        let models = model_list(&json, "Test").unwrap();
+        let ids: Vec<_> = models.iter().map(|model| model.id.as_str()).collect();
+        assert_eq!(ids, ["a", "b"]);
+        assert!(model_list(&json!({"models": []}), "Test").is_err());
+    }
+
+    #[test]
+    fn split_system_joins_system_turns() {
+        let (system, turns) = split_system(vec![
+            ChatMessage::new(Role::System, "One"),
+            ChatMessage::user("Hi"),
+            ChatMessage::new(Role::System, "Two"),
+        ]);
+        assert_eq!(system.as_deref(), Some("One\n\nTwo"));
+        assert_eq!(turns.len(), 1);
+        assert!(split_system(vec![ChatMessage::user("Hi")]).0.is_none());
+    }
+
+    #[test]
+    fn merge_consecutive_joins_same_role_turns() {
+        let merged = merge_consecutive(vec![
+            ChatMessage::user("a"),
+            ChatMessage::user("b"),
+            ChatMessage::new(Role::Assistant, "c"),
+            ChatMessage::user("d"),
+        ]);
+        let contents: Vec<_> = merged
+            .iter()
+            .map(|message| message.content.as_str())
+            .collect();
+        assert_eq!(contents, ["a\n\nb", "c", "d"]);
+    }
+
+    fn user_json(turn: &ChatMessage) -> Value {
+        json!({ "role": turn.role, "content": turn.content })
+    }
+
+    #[test]
+    fn text_attachments_are_inlined_into_latest_user_turn() {
+        let turns = vec![
+            ChatMessage::user("first"),
+            ChatMessage::new(Role::Assistant, "ok"),
+            ChatMessage::user("summarise"),
+        ];
+        let file = Attachment::from_content("a.txt", "body");
+        let messages =
+            turns_with_attachment(turns, Some(&file), "Test", user_json, |_, _| None).unwrap();
+        assert_eq!(messages[0]["content"], "first");
+        assert_eq!(
+            messages[2]["content"],
+            "File: a.txt\n```\nbody\n```\n\nsummarise"
+        );
+    }
+
+    #[test]
+    fn native_attachments_replace_their_turn() {
+        let file = Attachment::from_content("a.png", "data:image/png;base64,AAAA");
+        let messages = turns_with_attachment(
+            vec![ChatMessage::user("look")],
+            Some(&file),
+            "Test",
+            user_json,
+            |_, file| Some(json!({ "image": file.name() })),
+        )
+        .unwrap();
+        assert_eq!(messages, json!([{ "image": "a.png" }]));
+    }
+
+    #[test]
+    fn unreadable_attachments_are_refused() {
+        let file = Attachment::from_content("a.png", "data:image/png;base64,AAAA");
+        let result = turns_with_attachment(
+            vec![ChatMessage::user("look")],
+            Some(&file),
+            "Test",
+            user_json,
+            |_, _| None,
+        );
+        assert!(matches!(result, Err(ProviderError::InvalidRequest { .. })));
+    }
+}
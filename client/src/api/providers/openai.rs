@@ -0,0 +1,586 @@
+use super::sse::read_events;
+use super::{
+    insert_some, invalid_format, model_list, send_check, send_json, send_stream, tool_arguments,
+    turns_with_attachment, ChunkSink, PartialToolCall, Provider, ProviderError, StreamChunk,
+};
+use crate::files::Attachment;
+use crate::models::{
+    ApiResponse, ChatMessage, ModelInfo, Role, SendMessageRequest, ToolCall, ToolSpec, Usage,
+};
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder, Response};
+use serde_json::{json, Value};
+
+pub const OPENAI_API_BASE: &str = "https://api.openai.com/v1";
+
+pub struct OpenAiProvider;
+
+pub(crate) fn chat_request(
+    client: &Client,
+    base_url: &str,
+    api_key: &str,
+    body: &Value,
+) -> RequestBuilder {
+    client
+        .post(format!("{}/chat/completions", base_url))
+        .header("Authorization", format!("Bearer {}", api_key))
+        .header("Content-Type", "application/json")
+        .json(body)
+}
+
+/// Posts a body to an OpenAI compatible `/chat/completions` endpoint.
+/// Perplexity and xAI share this wire format.
+pub(crate) async fn chat_completion(
+    client: &Client,
+    base_url: &str,
+    api_key: &str,
+    body: &Value,
+    name: &str,
+) -> Result<Value, ProviderError> {
+    send_json(chat_request(client, base_url, api_key, body), name).await
+}
+
+/// Streaming counterpart of `chat_completion`. The body must already have
+/// `"stream": true` set.
+pub(crate) async fn chat_completion_stream(
+    client: &Client,
+    base_url: &str,
+    api_key: &str,
+    body: &Value,
+    name: &str,
+    on_chunk: ChunkSink<'_>,
+) -> Result<ApiResponse, ProviderError> {
+    let response = send_stream(chat_request(client, base_url, api_key, body), name).await?;
+    read_stream(response, name, on_chunk).await
+}
+
+/// Reads an OpenAI style chat completion event stream.
+pub(crate) async fn read_stream(
+    response: Response,
+    name: &str,
+    on_chunk: ChunkSink<'_>,
+) -> Result<ApiResponse, ProviderError> {
+    read_stream_with(response, name, on_chunk, |_| {}).await
+}
+
+/// Same as `read_stream`, also handing every parsed event to `on_event` for
+/// servers that add their own fields to the chunks.
+pub(crate) async fn read_stream_with(
+    response: Response,
+    name: &str,
+    on_chunk: ChunkSink<'_>,
+    mut on_event: impl FnMut(&Value),
+) -> Result<ApiResponse, ProviderError> {
+    let mut content = String::new();
+    let mut reasoning = String::new();
+    let mut usage = None;
+    let mut calls: Vec<PartialToolCall> = Vec::new();
+
+    read_events(response, name, |event| {
+        if event.data == "[DONE]" {
+            return Ok(false);
+        }
+
+        let json = parse_event(&event.data, name)?;
+        if json.get("error").is_some() {
+            return Err(ProviderError::from_event(name, &json));
+        }
+        if json["choices"][0]["finish_reason"] == "content_filter" {
+            return Err(content_filtered(name, &json));
+        }
+        on_event(&json);
+
+        // DeepSeek style servers and Grok stream their reasoning first
+        if let Some(text) = json["choices"][0]["delta"]["reasoning_content"].as_str() {
+            if !text.is_empty() {
+                reasoning.push_str(text);
+                on_chunk(StreamChunk::Reasoning(text.to_string()));
+            }
+        }
+        if let Some(text) = json["choices"][0]["delta"]["content"].as_str() {
+            if !text.is_empty() {
+                content.push_str(text);
+                on_chunk(StreamChunk::Text(text.to_string()));
+            }
+        }
+
+        // Calls arrive in pieces, keyed by their position in the list
+        if let Some(deltas) = json["choices"][0]["delta"]["tool_calls"].as_array() {
+            for delta in deltas {
+                let index = delta["index"].as_u64().unwrap_or(0) as usize;
+                if calls.len() <= index {
+                    calls.resize_with(index + 1, PartialToolCall::default);
+                }
+                let call = &mut calls[index];
+                if let Some(id) = delta["id"].as_str() {
+                    call.id = id.to_string();
+                }
+                if let Some(name) = delta["function"]["name"].as_str() {
+                    call.name.push_str(name);
+                }
+                if let Some(arguments) = delta["function"]["arguments"].as_str() {
+                    call.arguments.push_str(arguments);
+                }
+            }
+        }
+
+        // Some servers repeat usage on every chunk, only the last one counts
+        if json["usage"].is_object() {
+            usage = Some(parse_usage(&json["usage"]));
+        }
+
+        Ok(true)
+    })
+    .await?;
+
+    if let Some(usage) = &usage {
+        on_chunk(StreamChunk::Usage(usage.clone()));
+    }
+
+    Ok(ApiResponse::text(content)
+        .with_reasoning(reasoning)
+        .with_usage(usage)
+        .with_tool_calls(calls.into_iter().map(PartialToolCall::finish).collect()))
+}
+
+pub(crate) fn parse_event(data: &str, name: &str) -> Result<Value, ProviderError> {
+    serde_json::from_str(data).map_err(|e| {
+        ProviderError::bad_response(name, format!("Failed to parse stream event: {}", e), data)
+    })
+}
+
+fn content_filtered(name: &str, json: &Value) -> ProviderError {
+    ProviderError::content_filtered(
+        name,
+        "The response was stopped by the content filter",
+        &json.to_string(),
+    )
+}
+
+/// Text of the first choice. Refusals count as the answer, a missing
+/// message means the response isn't in the shape we expect. Turns that only
+/// call tools have no text.
+fn message_content(json: &Value, name: &str) -> Result<String, ProviderError> {
+    let choice = &json["choices"][0];
+    if choice["finish_reason"] == "content_filter" {
+        return Err(content_filtered(name, json));
+    }
+
+    let message = &choice["message"];
+    message["content"]
+        .as_str()
+        .or_else(|| message["refusal"].as_str())
+        .or_else(|| message["tool_calls"].is_array().then_some(""))
+        .map(String::from)
+        .ok_or_else(|| invalid_format(name, json))
+}
+
+fn message_tool_calls(message: &Value) -> Vec<ToolCall> {
+    message["tool_calls"]
+        .as_array()
+        .map(|calls| {
+            calls
+                .iter()
+                .map(|call| ToolCall {
+                    id: call["id"].as_str().unwrap_or_default().to_string(),
+                    name: call["function"]["name"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                    arguments: tool_arguments(
+                        call["function"]["arguments"].as_str().unwrap_or_default(),
+                    ),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Text, reasoning, tool calls and usage of a non-streaming chat
+/// completion.
+pub(crate) fn parse_response(json: &Value, name: &str) -> Result<ApiResponse, ProviderError> {
+    let usage = json["usage"]
+        .is_object()
+        .then(|| parse_usage(&json["usage"]));
+    let message = &json["choices"][0]["message"];
+    let reasoning = message["reasoning_content"].as_str().unwrap_or_default();
+    Ok(ApiResponse::text(message_content(json, name)?)
+        .with_reasoning(reasoning.to_string())
+        .with_usage(usage)
+        .with_tool_calls(message_tool_calls(message)))
+}
+
+/// Whether the model reasons before answering. These take a
+/// `reasoning_effort` and reject sampling settings.
+pub(crate) fn is_reasoning_model(model: &str) -> bool {
+    ["o1", "o3", "o4", "gpt-5"]
+        .iter()
+        .any(|family| model.starts_with(family))
+        && !model.starts_with("gpt-5-chat")
+}
+
+/// The `tools` array for a request.
+pub(crate) fn tools_json(tools: &[ToolSpec]) -> Value {
+    tools
+        .iter()
+        .map(|tool| {
+            json!({
+                "type": "function",
+                "function": {
+                    "name": tool.name,
+                    "description": tool.description,
+                    "parameters": tool.parameters
+                }
+            })
+        })
+        .collect()
+}
+
+/// Adds the request's tools to a body, if it has any.
+pub(crate) fn insert_tools(body: &mut Value, tools: &[ToolSpec]) {
+    if !tools.is_empty() {
+        body["tools"] = tools_json(tools);
+    }
+}
+
+/// Adds the request's `response_schema` as a JSON Schema `response_format`.
+/// Strict mode is left off since it rejects many ordinary schemas, and the
+/// answer is validated afterwards anyway.
+pub(crate) fn insert_response_format(body: &mut Value, request: &SendMessageRequest) {
+    if let Some(schema) = &request.response_schema {
+        body["response_format"] = json!({
+            "type": "json_schema",
+            "json_schema": {
+                "name": "response",
+                "schema": schema,
+                "strict": false
+            }
+        });
+    }
+}
+
+// Tool calls and results have their own message shapes
+fn message_json(turn: &ChatMessage) -> Value {
+    if let Some(result) = &turn.tool_result {
+        return json!({
+            "role": "tool",
+            "tool_call_id": result.call_id,
+            "content": result.content
+        });
+    }
+    if turn.tool_calls.is_empty() {
+        return json!({ "role": turn.role, "content": turn.content });
+    }
+
+    let calls: Vec<Value> = turn
+        .tool_calls
+        .iter()
+        .map(|call| {
+            json!({
+                "id": call.id,
+                "type": "function",
+                "function": {
+                    "name": call.name,
+                    "arguments": call.arguments.to_string()
+                }
+            })
+        })
+        .collect();
+    json!({
+        "role": Role::Assistant,
+        "content": if turn.content.is_empty() { Value::Null } else { json!(turn.content) },
+        "tool_calls": calls
+    })
+}
+
+pub(crate) fn parse_usage(usage: &Value) -> Usage {
+    Usage {
+        input_tokens: usage["prompt_tokens"].as_u64(),
+        output_tokens: usage["completion_tokens"].as_u64(),
+        cached_tokens: usage["prompt_tokens_details"]["cached_tokens"].as_u64(),
+        cache_write_tokens: None,
+        reasoning_tokens: usage["completion_tokens_details"]["reasoning_tokens"].as_u64(),
+    }
+}
+
+/// Builds an OpenAI style `messages` array. Images go in as `image_url`
+/// parts everywhere, PDFs as `file` parts only where `documents` is set and
+/// as extracted text otherwise.
+pub(crate) fn chat_messages(
+    turns: Vec<ChatMessage>,
+    attachment: Option<&Attachment>,
+    name: &str,
+    documents: bool,
+) -> Result<Value, ProviderError> {
+    turns_with_attachment(turns, attachment, name, message_json, |turn, file| {
+        let data_url = file.data_url()?;
+        let part = if file.is_image() {
+            json!({
+                "type": "image_url",
+                "image_url": { "url": data_url }
+            })
+        } else if file.is_pdf() && documents {
+            json!({
+                "type": "file",
+                "file": {
+                    "filename": file.name(),
+                    "file_data": data_url
+                }
+            })
+        } else {
+            return None;
+        };
+
+        Some(json!({
+            "role": turn.role,
+            "content": [part, { "type": "text", "text": turn.content }]
+        }))
+    })
+}
+
+/// Request body for servers that copy the OpenAI chat API without its newer
+/// additions, such as local models and OpenAI compatible gateways.
+pub(crate) fn compat_request_body(
+    request: &SendMessageRequest,
+    name: &str,
+    stream: bool,
+) -> Result<Value, ProviderError> {
+    let params = &request.params;
+    let messages = chat_messages(
+        request.conversation(),
+        request.attachment().as_ref(),
+        name,
+        false,
+    )?;
+    let mut body = json!({
+        "model": request.model,
+        "messages": messages,
+        "stream": stream
+    });
+
+    insert_some(&mut body, "temperature", &params.temperature);
+    insert_some(&mut body, "top_p", &params.top_p);
+    insert_some(&mut body, "max_tokens", &params.max_tokens);
+    insert_some(&mut body, "stop", &params.stop);
+    insert_some(&mut body, "seed", &params.seed);
+    insert_tools(&mut body, &request.tools);
+
+    if stream {
+        body["stream_options"] = json!({ "include_usage": true });
+    }
+
+    Ok(body)
+}
+
+fn request_body(request: &SendMessageRequest, stream: bool) -> Result<Value, ProviderError> {
+    let params = &request.params;
+    let messages = chat_messages(
+        request.conversation(),
+        request.attachment().as_ref(),
+        "OpenAI",
+        true,
+    )?;
+    let mut body = json!({
+        "model": request.model,
+        "messages": messages,
+        "stream": stream
+    });
+
+    if is_reasoning_model(&request.model) {
+        insert_some(&mut body, "reasoning_effort", &params.reasoning_effort);
+    } else {
+        insert_some(&mut body, "temperature", &params.temperature);
+        insert_some(&mut body, "top_p", &params.top_p);
+    }
+    insert_some(&mut body, "max_completion_tokens", &params.max_tokens);
+    insert_some(&mut body, "stop", &params.stop);
+    insert_some(&mut body, "seed", &params.seed);
+    insert_tools(&mut body, &request.tools);
+    insert_response_format(&mut body, request);
+
+    if stream {
+        body["stream_options"] = json!({ "include_usage": true });
+    }
+
+    Ok(body)
+}
+
+// `/models` only returns ids, so input support is read off the model family
+fn with_capabilities(model: &mut ModelInfo) {
+    let id = model.id.as_str();
+    let multimodal = ["gpt-4o", "gpt-4.1", "gpt-4.5", "gpt-5", "o1", "o3", "o4"]
+        .iter()
+        .any(|family| id.starts_with(family))
+        && !id.starts_with("o1-mini")
+        && !id.starts_with("o3-mini");
+
+    model.vision = multimodal;
+    model.documents = multimodal;
+    model.audio = id.contains("audio") || id.contains("realtime");
+}
+
+#[async_trait]
+impl Provider for OpenAiProvider {
+    fn id(&self) -> &str {
+        "openai"
+    }
+
+    fn display_name(&self) -> &str {
+        "OpenAI"
+    }
+
+    fn env_var(&self) -> &str {
+        "OPENAI_API_KEY"
+    }
+
+    fn supported_params(&self, request: &SendMessageRequest) -> &[&str] {
+        if is_reasoning_model(&request.model) {
+            &["max_tokens", "stop", "seed", "reasoning_effort"]
+        } else {
+            &["temperature", "top_p", "max_tokens", "stop", "seed"]
+        }
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    fn supports_response_schema(&self) -> bool {
+        true
+    }
+
+    async fn complete(
+        &self,
+        client: &Client,
+        api_key: &str,
+        request: &SendMessageRequest,
+    ) -> Result<ApiResponse, ProviderError> {
+        println!("Preparing request with model: {}", request.model);
+        let json = chat_completion(
+            client,
+            OPENAI_API_BASE,
+            api_key,
+            &request_body(request, false)?,
+            self.display_name(),
+        )
+        .await?;
+
+        parse_response(&json, self.display_name())
+    }
+
+    async fn stream(
+        &self,
+        client: &Client,
+        api_key: &str,
+        request: &SendMessageRequest,
+        on_chunk: ChunkSink<'_>,
+    ) -> Result<ApiResponse, ProviderError> {
+        chat_completion_stream(
+            client,
+            OPENAI_API_BASE,
+            api_key,
+            &request_body(request, true)?,
+            self.display_name(),
+            on_chunk,
+        )
+        .await
+    }
+
+    async fn list_models(
+        &self,
+        client: &Client,
+        api_key: &str,
+    ) -> Result<Vec<ModelInfo>, ProviderError> {
+        let request = client
+            .get(format!("{}/models", OPENAI_API_BASE))
+            .header("Authorization", format!("Bearer {}", api_key));
+
+        let json = send_json(request, self.display_name()).await?;
+        let mut models = model_list(&json, self.display_name())?;
+        for model in &mut models {
+            with_capabilities(model);
+        }
+        Ok(models)
+    }
+
+    async fn verify_key(&self, client: &Client, api_key: &str) -> Result<(), ProviderError> {
+        let request = client
+            .get(format!("{}/models", OPENAI_API_BASE))
+            .header("Authorization", format!("Bearer {}", api_key));
+
+        send_check(request, self.display_name()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capabilities_follow_the_model_family() {
+        let capabilities = |id: &str| {
+            let mut model = ModelInfo::new(id);
+            with_capabilities(&mut model);
+            (model.vision, model.audio)
+        };
+        assert_eq!(capabilities("gpt-4o-2024-08-06"), (true, false));
+        assert_eq!(capabilities("gpt-4o-audio-preview"), (true, true));
+        assert_eq!(capabilities("o3-mini"), (false, false));
+        assert_eq!(capabilities("gpt-3.5-turbo"), (false, false));
+    }
+
+    #[test]
+    fn parse_response_reads_first_choice() {
+        let json = json!({
+            "choices": [{"message": {"content": "Hi"}, "finish_reason": "stop"}],
+            "usage": {"prompt_tokens": 3, "completion_tokens": 1}
+        });
+        let response = parse_response(&json, "OpenAI").unwrap();
+        assert_eq!(response.content.as_deref(), Some("Hi"));
+        let usage = response.usage.unwrap();
+        assert_eq!(usage.input_tokens, Some(3));
+        assert_eq!(usage.output_tokens, Some(1));
+    }
+
+    #[test]
+    fn parse_response_accepts_refusals() {
+        let json = json!({"choices": [{"message": {"content": null, "refusal": "No"}}]});
+        let response = parse_response(&json, "OpenAI").unwrap();
+        assert_eq!(response.content.as_deref(), Some("No"));
+    }
+
+    #[test]
+    fn parse_response_rejects_filtered_and_malformed() {
+        let filtered = json!({"choices": [{"message": {}, "finish_reason": "content_filter"}]});
+        assert!(matches!(
+            parse_response(&filtered, "OpenAI"),
+            Err(ProviderError::ContentFiltered { .. })
+        ));
+        assert!(matches!(
+            parse_response(&json!({"error": "?"}), "OpenAI"),
+            Err(ProviderError::BadResponse { .. })
+        ));
+    }
+
+    #[test]
+    fn temperature_is_only_sent_when_set() {
+        let mut request: SendMessageRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "provider": "openai",
+            "message": "Hi"
+        }))
+        .unwrap();
+        assert!(request_body(&request, false)
+            .unwrap()
+            .get("temperature")
+            .is_none());
+
+        request.params.temperature = Some(0.2);
+        assert_eq!(request_body(&request, false).unwrap()["temperature"], 0.2);
+        request.model = "o3".to_string();
+        assert!(request_body(&request, false)
+            .unwrap()
+            .get("temperature")
+            .is_none());
+    }
+}
@@ -0,0 +1,283 @@
+use super::openai::{
+    chat_completion, chat_messages, chat_request, parse_response, read_stream_with,
+};
+use super::{
+    insert_some, merge_consecutive, send_check, send_stream, ChunkSink, Provider, ProviderError,
+};
+use crate::models::{
+    ApiResponse, Citation, ModelInfo, PerplexityImage, PerplexitySources, SendMessageRequest,
+};
+use async_trait::async_trait;
+use reqwest::Client;
+use serde_json::{json, Value};
+
+const PERPLEXITY_API_BASE: &str = "https://api.perplexity.ai";
+
+// Perplexity has no endpoint for listing models
+const PERPLEXITY_MODELS: &[(&str, &str)] = &[
+    ("sonar", "Sonar"),
+    ("sonar-pro", "Sonar Pro"),
+    (
+        "llama-3.1-sonar-small-128k-online",
+        "Sonar Small Online (8B)",
+    ),
+    (
+        "llama-3.1-sonar-large-128k-online",
+        "Sonar Large Online (70B)",
+    ),
+    (
+        "llama-3.1-sonar-huge-128k-online",
+        "Sonar Huge Online (405B)",
+    ),
+    ("llama-3.1-sonar-small-128k-chat", "Sonar Small Chat (8B)"),
+    ("llama-3.1-sonar-large-128k-chat", "Sonar Large Chat (70B)"),
+    ("llama-3.1-8b-instruct", "Llama 3.1 8B Instruct"),
+    ("llama-3.1-70b-instruct", "Llama 3.1 70B Instruct"),
+];
+const CONTEXT_WINDOW: u64 = 127_072;
+
+pub struct PerplexityProvider;
+
+fn request_body(request: &SendMessageRequest, stream: bool) -> Result<Value, ProviderError> {
+    let params = &request.params;
+    let messages = chat_messages(
+        merge_consecutive(request.conversation()),
+        request.attachment().as_ref(),
+        "Perplexity",
+        false,
+    )?;
+    let mut body = json!({
+        "model": request.model,
+        "messages": messages,
+        "stream": stream
+    });
+
+    insert_some(&mut body, "temperature", &params.temperature);
+    insert_some(&mut body, "top_p", &params.top_p);
+    insert_some(&mut body, "max_tokens", &params.max_tokens);
+    insert_some(
+        &mut body,
+        "search_recency_filter",
+        &params.search_recency_filter,
+    );
+    insert_some(&mut body, "return_images", &params.return_images);
+    insert_some(
+        &mut body,
+        "return_related_questions",
+        &params.return_related_questions,
+    );
+    if let Some(schema) = &request.response_schema {
+        body["response_format"] = json!({
+            "type": "json_schema",
+            "json_schema": { "schema": schema }
+        });
+    }
+    Ok(body)
+}
+
+fn has_sources(json: &Value) -> bool {
+    ["citations", "search_results", "images", "related_questions"]
+        .iter()
+        .any(|key| json.get(key).is_some())
+}
+
+/// Copies citations, images and related questions from a Perplexity
+/// response onto `response`. Citations are numbered to match the `[n]`
+/// markers in the text.
+fn with_sources(mut response: ApiResponse, json: &Value) -> ApiResponse {
+    let sources: PerplexitySources = serde_json::from_value(json.clone()).unwrap_or_default();
+
+    let title_for = |url: &str| {
+        sources
+            .search_results
+            .iter()
+            .find(|result| result.url == url)
+            .and_then(|result| result.title.clone())
+    };
+
+    // `citations` defines the marker order, `search_results` only adds titles
+    let citations: Vec<Citation> = if sources.citations.is_empty() {
+        sources
+            .search_results
+            .iter()
+            .enumerate()
+            .map(|(i, result)| Citation {
+                index: i + 1,
+                url: result.url.clone(),
+                title: result.title.clone(),
+            })
+            .collect()
+    } else {
+        sources
+            .citations
+            .iter()
+            .enumerate()
+            .map(|(i, url)| Citation {
+                index: i + 1,
+                url: url.clone(),
+                title: title_for(url),
+            })
+            .collect()
+    };
+
+    let images: Vec<String> = sources
+        .images
+        .into_iter()
+        .map(|image| match image {
+            PerplexityImage::Url(url) => url,
+            PerplexityImage::Detailed { image_url } => image_url,
+        })
+        .collect();
+
+    response.citations = Some(citations).filter(|list| !list.is_empty());
+    response.images = Some(images).filter(|list| !list.is_empty());
+    response.related_questions = Some(sources.related_questions).filter(|list| !list.is_empty());
+    response
+}
+
+#[async_trait]
+impl Provider for PerplexityProvider {
+    fn id(&self) -> &str {
+        "perplexity"
+    }
+
+    fn display_name(&self) -> &str {
+        "Perplexity"
+    }
+
+    fn env_var(&self) -> &str {
+        "PERPLEXITY_API_KEY"
+    }
+
+    fn supported_params(&self, _request: &SendMessageRequest) -> &[&str] {
+        &[
+            "temperature",
+            "top_p",
+            "max_tokens",
+            "search_recency_filter",
+            "return_images",
+            "return_related_questions",
+        ]
+    }
+
+    fn supports_response_schema(&self) -> bool {
+        true
+    }
+
+    async fn complete(
+        &self,
+        client: &Client,
+        api_key: &str,
+        request: &SendMessageRequest,
+    ) -> Result<ApiResponse, ProviderError> {
+        let json = chat_completion(
+            client,
+            PERPLEXITY_API_BASE,
+            api_key,
+            &request_body(request, false)?,
+            self.display_name(),
+        )
+        .await?;
+
+        Ok(with_sources(
+            parse_response(&json, self.display_name())?,
+            &json,
+        ))
+    }
+
+    async fn stream(
+        &self,
+        client: &Client,
+        api_key: &str,
+        request: &SendMessageRequest,
+        on_chunk: ChunkSink<'_>,
+    ) -> Result<ApiResponse, ProviderError> {
+        let body = request_body(request, true)?;
+        let http_request = chat_request(client, PERPLEXITY_API_BASE, api_key, &body);
+        let response = send_stream(http_request, self.display_name()).await?;
+
+        let mut sources = Value::Null;
+        let response = read_stream_with(response, self.display_name(), on_chunk, |json| {
+            if has_sources(json) {
+                sources = json.clone();
+            }
+        })
+        .await?;
+
+        Ok(with_sources(response, &sources))
+    }
+
+    async fn list_models(
+        &self,
+        _client: &Client,
+        _api_key: &str,
+    ) -> Result<Vec<ModelInfo>, ProviderError> {
+        Ok(PERPLEXITY_MODELS
+            .iter()
+            .map(|(id, name)| {
+                let mut info = ModelInfo::new(*id);
+                info.display_name = name.to_string();
+                info.context_window = Some(CONTEXT_WINDOW);
+                info
+            })
+            .collect())
+    }
+
+    async fn verify_key(&self, client: &Client, api_key: &str) -> Result<(), ProviderError> {
+        let request = client
+            .post(format!("{}/chat/completions", PERPLEXITY_API_BASE))
+            .header("Authorization", format!("Bearer {}", api_key))
+            .json(&json!({
+                "model": PERPLEXITY_MODELS[0].0,
+                "messages": [{
+                    "role": "user",
+                    "content": "Test message for API key verification"
+                }],
+                "max_tokens": 1
+            }));
+
+        send_check(request, self.display_name()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn citations_keep_the_marker_order() {
+        let json = json!({
+            "citations": ["https://b.example", "https://a.example"],
+            "search_results": [
+                { "url": "https://a.example", "title": "A" },
+                { "url": "https://b.example" }
+            ],
+            "images": ["https://img.example/1.png", { "image_url": "https://img.example/2.png" }],
+            "related_questions": ["Why?"]
+        });
+        assert!(has_sources(&json));
+
+        let response = with_sources(ApiResponse::default(), &json);
+        let citations = response.citations.unwrap();
+        assert_eq!(citations[0].index, 1);
+        assert_eq!(citations[0].url, "https://b.example");
+        assert_eq!(citations[0].title, None);
+        assert_eq!(citations[1].title.as_deref(), Some("A"));
+        assert_eq!(
+            response.images.unwrap(),
+            ["https://img.example/1.png", "https://img.example/2.png"]
+        );
+        assert_eq!(response.related_questions.unwrap(), ["Why?"]);
+    }
+
+    #[test]
+    fn search_results_stand_in_for_missing_citations() {
+        let json = json!({ "search_results": [{ "url": "https://a.example", "title": "A" }] });
+        let response = with_sources(ApiResponse::default(), &json);
+        let citations = response.citations.unwrap();
+        assert_eq!(citations.len(), 1);
+        assert_eq!(citations[0].title.as_deref(), Some("A"));
+        assert!(response.images.is_none());
+        assert!(!has_sources(&json!({ "choices": [] })));
+    }
+}
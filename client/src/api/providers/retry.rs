@@ -0,0 +1,290 @@
+use super::ProviderError;
+use crate::api::http;
+use rand::Rng;
+use reqwest::header::HeaderMap;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MAX_ATTEMPTS: u32 = 4;
+const BASE_DELAY: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+// Servers asking for a longer wait get their error passed on instead
+const MAX_SERVER_DELAY: Duration = Duration::from_secs(60);
+
+/// Sent to the current listener before each retry.
+pub struct RetryNotice {
+    /// The attempt about to be made, starting at 2.
+    pub attempt: u32,
+    pub max_attempts: u32,
+    pub delay: Duration,
+    pub reason: String,
+}
+
+pub type RetryListener = Arc<dyn Fn(RetryNotice) + Send + Sync>;
+
+tokio::task_local! {
+    static LISTENER: RetryListener;
+}
+
+/// Runs `future` with `listener` told about every retry made inside it, so
+/// providers don't need to pass progress callbacks around.
+pub async fn with_listener<F: Future>(listener: RetryListener, future: F) -> F::Output {
+    LISTENER.scope(listener, future).await
+}
+
+/// Sends a request, retrying rate limits, server errors (including
+/// Anthropic's 529 "overloaded") and connections that couldn't be opened.
+/// Waits follow `Retry-After` or `x-ratelimit-reset-*` when the server sends
+/// them and exponential backoff with jitter otherwise. The last response is
+/// returned as is once attempts run out. A request that timed out or lost
+/// its connection after being sent isn't retried, since the server may
+/// already be working on it and billing for it.
+pub(crate) async fn send(request: RequestBuilder, name: &str) -> Result<Response, ProviderError> {
+    let mut attempt = 1;
+
+    loop {
+        // Requests with a streamed body can't be cloned and get a single attempt
+        let current = match request.try_clone() {
+            Some(current) => current,
+            None => {
+                return http::read(request.send())
+                    .await
+                    .map_err(|timeout| ProviderError::timed_out(name, timeout))?
+                    .map_err(|e| ProviderError::network(name, &e))
+            }
+        };
+
+        let (reason, delay) = match http::read(current.send()).await {
+            Ok(Ok(response)) if is_retryable_status(response.status()) => {
+                let server_delay = server_delay(response.headers());
+                if attempt >= MAX_ATTEMPTS
+                    || server_delay.map_or(false, |delay| delay > MAX_SERVER_DELAY)
+                {
+                    return Ok(response);
+                }
+                (
+                    format!("HTTP {}", response.status()),
+                    server_delay.unwrap_or_else(|| backoff(attempt)),
+                )
+            }
+            Ok(Ok(response)) => return Ok(response),
+            Ok(Err(e)) if e.is_connect() && attempt < MAX_ATTEMPTS => {
+                (e.to_string(), backoff(attempt))
+            }
+            Ok(Err(e)) => return Err(ProviderError::network(name, &e)),
+            Err(timeout) => return Err(ProviderError::timed_out(name, timeout)),
+        };
+
+        attempt += 1;
+        println!(
+            "Retrying in {:?} after {} (attempt {}/{})",
+            delay, reason, attempt, MAX_ATTEMPTS
+        );
+        let _ = LISTENER.try_with(|listener| {
+            listener(RetryNotice {
+                attempt,
+                max_attempts: MAX_ATTEMPTS,
+                delay,
+                reason,
+            })
+        });
+
+        tokio::time::sleep(delay).await;
+    }
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+// Somewhere between half and all of the exponential delay
+fn backoff(attempt: u32) -> Duration {
+    let delay = BASE_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt - 1))
+        .min(MAX_BACKOFF);
+    delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+}
+
+pub(crate) fn server_delay(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+
+    if let Some(delay) = header("retry-after").and_then(parse_delay) {
+        return Some(delay);
+    }
+
+    // OpenAI style limits: wait for whichever budget ran out
+    let exhausted = ["requests", "tokens"]
+        .iter()
+        .filter(|kind| header(&format!("x-ratelimit-remaining-{}", kind)) == Some("0"))
+        .filter_map(|kind| header(&format!("x-ratelimit-reset-{}", kind)))
+        .filter_map(parse_delay)
+        .max();
+
+    exhausted.or_else(|| header("x-ratelimit-reset").and_then(parse_delay))
+}
+
+/// Reads a delay given as seconds, a unix timestamp, or a Go style duration
+/// such as `1m30s` or `250ms`.
+fn parse_delay(value: &str) -> Option<Duration> {
+    let value = value.trim();
+
+    if let Ok(number) = value.parse::<f64>() {
+        let seconds = if number > 1_000_000_000.0 {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+            number - now.as_secs_f64()
+        } else {
+            number
+        };
+        return Some(Duration::from_secs_f64(seconds.max(0.0)));
+    }
+
+    let mut seconds = 0.0;
+    let mut number = String::new();
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_ascii_digit() || c == '.' {
+            number.push(c);
+            continue;
+        }
+
+        let amount: f64 = number.parse().ok()?;
+        number.clear();
+        let unit = match c {
+            'h' => 3600.0,
+            'm' if chars.peek() == Some(&'s') => {
+                chars.next();
+                0.001
+            }
+            'm' => 60.0,
+            's' => 1.0,
+            _ => return None,
+        };
+        seconds += amount * unit;
+    }
+
+    if number.is_empty() {
+        Some(Duration::from_secs_f64(seconds))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn parses_seconds_and_go_durations() {
+        assert_eq!(parse_delay("2"), Some(Duration::from_secs(2)));
+        assert_eq!(parse_delay(" 0.5 "), Some(Duration::from_millis(500)));
+        assert_eq!(parse_delay("1m30s"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_delay("250ms"), Some(Duration::from_millis(250)));
+        assert_eq!(parse_delay("1h"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_delay("-3"), Some(Duration::ZERO));
+        assert_eq!(parse_delay("Wed, 21 Oct 2015 07:28:00 GMT"), None);
+        assert_eq!(parse_delay("5x"), None);
+        assert_eq!(parse_delay("12"), Some(Duration::from_secs(12)));
+    }
+
+    #[test]
+    fn parses_unix_timestamps_as_time_left() {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let delay = parse_delay(&(now.as_secs() + 30).to_string()).unwrap();
+        assert!(delay > Duration::from_secs(28) && delay <= Duration::from_secs(30));
+        assert_eq!(parse_delay("1000000001"), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn server_delay_waits_for_the_exhausted_limit() {
+        let headers = |pairs: &[(&'static str, &'static str)]| {
+            let mut headers = HeaderMap::new();
+            for (name, value) in pairs {
+                headers.insert(*name, HeaderValue::from_static(value));
+            }
+            headers
+        };
+
+        assert_eq!(
+            server_delay(&headers(&[
+                ("retry-after", "3"),
+                ("x-ratelimit-reset", "9")
+            ])),
+            Some(Duration::from_secs(3))
+        );
+        assert_eq!(
+            server_delay(&headers(&[
+                ("x-ratelimit-remaining-requests", "5"),
+                ("x-ratelimit-reset-requests", "1s"),
+                ("x-ratelimit-remaining-tokens", "0"),
+                ("x-ratelimit-reset-tokens", "6m0s"),
+            ])),
+            Some(Duration::from_secs(360))
+        );
+        assert_eq!(
+            server_delay(&headers(&[("x-ratelimit-reset", "250ms")])),
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(
+            server_delay(&headers(&[("x-ratelimit-remaining-requests", "0")])),
+            None
+        );
+    }
+
+    // Serves the responses in order, one per connection, and counts the
+    // connections made. `None` accepts the request without ever answering.
+    async fn serve(responses: Vec<Option<&'static str>>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counter = connections.clone();
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                counter.fetch_add(1, Ordering::SeqCst);
+                let mut buffer = [0; 4096];
+                let _ = socket.read(&mut buffer).await;
+                match response {
+                    Some(response) => {
+                        let _ = socket.write_all(response.as_bytes()).await;
+                    }
+                    None => {
+                        tokio::time::sleep(Duration::from_secs(5)).await;
+                    }
+                }
+            }
+        });
+        (url, connections)
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors_before_the_body() {
+        let (url, connections) = serve(vec![
+            Some("HTTP/1.1 503 Service Unavailable\r\nretry-after: 0\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"),
+            Some("HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok"),
+        ])
+        .await;
+
+        let response = send(reqwest::Client::new().post(&url).body("{}"), "Test")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(connections.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn does_not_resend_after_a_read_timeout() {
+        let (url, connections) = serve(vec![None, None]).await;
+
+        let request = reqwest::Client::new().post(&url).body("{}");
+        let result =
+            http::with_read_timeout(Duration::from_millis(200), send(request, "Test")).await;
+        assert!(matches!(result, Err(ProviderError::Network { .. })));
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,135 @@
+use super::ProviderError;
+use crate::api::http;
+use bytes::Bytes;
+use futures_util::{pin_mut, Stream, StreamExt};
+use reqwest::Response;
+
+/// One server-sent event. Multi-line `data:` fields are joined with `\n`.
+pub(crate) struct SseEvent {
+    pub event: Option<String>,
+    pub data: String,
+}
+
+fn parse_event(block: &str) -> Option<SseEvent> {
+    let mut event = None;
+    let mut data = Vec::new();
+
+    for line in block.lines() {
+        if let Some(value) = line.strip_prefix("event:") {
+            event = Some(value.trim().to_string());
+        } else if let Some(value) = line.strip_prefix("data:") {
+            data.push(value.strip_prefix(' ').unwrap_or(value));
+        }
+    }
+
+    if event.is_none() && data.is_empty() {
+        None
+    } else {
+        Some(SseEvent {
+            event,
+            data: data.join("\n"),
+        })
+    }
+}
+
+/// Reads an `text/event-stream` body and hands each event to `on_event`.
+/// Returning `Ok(false)` from the callback stops reading early.
+pub(crate) async fn read_events<F>(
+    response: Response,
+    name: &str,
+    on_event: F,
+) -> Result<(), ProviderError>
+where
+    F: FnMut(SseEvent) -> Result<bool, ProviderError>,
+{
+    read_byte_stream(response.bytes_stream(), name, on_event).await
+}
+
+async fn read_byte_stream<S, F>(stream: S, name: &str, mut on_event: F) -> Result<(), ProviderError>
+where
+    S: Stream<Item = reqwest::Result<Bytes>>,
+    F: FnMut(SseEvent) -> Result<bool, ProviderError>,
+{
+    pin_mut!(stream);
+    // Kept as bytes so multi-byte characters split across chunks survive
+    let mut buffer: Vec<u8> = Vec::new();
+
+    while let Some(chunk) = http::read(stream.next())
+        .await
+        .map_err(|timeout| ProviderError::timed_out(name, timeout))?
+    {
+        let chunk = chunk.map_err(|e| ProviderError::network(name, &e))?;
+        buffer.extend(chunk.iter().filter(|&&byte| byte != b'\r'));
+
+        while let Some(end) = buffer.windows(2).position(|pair| pair == b"\n\n") {
+            let block: Vec<u8> = buffer.drain(..end + 2).collect();
+            if let Some(event) = parse_event(&String::from_utf8_lossy(&block)) {
+                if !on_event(event)? {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    // Flush an unterminated final event
+    if let Some(event) = parse_event(&String::from_utf8_lossy(&buffer)) {
+        on_event(event)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::stream;
+
+    fn collect(chunks: Vec<&'static [u8]>) -> Vec<(Option<String>, String)> {
+        let chunks = stream::iter(chunks.into_iter().map(|chunk| Ok(Bytes::from(chunk))));
+        let mut events = Vec::new();
+        let read = read_byte_stream(chunks, "Test", |event| {
+            events.push((event.event, event.data));
+            Ok(true)
+        });
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(read)
+            .unwrap();
+        events
+    }
+
+    #[test]
+    fn parse_event_joins_data_lines() {
+        let event = parse_event("event: delta\ndata: one\ndata:two\n: comment").unwrap();
+        assert_eq!(event.event.as_deref(), Some("delta"));
+        assert_eq!(event.data, "one\ntwo");
+        assert!(parse_event(": keep-alive").is_none());
+    }
+
+    #[test]
+    fn events_split_across_chunks() {
+        let events = collect(vec![b"data: {\"a\"", b":1}\r\n\r\ndata: [DO", b"NE]\n\n"]);
+        assert_eq!(
+            events,
+            [
+                (None, "{\"a\":1}".to_string()),
+                (None, "[DONE]".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn multibyte_characters_split_across_chunks() {
+        let text = "data: h\u{e9}llo \u{1f600}\n\n".as_bytes();
+        let (first, rest) = text.split_at(8);
+        let (middle, last) = rest.split_at(6);
+        let events = collect(vec![first, middle, last]);
+        assert_eq!(events, [(None, "h\u{e9}llo \u{1f600}".to_string())]);
+    }
+
+    #[test]
+    fn unterminated_final_event_is_flushed() {
+        let events = collect(vec![b"event: done\ndata: x"]);
+        assert_eq!(events, [(Some("done".to_string()), "x".to_string())]);
+    }
+}
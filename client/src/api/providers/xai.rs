@@ -0,0 +1,165 @@
+use super::openai::{
+    chat_completion, chat_completion_stream, chat_messages, insert_response_format, insert_tools,
+    parse_response,
+};
+use super::{
+    insert_some, invalid_format, send_check, send_json, ChunkSink, Provider, ProviderError,
+};
+use crate::models::{ApiResponse, ModelInfo, SendMessageRequest};
+use async_trait::async_trait;
+use reqwest::Client;
+use serde_json::{json, Value};
+
+const XAI_API_BASE: &str = "https://api.x.ai/v1";
+
+pub struct XaiProvider;
+
+// Other Grok models reject the setting, whether they reason or not
+fn takes_reasoning_effort(model: &str) -> bool {
+    model.starts_with("grok-3-mini")
+}
+
+fn request_body(request: &SendMessageRequest, stream: bool) -> Result<Value, ProviderError> {
+    let params = &request.params;
+    // Grok reads images but not documents
+    let messages = chat_messages(
+        request.conversation(),
+        request.attachment().as_ref(),
+        "Grok",
+        false,
+    )?;
+    let mut body = json!({
+        "messages": messages,
+        "model": request.model,
+        "stream": stream
+    });
+
+    insert_some(&mut body, "temperature", &params.temperature);
+    insert_some(&mut body, "top_p", &params.top_p);
+    insert_some(&mut body, "max_tokens", &params.max_tokens);
+    insert_some(&mut body, "stop", &params.stop);
+    insert_some(&mut body, "seed", &params.seed);
+    if takes_reasoning_effort(&request.model) {
+        insert_some(&mut body, "reasoning_effort", &params.reasoning_effort);
+    }
+    insert_tools(&mut body, &request.tools);
+    insert_response_format(&mut body, request);
+
+    if stream {
+        body["stream_options"] = json!({ "include_usage": true });
+    }
+
+    Ok(body)
+}
+
+#[async_trait]
+impl Provider for XaiProvider {
+    fn id(&self) -> &str {
+        "xai"
+    }
+
+    fn display_name(&self) -> &str {
+        "Grok"
+    }
+
+    fn env_var(&self) -> &str {
+        "XAI_API_KEY"
+    }
+
+    fn supported_params(&self, request: &SendMessageRequest) -> &[&str] {
+        if takes_reasoning_effort(&request.model) {
+            &[
+                "temperature",
+                "top_p",
+                "max_tokens",
+                "stop",
+                "seed",
+                "reasoning_effort",
+            ]
+        } else {
+            &["temperature", "top_p", "max_tokens", "stop", "seed"]
+        }
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    fn supports_response_schema(&self) -> bool {
+        true
+    }
+
+    async fn complete(
+        &self,
+        client: &Client,
+        api_key: &str,
+        request: &SendMessageRequest,
+    ) -> Result<ApiResponse, ProviderError> {
+        let json = chat_completion(
+            client,
+            XAI_API_BASE,
+            api_key,
+            &request_body(request, false)?,
+            self.display_name(),
+        )
+        .await?;
+
+        parse_response(&json, self.display_name())
+    }
+
+    async fn stream(
+        &self,
+        client: &Client,
+        api_key: &str,
+        request: &SendMessageRequest,
+        on_chunk: ChunkSink<'_>,
+    ) -> Result<ApiResponse, ProviderError> {
+        chat_completion_stream(
+            client,
+            XAI_API_BASE,
+            api_key,
+            &request_body(request, true)?,
+            self.display_name(),
+            on_chunk,
+        )
+        .await
+    }
+
+    // `/language-models` reports input modalities, unlike `/models`
+    async fn list_models(
+        &self,
+        client: &Client,
+        api_key: &str,
+    ) -> Result<Vec<ModelInfo>, ProviderError> {
+        let request = client
+            .get(format!("{}/language-models", XAI_API_BASE))
+            .header("Authorization", format!("Bearer {}", api_key));
+        let json = send_json(request, self.display_name()).await?;
+
+        json["models"]
+            .as_array()
+            .map(|models| {
+                models
+                    .iter()
+                    .filter_map(|model| {
+                        let mut info = ModelInfo::new(model["id"].as_str()?);
+                        info.vision = model["input_modalities"]
+                            .as_array()
+                            .map_or(false, |modalities| {
+                                modalities.iter().any(|modality| modality == "image")
+                            });
+                        Some(info)
+                    })
+                    .collect()
+            })
+            .ok_or_else(|| invalid_format(self.display_name(), &json))
+    }
+
+    async fn verify_key(&self, client: &Client, api_key: &str) -> Result<(), ProviderError> {
+        let request = client
+            .get(format!("{}/models", XAI_API_BASE))
+            .header("Authorization", format!("Bearer {}", api_key));
+
+        send_check(request, self.display_name()).await
+    }
+}
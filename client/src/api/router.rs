@@ -0,0 +1,529 @@
+use crate::api::budget::{self, CHARS_PER_TOKEN};
+use crate::api::chat::resolve_api_key;
+use crate::api::usage;
+use crate::api::ApiState;
+use crate::config;
+use crate::files::Attachment;
+use crate::models::{ModelTarget, Role, RouteDecision, SendMessageRequest, Usage};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::AppHandle;
+
+/// Model name that leaves the choice of model to the router.
+pub const AUTO_MODEL: &str = "auto";
+
+// Rough cost of an image in prompt tokens, whatever its file size
+const IMAGE_TOKENS: u64 = 1_500;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AttachmentKind {
+    Image,
+    Pdf,
+    Epub,
+    Text,
+    Any,
+}
+
+/// An entry of `router.rules` in config.json. Every condition that is set
+/// has to hold, so a rule without any matches every request.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RouteRule {
+    pub name: String,
+    #[serde(flatten)]
+    pub target: ModelTarget,
+    // Starts of the prompt such as "search:", removed before sending
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub prefixes: Vec<String>,
+    // Whole words, case insensitive. Any one of them is enough.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attachment: Option<AttachmentKind>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_prompt_tokens: Option<u64>,
+}
+
+impl RouteRule {
+    fn new(name: &str, provider: &str, model: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            target: ModelTarget {
+                provider: provider.to_string(),
+                model: model.to_string(),
+            },
+            prefixes: Vec::new(),
+            keywords: Vec::new(),
+            attachment: None,
+            min_prompt_tokens: None,
+        }
+    }
+}
+
+/// Stored under `router` in config.json.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct RouterConfig {
+    // Tried in order, the built-in rules when empty
+    #[serde(default)]
+    pub rules: Vec<RouteRule>,
+    // Models a request is estimated to cost more than this on, in USD, are
+    // passed over
+    #[serde(default)]
+    pub max_cost: Option<f64>,
+}
+
+/// Rules used until the user sets their own. Several catch-alls close the
+/// list so that whichever provider has a key gets the rest.
+pub fn default_rules() -> Vec<RouteRule> {
+    vec![
+        RouteRule {
+            prefixes: vec!["search:".to_string(), "/search".to_string()],
+            ..RouteRule::new("search", "perplexity", "sonar-pro")
+        },
+        RouteRule {
+            attachment: Some(AttachmentKind::Image),
+            ..RouteRule::new("images", "openai", "gpt-4o")
+        },
+        RouteRule {
+            attachment: Some(AttachmentKind::Pdf),
+            ..RouteRule::new("documents", "anthropic", "claude-3-5-sonnet-latest")
+        },
+        RouteRule {
+            min_prompt_tokens: Some(100_000),
+            ..RouteRule::new("long prompts", "google", "gemini-1.5-pro")
+        },
+        RouteRule {
+            prefixes: vec!["/code".to_string()],
+            keywords: ["code", "function", "bug", "compile", "regex", "sql"]
+                .iter()
+                .map(|keyword| keyword.to_string())
+                .collect(),
+            ..RouteRule::new("code", "anthropic", "claude-3-5-sonnet-latest")
+        },
+        RouteRule::new("default", "anthropic", "claude-3-5-sonnet-latest"),
+        RouteRule::new("default", "openai", "gpt-4o"),
+        RouteRule::new("default", "google", "gemini-1.5-pro"),
+        RouteRule::new("default", "xai", "grok-2-latest"),
+        RouteRule::new("default", "perplexity", "sonar"),
+    ]
+}
+
+pub fn router_config(config: &Value) -> RouterConfig {
+    if config["router"].is_null() {
+        return RouterConfig::default();
+    }
+    serde_json::from_value(config["router"].clone()).unwrap_or_else(|e| {
+        println!("Ignoring invalid router config: {}", e);
+        RouterConfig::default()
+    })
+}
+
+// The user's newest turn, which is what the rules look at
+fn latest_prompt(request: &mut SendMessageRequest) -> &mut String {
+    if request.messages.is_empty() {
+        return &mut request.message;
+    }
+    match request
+        .messages
+        .iter()
+        .rposition(|message| message.role == Role::User)
+    {
+        Some(index) => &mut request.messages[index].content,
+        None => &mut request.message,
+    }
+}
+
+// Text and text attachments by length, images at a flat rate since their
+// size says little about what they cost
+fn prompt_tokens(request: &SendMessageRequest, attachment: Option<&Attachment>) -> u64 {
+    let text_chars: usize = request
+        .conversation()
+        .iter()
+        .map(|message| message.content.len())
+        .sum();
+    let attachment_tokens = match attachment {
+        Some(file) if file.is_image() => IMAGE_TOKENS,
+        Some(Attachment::Text { content, .. }) => (content.len() / CHARS_PER_TOKEN) as u64,
+        Some(Attachment::Binary { data, .. }) => (data.len() / CHARS_PER_TOKEN) as u64,
+        None => 0,
+    };
+    (text_chars / CHARS_PER_TOKEN) as u64 + attachment_tokens
+}
+
+fn attachment_matches(kind: AttachmentKind, attachment: Option<&Attachment>) -> bool {
+    let file = match attachment {
+        Some(file) => file,
+        None => return false,
+    };
+    match kind {
+        AttachmentKind::Image => file.is_image(),
+        AttachmentKind::Pdf => file.is_pdf(),
+        AttachmentKind::Epub => file.is_epub(),
+        AttachmentKind::Text => matches!(file, Attachment::Text { .. }),
+        AttachmentKind::Any => true,
+    }
+}
+
+fn matched_prefix<'a>(rule: &'a RouteRule, prompt: &str) -> Option<&'a str> {
+    let prompt = prompt.trim_start().to_lowercase();
+    rule.prefixes
+        .iter()
+        .find(|prefix| prompt.starts_with(&prefix.to_lowercase()))
+        .map(String::as_str)
+}
+
+fn without_prefix(prompt: &str, prefix: &str) -> String {
+    let trimmed = prompt.trim_start();
+    let rest = trimmed.get(prefix.len()..).unwrap_or(trimmed);
+    rest.trim_start().to_string()
+}
+
+fn matched_keyword<'a>(rule: &'a RouteRule, prompt: &str) -> Option<&'a str> {
+    rule.keywords
+        .iter()
+        .find(|keyword| {
+            Regex::new(&format!(r"(?i)\b{}\b", regex::escape(keyword)))
+                .is_ok_and(|pattern| pattern.is_match(prompt))
+        })
+        .map(String::as_str)
+}
+
+// Why the rule matches the request, or None when it doesn't
+fn match_reason(
+    rule: &RouteRule,
+    prompt: &str,
+    attachment: Option<&Attachment>,
+    prompt_tokens: u64,
+) -> Option<String> {
+    let mut reasons = Vec::new();
+
+    // A prefix or a keyword will do when a rule has both, as with "/code"
+    if !rule.prefixes.is_empty() || !rule.keywords.is_empty() {
+        match (matched_prefix(rule, prompt), matched_keyword(rule, prompt)) {
+            (Some(prefix), _) => reasons.push(format!("prompt starts with {}", prefix)),
+            (None, Some(keyword)) => reasons.push(format!("prompt mentions {}", keyword)),
+            (None, None) => return None,
+        }
+    }
+    if let Some(kind) = rule.attachment {
+        if !attachment_matches(kind, attachment) {
+            return None;
+        }
+        reasons.push(format!("{:?} attachment", kind).to_lowercase());
+    }
+    if let Some(min) = rule.min_prompt_tokens {
+        if prompt_tokens < min {
+            return None;
+        }
+        reasons.push(format!("about {} prompt tokens", prompt_tokens));
+    }
+
+    if reasons.is_empty() {
+        reasons.push("no other rule applied".to_string());
+    }
+    Some(reasons.join(", "))
+}
+
+// Why the rule's model can't take the request, or None when it can
+#[allow(clippy::too_many_arguments)]
+fn unsuitable(
+    rule: &RouteRule,
+    request: &SendMessageRequest,
+    app_handle: &AppHandle,
+    state: &ApiState,
+    router: &RouterConfig,
+    config: &Value,
+    attachment: Option<&Attachment>,
+    prompt_tokens: u64,
+) -> Option<String> {
+    let ModelTarget { provider, model } = &rule.target;
+    let provider = match state.providers.get(provider) {
+        Some(provider) => provider,
+        None => return Some(format!("{} isn't a known provider", provider)),
+    };
+    let api_key = resolve_api_key(app_handle, state, provider.as_ref()).unwrap_or_default();
+    if api_key.is_empty() && provider.requires_key() {
+        return Some(format!("no API key for {}", provider.display_name()));
+    }
+
+    // Only what's known from listing models; unlisted ones get the benefit
+    // of the doubt
+    let info = state
+        .models
+        .get(provider.id())
+        .and_then(|models| models.into_iter().find(|info| &info.id == model));
+    if let Some(info) = info {
+        if matches!(attachment, Some(file) if file.is_image()) && !info.vision {
+            return Some(format!("{} can't read images", model));
+        }
+        if let Some(window) = info.context_window {
+            if prompt_tokens > window {
+                return Some(format!(
+                    "about {} prompt tokens don't fit in {}'s {} token window",
+                    prompt_tokens, model, window
+                ));
+            }
+        }
+    }
+
+    if let Some(max_cost) = router.max_cost {
+        let prices = usage::pricing(config);
+        let estimate = usage::price_for(&prices, provider.id(), model).map(|price| {
+            price.cost(&Usage {
+                input_tokens: Some(prompt_tokens),
+                ..budget::estimate_usage(request)
+            })
+        });
+        if let Some(estimate) = estimate.filter(|estimate| *estimate > max_cost) {
+            return Some(format!(
+                "{} would cost about ${:.4}, over the ${} limit",
+                model, estimate, max_cost
+            ));
+        }
+    }
+    None
+}
+
+/// Picks a model for a request sent to the `auto` model and points the
+/// request at it. The first rule that matches and whose model can take the
+/// request wins. Requests for any other model are returned untouched.
+pub fn route(
+    mut request: SendMessageRequest,
+    app_handle: &AppHandle,
+    state: &ApiState,
+) -> Result<(SendMessageRequest, Option<RouteDecision>), String> {
+    if request.model != AUTO_MODEL {
+        return Ok((request, None));
+    }
+
+    let config = config::load_stored_keys(app_handle)?;
+    let router = router_config(&config);
+    let rules = if router.rules.is_empty() {
+        default_rules()
+    } else {
+        router.rules.clone()
+    };
+
+    let attachment = request.attachment();
+    let prompt = latest_prompt(&mut request).clone();
+    let tokens = prompt_tokens(&request, attachment.as_ref());
+    let mut passed_over = Vec::new();
+
+    for rule in &rules {
+        let reason = match match_reason(rule, &prompt, attachment.as_ref(), tokens) {
+            Some(reason) => reason,
+            None => continue,
+        };
+        if let Some(problem) = unsuitable(
+            rule,
+            &request,
+            app_handle,
+            state,
+            &router,
+            &config,
+            attachment.as_ref(),
+            tokens,
+        ) {
+            passed_over.push(format!("{}: {}", rule.name, problem));
+            continue;
+        }
+
+        if let Some(prefix) = matched_prefix(rule, &prompt) {
+            *latest_prompt(&mut request) = without_prefix(&prompt, prefix);
+        }
+        request.provider = rule.target.provider.clone();
+        request.model = rule.target.model.clone();
+
+        println!(
+            "Routing to {} {} by rule {}: {}",
+            request.provider, request.model, rule.name, reason
+        );
+        let decision = RouteDecision {
+            provider: request.provider.clone(),
+            model: request.model.clone(),
+            rule: rule.name.clone(),
+            reason,
+            passed_over,
+        };
+        return Ok((request, Some(decision)));
+    }
+
+    if passed_over.is_empty() {
+        Err("No routing rule matches this request".to_string())
+    } else {
+        Err(format!(
+            "No routed model can take this request ({})",
+            passed_over.join("; ")
+        ))
+    }
+}
+
+/// The router settings, with the built-in rules filled in when none are
+/// set so they can be shown and edited.
+#[tauri::command]
+pub fn get_router_config(app_handle: AppHandle) -> Result<RouterConfig, String> {
+    let config = config::load_stored_keys(&app_handle)?;
+    let mut router = router_config(&config);
+    if router.rules.is_empty() {
+        router.rules = default_rules();
+    }
+    Ok(router)
+}
+
+#[tauri::command]
+pub fn set_router_config(app_handle: AppHandle, router: RouterConfig) -> Result<(), String> {
+    let mut config = config::load_stored_keys(&app_handle)?;
+    let config_obj = config.as_object_mut().ok_or("Invalid stored keys format")?;
+
+    if router.rules.is_empty() && router.max_cost.is_none() {
+        config_obj.remove("router");
+    } else {
+        config_obj.insert(
+            "router".to_string(),
+            serde_json::to_value(router).map_err(|e| e.to_string())?,
+        );
+    }
+    config::save_keys(&app_handle, &config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request(value: Value) -> SendMessageRequest {
+        let mut request = json!({ "provider": "anthropic", "model": AUTO_MODEL });
+        request
+            .as_object_mut()
+            .unwrap()
+            .extend(value.as_object().unwrap().clone());
+        serde_json::from_value(request).unwrap()
+    }
+
+    // The rule `route` would pick if every model could take the request
+    fn first_match(rules: &[RouteRule], request: &mut SendMessageRequest) -> Option<String> {
+        let attachment = request.attachment();
+        let prompt = latest_prompt(request).clone();
+        let tokens = prompt_tokens(request, attachment.as_ref());
+        rules
+            .iter()
+            .find(|rule| match_reason(rule, &prompt, attachment.as_ref(), tokens).is_some())
+            .map(|rule| rule.name.clone())
+    }
+
+    #[test]
+    fn default_rules_route_by_prefix_attachment_and_keyword() {
+        let rules = default_rules();
+        let cases = [
+            (json!({ "message": "Search: rust 1.80 release" }), "search"),
+            (
+                json!({ "message": "what is this?", "file_name": "a.png", "file_content": "data:image/png;base64,AAAA" }),
+                "images",
+            ),
+            (
+                json!({ "message": "summarise", "file_name": "a.pdf", "file_content": "data:application/pdf;base64,AAAA" }),
+                "documents",
+            ),
+            (json!({ "message": "Why won't this COMPILE?" }), "code"),
+            (json!({ "message": "/code tidy this up" }), "code"),
+            (json!({ "message": "tell me a story" }), "default"),
+            // Keywords are whole words only
+            (json!({ "message": "a bugle call" }), "default"),
+        ];
+        for (value, expected) in cases {
+            let mut request = request(value.clone());
+            assert_eq!(
+                first_match(&rules, &mut request).as_deref(),
+                Some(expected),
+                "{}",
+                value
+            );
+        }
+
+        let long = "word ".repeat(100_000 * CHARS_PER_TOKEN / 5 + 1);
+        let mut request = request(json!({ "message": long }));
+        assert_eq!(
+            first_match(&rules, &mut request).as_deref(),
+            Some("long prompts")
+        );
+    }
+
+    #[test]
+    fn the_latest_user_turn_is_matched() {
+        let rules = default_rules();
+        let mut request = request(json!({
+            "messages": [
+                { "role": "user", "content": "search: old question" },
+                { "role": "assistant", "content": "an answer" },
+                { "role": "user", "content": "thanks" },
+                { "role": "assistant", "content": "" }
+            ]
+        }));
+        assert_eq!(
+            first_match(&rules, &mut request).as_deref(),
+            Some("default")
+        );
+        assert_eq!(latest_prompt(&mut request), "thanks");
+    }
+
+    #[test]
+    fn every_condition_of_a_rule_has_to_hold() {
+        let rule = RouteRule {
+            keywords: vec!["invoice".to_string()],
+            attachment: Some(AttachmentKind::Any),
+            ..RouteRule::new("invoices", "openai", "gpt-4o")
+        };
+        let text = Attachment::from_content("a.txt", "plain text");
+
+        assert_eq!(
+            match_reason(&rule, "check this invoice", Some(&text), 10).as_deref(),
+            Some("prompt mentions invoice, any attachment")
+        );
+        assert_eq!(match_reason(&rule, "check this invoice", None, 10), None);
+        assert_eq!(match_reason(&rule, "check this", Some(&text), 10), None);
+
+        let long = RouteRule {
+            min_prompt_tokens: Some(100),
+            ..RouteRule::new("long", "google", "gemini-1.5-pro")
+        };
+        assert_eq!(match_reason(&long, "", None, 99), None);
+        assert_eq!(
+            match_reason(&long, "", None, 100).as_deref(),
+            Some("about 100 prompt tokens")
+        );
+        assert_eq!(
+            match_reason(&RouteRule::new("any", "xai", "grok"), "", None, 0).as_deref(),
+            Some("no other rule applied")
+        );
+    }
+
+    #[test]
+    fn prefixes_are_matched_loosely_and_removed() {
+        let rule = RouteRule {
+            prefixes: vec!["Search:".to_string()],
+            ..RouteRule::new("search", "perplexity", "sonar")
+        };
+        assert_eq!(matched_prefix(&rule, "  search: weather"), Some("Search:"));
+        assert_eq!(matched_prefix(&rule, "research: weather"), None);
+        assert_eq!(without_prefix("  search:  weather", "Search:"), "weather");
+    }
+
+    #[test]
+    fn invalid_router_config_falls_back_to_defaults() {
+        assert!(router_config(&json!({})).rules.is_empty());
+        assert!(router_config(&json!({ "router": { "rules": 3 } }))
+            .rules
+            .is_empty());
+
+        let router = router_config(&json!({
+            "router": {
+                "rules": [{ "name": "all", "provider": "openai", "model": "gpt-4o", "attachment": "epub" }],
+                "max_cost": 0.05
+            }
+        }));
+        assert_eq!(router.rules[0].target.model, "gpt-4o");
+        assert_eq!(router.rules[0].attachment, Some(AttachmentKind::Epub));
+        assert_eq!(router.max_cost, Some(0.05));
+    }
+}
@@ -1,15 +1,20 @@
+use crate::api::ApiState;
 use crate::config;
 use base64::{engine::general_purpose::STANDARD, Engine};
-use tauri::AppHandle;
+use tauri::{AppHandle, State};
 
 #[tauri::command]
-pub async fn text_to_speech(text: String, app_handle: AppHandle) -> Result<String, String> {
+pub async fn text_to_speech(
+    text: String,
+    app_handle: AppHandle,
+    state: State<'_, ApiState>,
+) -> Result<String, String> {
     let stored_keys = config::load_stored_keys(&app_handle)?;
     let api_key = stored_keys["elevenlabs"]
         .as_str()
         .ok_or("ElevenLabs API key not found")?;
 
-    let client = reqwest::Client::new();
+    let client = state.http.client();
     let voice_id = "21m00Tcm4TlvDq8ikWAM"; // Default voice ID
 
     let request_body = serde_json::json!({
@@ -0,0 +1,497 @@
+use crate::api::providers::{ChunkSink, Provider, ProviderError, StreamChunk};
+use crate::api::usage::UsageMeter;
+use crate::models::{ApiResponse, ChatMessage, Role, SendMessageRequest};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::Client;
+use serde_json::{json, Value};
+
+/// Tool Anthropic is forced to call, its input being the structured answer.
+pub(crate) const RESPONSE_TOOL: &str = "structured_response";
+
+static CODE_FENCE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?s)```(?:json)?\s*(.*?)```").unwrap());
+
+/// Sends a request that has a `response_schema` and checks the answer
+/// against it. An answer that doesn't match is sent back once with the
+/// problem so the model can fix it.
+///
+/// The answer is only known to be valid once it is complete, so streaming
+/// callers get it as a single chunk. Usage of both attempts is summed on
+/// `spent`.
+pub async fn run(
+    provider: &dyn Provider,
+    client: &Client,
+    api_key: &str,
+    mut request: SendMessageRequest,
+    spent: &UsageMeter,
+    on_chunk: Option<ChunkSink<'_>>,
+) -> Result<ApiResponse, ProviderError> {
+    let schema = request.response_schema.clone().unwrap_or_default();
+    let name = provider.display_name();
+
+    // Corrections are appended to the history, so fix it in place first
+    request.messages = request.conversation();
+    request.params.system_prompt = None;
+    if !provider.supports_response_schema() {
+        request.messages.insert(
+            0,
+            ChatMessage::new(
+                Role::System,
+                format!(
+                    "Reply with only a JSON value matching this JSON Schema, without any other text:\n{}",
+                    schema
+                ),
+            ),
+        );
+    }
+
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let mut response = provider.complete(client, api_key, &request).await?;
+        if let Some(usage) = &response.usage {
+            spent.add(usage);
+        }
+
+        let text = answer_text(&schema, &mut response);
+        let checked = extract_json(&text)
+            .ok_or_else(|| "The response is not valid JSON".to_string())
+            .and_then(|value| validate(&schema, &value).map(|_| value));
+
+        match checked {
+            Ok(value) => {
+                let content = value.to_string();
+                let usage = spent.total();
+                if let Some(on_chunk) = on_chunk {
+                    if let Some(reasoning) = &response.reasoning {
+                        on_chunk(StreamChunk::Reasoning(reasoning.clone()));
+                    }
+                    on_chunk(StreamChunk::Text(content.clone()));
+                    if let Some(usage) = &usage {
+                        on_chunk(StreamChunk::Usage(usage.clone()));
+                    }
+                }
+                response.content = Some(content);
+                response.structured = Some(value);
+                response.usage = usage;
+                return Ok(response);
+            }
+            Err(e) if attempt == 1 => {
+                println!(
+                    "{} answer didn't match the schema, asking again: {}",
+                    name, e
+                );
+                request
+                    .messages
+                    .push(ChatMessage::new(Role::Assistant, text));
+                request.messages.push(ChatMessage::user(format!(
+                    "That answer doesn't match the schema: {}. Reply again with only JSON that matches it.",
+                    e
+                )));
+            }
+            Err(e) => {
+                return Err(ProviderError::bad_response(
+                    name,
+                    format!("The answer doesn't match the schema: {}", e),
+                    &text,
+                ))
+            }
+        }
+    }
+}
+
+// Anthropic answers through the forced tool, everyone else as text
+fn answer_text(schema: &Value, response: &mut ApiResponse) -> String {
+    let calls = response.tool_calls.take().unwrap_or_default();
+    match calls.into_iter().find(|call| call.name == RESPONSE_TOOL) {
+        Some(call) if is_object_schema(schema) => call.arguments.to_string(),
+        Some(call) => call.arguments["value"].to_string(),
+        None => response.content.take().unwrap_or_default(),
+    }
+}
+
+fn is_object_schema(schema: &Value) -> bool {
+    schema["type"] == "object"
+}
+
+/// The `input_schema` of the response tool. Tool inputs have to be objects,
+/// so any other answer is asked for as the `value` field of one.
+pub(crate) fn tool_input_schema(schema: &Value) -> Value {
+    if is_object_schema(schema) {
+        return schema.clone();
+    }
+    let mut wrapper = json!({
+        "type": "object",
+        "properties": { "value": schema },
+        "required": ["value"]
+    });
+    // Keep `#/$defs/...` references resolving from the new root
+    for key in ["$defs", "definitions"] {
+        if let Some(definitions) = schema.get(key) {
+            wrapper[key] = definitions.clone();
+        }
+    }
+    wrapper
+}
+
+/// Finds the JSON in a model's answer, which may be wrapped in a code
+/// fence or have a sentence around it despite being asked not to.
+pub fn extract_json(text: &str) -> Option<Value> {
+    let text = text.trim();
+    if let Ok(value) = serde_json::from_str(text) {
+        return Some(value);
+    }
+
+    if let Some(inner) = CODE_FENCE.captures(text).and_then(|caps| caps.get(1)) {
+        if let Ok(value) = serde_json::from_str(inner.as_str().trim()) {
+            return Some(value);
+        }
+    }
+
+    let start = text.find(['{', '['])?;
+    let end = text.rfind(['}', ']'])?;
+    (start < end)
+        .then(|| serde_json::from_str(&text[start..=end]).ok())
+        .flatten()
+}
+
+/// Checks `value` against `schema` and describes the first mismatch. Covers
+/// the keywords structured output schemas use: types, `properties`,
+/// `required`, `additionalProperties`, `items`, `enum`, `const`, bounds,
+/// `pattern`, the combinators and local `$ref`s.
+pub fn validate(schema: &Value, value: &Value) -> Result<(), String> {
+    check(schema, schema, value, "$", &mut Vec::new())
+}
+
+fn type_matches(kind: &str, value: &Value) -> bool {
+    match kind {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => {
+            value.is_i64() || value.is_u64() || value.as_f64().map_or(false, |n| n.fract() == 0.0)
+        }
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => true,
+    }
+}
+
+fn check(
+    root: &Value,
+    schema: &Value,
+    value: &Value,
+    path: &str,
+    // `$ref`s being followed and where, so cycles like `{"$ref": "#"}` end
+    refs: &mut Vec<(String, String)>,
+) -> Result<(), String> {
+    let schema = match schema {
+        Value::Bool(false) => return Err(format!("{} is not allowed", path)),
+        Value::Object(_) => schema,
+        _ => return Ok(()),
+    };
+
+    if let Some(reference) = schema["$ref"].as_str() {
+        let target = reference
+            .strip_prefix('#')
+            .and_then(|pointer| root.pointer(pointer))
+            .ok_or_else(|| format!("unsupported $ref {}", reference))?;
+        let followed = (reference.to_string(), path.to_string());
+        if refs.contains(&followed) {
+            return Err(format!("{} loops through $ref {}", path, reference));
+        }
+        refs.push(followed);
+        let result = check(root, target, value, path, refs);
+        refs.pop();
+        return result;
+    }
+
+    let types: Vec<&str> = match &schema["type"] {
+        Value::String(kind) => vec![kind.as_str()],
+        Value::Array(kinds) => kinds.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    if !types.is_empty() && !types.iter().any(|kind| type_matches(kind, value)) {
+        return Err(format!("{} should be {}", path, types.join(" or ")));
+    }
+
+    if let Some(options) = schema["enum"].as_array() {
+        if !options.contains(value) {
+            return Err(format!("{} should be one of {}", path, schema["enum"]));
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            return Err(format!("{} should be {}", path, expected));
+        }
+    }
+
+    match value {
+        Value::String(text) => check_string(schema, text, path)?,
+        Value::Number(_) => check_number(schema, value.as_f64().unwrap_or_default(), path)?,
+        Value::Array(items) => {
+            check_length(schema, items.len(), "minItems", "maxItems", "items", path)?;
+            if schema["items"].is_object() || schema["items"].is_boolean() {
+                for (index, item) in items.iter().enumerate() {
+                    check(
+                        root,
+                        &schema["items"],
+                        item,
+                        &format!("{}[{}]", path, index),
+                        refs,
+                    )?;
+                }
+            }
+        }
+        Value::Object(fields) => {
+            if let Some(required) = schema["required"].as_array() {
+                for key in required.iter().filter_map(Value::as_str) {
+                    if !fields.contains_key(key) {
+                        return Err(format!("{} is missing {}", path, key));
+                    }
+                }
+            }
+            let properties = schema["properties"].as_object();
+            for (key, field) in fields {
+                let field_path = format!("{}.{}", path, key);
+                match properties.and_then(|properties| properties.get(key)) {
+                    Some(property) => check(root, property, field, &field_path, refs)?,
+                    None => match &schema["additionalProperties"] {
+                        Value::Bool(false) => {
+                            return Err(format!("{} has unexpected field {}", path, key))
+                        }
+                        extra @ Value::Object(_) => check(root, extra, field, &field_path, refs)?,
+                        _ => {}
+                    },
+                }
+            }
+        }
+        _ => {}
+    }
+
+    if let Some(all) = schema["allOf"].as_array() {
+        for option in all {
+            check(root, option, value, path, refs)?;
+        }
+    }
+    if let Some(any) = schema["anyOf"].as_array() {
+        if !any
+            .iter()
+            .any(|option| check(root, option, value, path, refs).is_ok())
+        {
+            return Err(format!("{} doesn't match any allowed shape", path));
+        }
+    }
+    if let Some(one) = schema["oneOf"].as_array() {
+        let matches = one
+            .iter()
+            .filter(|option| check(root, option, value, path, refs).is_ok())
+            .count();
+        if matches != 1 {
+            return Err(format!("{} should match exactly one allowed shape", path));
+        }
+    }
+    if let Some(not) = schema.get("not") {
+        if check(root, not, value, path, refs).is_ok() {
+            return Err(format!("{} matches a disallowed shape", path));
+        }
+    }
+
+    Ok(())
+}
+
+fn check_length(
+    schema: &Value,
+    length: usize,
+    min: &str,
+    max: &str,
+    unit: &str,
+    path: &str,
+) -> Result<(), String> {
+    if let Some(min) = schema[min].as_u64() {
+        if (length as u64) < min {
+            return Err(format!("{} should have at least {} {}", path, min, unit));
+        }
+    }
+    if let Some(max) = schema[max].as_u64() {
+        if (length as u64) > max {
+            return Err(format!("{} should have at most {} {}", path, max, unit));
+        }
+    }
+    Ok(())
+}
+
+fn check_string(schema: &Value, text: &str, path: &str) -> Result<(), String> {
+    check_length(
+        schema,
+        text.chars().count(),
+        "minLength",
+        "maxLength",
+        "characters",
+        path,
+    )?;
+
+    if let Some(pattern) = schema["pattern"].as_str() {
+        // A pattern we can't compile is the schema's problem, not the answer's
+        if let Ok(regex) = Regex::new(pattern) {
+            if !regex.is_match(text) {
+                return Err(format!("{} should match {}", path, pattern));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_number(schema: &Value, number: f64, path: &str) -> Result<(), String> {
+    let bounds: [(&str, &str, fn(f64, f64) -> bool); 4] = [
+        ("minimum", "at least", |number, bound| number >= bound),
+        ("maximum", "at most", |number, bound| number <= bound),
+        ("exclusiveMinimum", "more than", |number, bound| {
+            number > bound
+        }),
+        ("exclusiveMaximum", "less than", |number, bound| {
+            number < bound
+        }),
+    ];
+
+    for (keyword, phrase, within) in bounds {
+        if let Some(bound) = schema[keyword].as_f64() {
+            if !within(number, bound) {
+                return Err(format!("{} should be {} {}", path, phrase, bound));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ToolCall;
+
+    #[test]
+    fn json_is_found_around_other_text() {
+        assert_eq!(extract_json(" {\"a\": 1} "), Some(json!({ "a": 1 })));
+        assert_eq!(
+            extract_json("Here you go:\n```json\n[1, 2]\n```\nAnything else?"),
+            Some(json!([1, 2]))
+        );
+        assert_eq!(
+            extract_json("The answer is {\"ok\": true}, as asked."),
+            Some(json!({ "ok": true }))
+        );
+        assert_eq!(extract_json("\"plain\""), Some(json!("plain")));
+        assert_eq!(extract_json("no json here"), None);
+        assert_eq!(extract_json("} backwards {"), None);
+    }
+
+    #[test]
+    fn values_are_checked_against_the_schema() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string", "minLength": 1, "pattern": "^[a-z]+$" },
+                "age": { "type": "integer", "minimum": 0, "exclusiveMaximum": 150 },
+                "tags": { "type": "array", "items": { "enum": ["a", "b"] }, "maxItems": 2 },
+                "kind": { "$ref": "#/$defs/kind" }
+            },
+            "required": ["name"],
+            "additionalProperties": false,
+            "$defs": { "kind": { "oneOf": [{ "const": "cat" }, { "const": "dog" }] } }
+        });
+
+        assert_eq!(
+            validate(
+                &schema,
+                &json!({ "name": "tom", "age": 3, "tags": ["a"], "kind": "cat" })
+            ),
+            Ok(())
+        );
+        let failures = [
+            (json!([]), "$ should be object"),
+            (json!({}), "$ is missing name"),
+            (json!({ "name": "Tom" }), "$.name should match ^[a-z]+$"),
+            (
+                json!({ "name": "tom", "age": 2.5 }),
+                "$.age should be integer",
+            ),
+            (
+                json!({ "name": "tom", "age": 150 }),
+                "$.age should be less than 150",
+            ),
+            (
+                json!({ "name": "tom", "tags": ["c"] }),
+                "$.tags[0] should be one of [\"a\",\"b\"]",
+            ),
+            (
+                json!({ "name": "tom", "tags": ["a", "a", "b"] }),
+                "$.tags should have at most 2 items",
+            ),
+            (
+                json!({ "name": "tom", "kind": "cow" }),
+                "$.kind should match exactly one allowed shape",
+            ),
+            (
+                json!({ "name": "tom", "extra": 1 }),
+                "$ has unexpected field extra",
+            ),
+        ];
+        for (value, error) in failures {
+            assert_eq!(
+                validate(&schema, &value),
+                Err(error.to_string()),
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn ref_cycles_end() {
+        assert_eq!(
+            validate(&json!({ "$ref": "#" }), &json!(1)),
+            Err("$ loops through $ref #".to_string())
+        );
+        let branching = json!({ "anyOf": [{ "$ref": "#" }, { "$ref": "#" }] });
+        assert!(validate(&branching, &json!(1)).is_err());
+
+        // Recursing into a smaller part of the value is fine
+        let tree = json!({
+            "type": "object",
+            "properties": { "children": { "type": "array", "items": { "$ref": "#" } } }
+        });
+        assert_eq!(
+            validate(&tree, &json!({ "children": [{ "children": [{}] }] })),
+            Ok(())
+        );
+        assert_eq!(
+            validate(&tree, &json!({ "children": [{ "children": [1] }] })),
+            Err("$.children[0].children[0] should be object".to_string())
+        );
+    }
+
+    #[test]
+    fn non_object_answers_are_wrapped_for_the_response_tool() {
+        let object = json!({ "type": "object", "properties": {} });
+        assert_eq!(tool_input_schema(&object), object);
+
+        let list = json!({ "type": "array", "items": { "$ref": "#/$defs/item" }, "$defs": { "item": { "type": "string" } } });
+        let wrapped = tool_input_schema(&list);
+        assert_eq!(wrapped["type"], "object");
+        assert_eq!(wrapped["properties"]["value"], list);
+        assert_eq!(wrapped["required"], json!(["value"]));
+        assert_eq!(validate(&wrapped, &json!({ "value": ["x"] })), Ok(()));
+        assert!(validate(&wrapped, &json!({ "value": [1] })).is_err());
+
+        let mut response = ApiResponse {
+            tool_calls: Some(vec![ToolCall {
+                id: "1".to_string(),
+                name: RESPONSE_TOOL.to_string(),
+                arguments: json!({ "value": ["x", "y"] }),
+            }]),
+            ..Default::default()
+        };
+        assert_eq!(answer_text(&list, &mut response), "[\"x\",\"y\"]");
+    }
+}
@@ -0,0 +1,103 @@
+use crate::api::ApiState;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, State};
+use tokio::sync::oneshot;
+
+// How long a tool waits for an answer before treating it as a denial
+const APPROVAL_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Emitted as a `tool-approval` event when a tool wants the user's go-ahead.
+/// The frontend answers with `respond_tool_approval`.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ApprovalRequest {
+    pub approval_id: String,
+    pub request_id: String,
+    pub tool: String,
+    // What the tool is about to do, for the prompt
+    pub summary: String,
+    pub detail: Option<String>,
+}
+
+/// Tool actions waiting on the user, keyed by approval id.
+#[derive(Default)]
+pub struct ToolApprovals {
+    pending: Mutex<HashMap<String, oneshot::Sender<bool>>>,
+}
+
+// Forgets the approval if the request is cancelled while waiting
+struct Pending<'a> {
+    approvals: &'a ToolApprovals,
+    approval_id: &'a str,
+}
+
+impl Drop for Pending<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut pending) = self.approvals.pending.lock() {
+            pending.remove(self.approval_id);
+        }
+    }
+}
+
+impl ToolApprovals {
+    /// Asks the frontend to approve `request` and waits for the answer.
+    /// Anything but an explicit approval, including a timeout, is a denial.
+    pub async fn request(&self, app_handle: &AppHandle, request: ApprovalRequest) -> bool {
+        let (sender, receiver) = oneshot::channel();
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.insert(request.approval_id.clone(), sender);
+        }
+        let _pending = Pending {
+            approvals: self,
+            approval_id: &request.approval_id,
+        };
+
+        if let Err(e) = app_handle.emit("tool-approval", request.clone()) {
+            eprintln!("Failed to emit tool approval: {:?}", e);
+            return false;
+        }
+
+        match tokio::time::timeout(APPROVAL_TIMEOUT, receiver).await {
+            Ok(Ok(approved)) => approved,
+            Ok(Err(_)) => false,
+            Err(_) => {
+                println!("Approval {} timed out", request.approval_id);
+                false
+            }
+        }
+    }
+
+    pub fn respond(&self, approval_id: &str, approved: bool) -> bool {
+        let sender = self
+            .pending
+            .lock()
+            .ok()
+            .and_then(|mut pending| pending.remove(approval_id));
+
+        match sender {
+            Some(sender) => sender.send(approved).is_ok(),
+            None => false,
+        }
+    }
+}
+
+/// Answers a `tool-approval` event. Returns false when nothing is waiting
+/// on that id any more.
+#[tauri::command]
+pub fn respond_tool_approval(
+    approval_id: String,
+    approved: bool,
+    state: State<'_, ApiState>,
+) -> bool {
+    let answered = state.approvals.respond(&approval_id, approved);
+    println!(
+        "Tool approval {} {}: {}",
+        approval_id,
+        if approved { "granted" } else { "denied" },
+        answered
+    );
+    answered
+}
@@ -0,0 +1,712 @@
+use super::approval::{ApprovalRequest, ToolApprovals};
+use super::{Tool, ToolContext, ToolRegistry};
+use crate::config;
+use crate::files;
+use async_trait::async_trait;
+use regex::RegexBuilder;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tauri::AppHandle;
+
+// Files larger than this are not read or searched
+const MAX_FILE_BYTES: u64 = 2 * 1024 * 1024;
+// Longest tool output handed back to the model, in characters
+const MAX_OUTPUT_CHARS: usize = 100_000;
+const MAX_WRITE_BYTES: usize = 1024 * 1024;
+const MAX_DIR_ENTRIES: usize = 500;
+const MAX_MATCHES: usize = 200;
+const MAX_FILES_SEARCHED: usize = 10_000;
+const MAX_MATCH_LINE_CHARS: usize = 300;
+// How much of a file is checked for NUL bytes
+const BINARY_SNIFF_BYTES: usize = 8000;
+// Build output and dependencies that would drown out real matches
+const SKIPPED_DIRS: &[&str] = &["node_modules", "target"];
+
+/// Folders the filesystem tools may touch, stored under `filesystem_access`
+/// in `config.json`. Nothing is reachable until a root is added.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct FilesystemAccess {
+    #[serde(default)]
+    pub roots: Vec<String>,
+    // `write_file` refuses to run unless this is set, and still asks first
+    #[serde(default)]
+    pub allow_write: bool,
+}
+
+impl FilesystemAccess {
+    pub fn from_config(config: &Value) -> Self {
+        match serde_json::from_value(config["filesystem_access"].clone()) {
+            Ok(access) => access,
+            Err(e) => {
+                if !config["filesystem_access"].is_null() {
+                    println!("Ignoring invalid filesystem_access: {}", e);
+                }
+                Self::default()
+            }
+        }
+    }
+
+    fn load(app_handle: &AppHandle) -> Result<Self, String> {
+        Ok(Self::from_config(&config::load_stored_keys(app_handle)?))
+    }
+
+    // Roots that still exist, with symlinks resolved so prefix checks hold
+    fn canonical_roots(&self) -> Result<Vec<PathBuf>, String> {
+        let roots: Vec<PathBuf> = self
+            .roots
+            .iter()
+            .filter_map(|root| match fs::canonicalize(root) {
+                Ok(root) => Some(root),
+                Err(e) => {
+                    println!("Skipping filesystem root {}: {}", root, e);
+                    None
+                }
+            })
+            .collect();
+
+        if roots.is_empty() {
+            Err("No folders have been shared. Add one in settings to use file tools.".to_string())
+        } else {
+            Ok(roots)
+        }
+    }
+
+    /// Resolves `path` to a real location inside one of the roots. Relative
+    /// paths are taken from the first root they exist under. With
+    /// `must_exist` unset only the parent folder has to exist, for writes.
+    fn resolve(&self, path: &str, must_exist: bool) -> Result<PathBuf, String> {
+        let roots = self.canonical_roots()?;
+        let requested = Path::new(path.trim());
+        let candidate = if requested.is_absolute() {
+            requested.to_path_buf()
+        } else {
+            roots
+                .iter()
+                .map(|root| root.join(requested))
+                .find(|candidate| candidate.exists())
+                .unwrap_or_else(|| roots[0].join(requested))
+        };
+
+        let resolved = if must_exist || candidate.exists() {
+            fs::canonicalize(&candidate)
+                .map_err(|e| format!("Can't open {}: {}", candidate.display(), e))?
+        } else {
+            // `exists` follows symlinks, so a dangling one looks like a free
+            // name but writing to it would create the file it points to
+            if fs::symlink_metadata(&candidate).is_ok() {
+                return Err(format!("{} is a symlink to a missing file", path));
+            }
+            let name = candidate
+                .file_name()
+                .filter(|name| *name != "..")
+                .ok_or_else(|| format!("Invalid file path: {}", path))?;
+            let parent = candidate.parent().unwrap_or(Path::new(""));
+            fs::canonicalize(parent)
+                .map_err(|e| format!("Can't open {}: {}", parent.display(), e))?
+                .join(name)
+        };
+
+        if roots.iter().any(|root| resolved.starts_with(root)) {
+            Ok(resolved)
+        } else {
+            Err(format!("{} is outside the shared folders", path))
+        }
+    }
+}
+
+fn arguments<T: DeserializeOwned>(arguments: Value) -> Result<T, String> {
+    serde_json::from_value(arguments).map_err(|e| format!("Invalid arguments: {}", e))
+}
+
+// File access blocks, so it runs off the async workers
+async fn blocking<T: Send + 'static>(
+    task: impl FnOnce() -> Result<T, String> + Send + 'static,
+) -> Result<T, String> {
+    tokio::task::spawn_blocking(task)
+        .await
+        .map_err(|e| format!("File task failed: {}", e))?
+}
+
+// Writes `content` without following a symlink at `path`, in case one was
+// put there after the path was resolved. New files are only ever created,
+// never opened.
+fn write_file(path: &Path, content: &[u8]) -> Result<(), String> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true);
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_symlink() => {
+            return Err(format!("{} is a symlink", path.display()));
+        }
+        Ok(_) => options.truncate(true),
+        Err(_) => options.create_new(true),
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.custom_flags(libc::O_NOFOLLOW);
+    }
+
+    let mut file = options
+        .open(path)
+        .map_err(|e| format!("Can't write {}: {}", path.display(), e))?;
+    file.write_all(content)
+        .map_err(|e| format!("Can't write {}: {}", path.display(), e))
+}
+
+fn is_binary(path: &Path, bytes: &[u8]) -> bool {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    files::is_binary_file(name) || bytes.iter().take(BINARY_SNIFF_BYTES).any(|&b| b == 0)
+}
+
+// Reads a text file, refusing anything too large or binary
+fn read_text(path: &Path) -> Result<String, String> {
+    let metadata =
+        fs::metadata(path).map_err(|e| format!("Can't read {}: {}", path.display(), e))?;
+    if !metadata.is_file() {
+        return Err(format!("{} is not a file", path.display()));
+    }
+    if is_binary(path, &[]) {
+        return Err(format!("{} is a binary file", path.display()));
+    }
+    if metadata.len() > MAX_FILE_BYTES {
+        return Err(format!(
+            "{} is too large to read ({} bytes, limit {})",
+            path.display(),
+            metadata.len(),
+            MAX_FILE_BYTES
+        ));
+    }
+
+    let bytes = fs::read(path).map_err(|e| format!("Can't read {}: {}", path.display(), e))?;
+    if is_binary(path, &bytes) {
+        return Err(format!("{} is a binary file", path.display()));
+    }
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn truncate(mut output: String, note: &str) -> String {
+    if let Some((end, _)) = output.char_indices().nth(MAX_OUTPUT_CHARS) {
+        output.truncate(end);
+        output.push_str("\n[Output truncated. ");
+        output.push_str(note);
+        output.push(']');
+    }
+    output
+}
+
+pub struct ListDir;
+
+#[derive(Deserialize)]
+struct ListDirArgs {
+    #[serde(default)]
+    path: Option<String>,
+}
+
+fn list_dir(access: &FilesystemAccess, path: Option<&str>) -> Result<String, String> {
+    let dir = match path.filter(|path| !path.trim().is_empty()) {
+        Some(path) => access.resolve(path, true)?,
+        // Without a path the shared folders themselves are listed
+        None => {
+            return Ok(access
+                .canonical_roots()?
+                .iter()
+                .map(|root| format!("{}/", root.display()))
+                .collect::<Vec<_>>()
+                .join("\n"))
+        }
+    };
+
+    let mut entries: Vec<String> = fs::read_dir(&dir)
+        .map_err(|e| format!("Can't list {}: {}", dir.display(), e))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            match entry.metadata() {
+                Ok(metadata) if metadata.is_dir() => format!("{}/", name),
+                Ok(metadata) => format!("{} ({} bytes)", name, metadata.len()),
+                Err(_) => name,
+            }
+        })
+        .collect();
+    entries.sort();
+
+    let total = entries.len();
+    entries.truncate(MAX_DIR_ENTRIES);
+    let mut output = format!("{}:\n{}", dir.display(), entries.join("\n"));
+    if total > MAX_DIR_ENTRIES {
+        output.push_str(&format!(
+            "\n[{} more entries not shown]",
+            total - MAX_DIR_ENTRIES
+        ));
+    }
+    Ok(output)
+}
+
+#[async_trait]
+impl Tool for ListDir {
+    fn name(&self) -> &str {
+        "list_dir"
+    }
+
+    fn description(&self) -> &str {
+        "Lists a folder the user has shared. Folders end with a slash and files show their size. Leave out the path to list the shared folders."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "path": {
+                    "type": "string",
+                    "description": "Folder to list, absolute or relative to a shared folder"
+                }
+            }
+        })
+    }
+
+    async fn invoke(&self, arguments: Value, context: &ToolContext) -> Result<String, String> {
+        let args: ListDirArgs = self::arguments(arguments)?;
+        let access = FilesystemAccess::load(&context.app_handle)?;
+        blocking(move || list_dir(&access, args.path.as_deref())).await
+    }
+}
+
+pub struct ReadFile;
+
+#[derive(Deserialize)]
+struct ReadFileArgs {
+    path: String,
+    #[serde(default)]
+    start_line: Option<usize>,
+    #[serde(default)]
+    end_line: Option<usize>,
+}
+
+fn read_file(access: &FilesystemAccess, args: &ReadFileArgs) -> Result<String, String> {
+    let path = access.resolve(&args.path, true)?;
+    let text = read_text(&path)?;
+
+    if args.start_line.is_none() && args.end_line.is_none() {
+        return Ok(truncate(text, "Pass start_line to read further."));
+    }
+
+    // Lines are numbered from 1 and the range is inclusive
+    let start = args.start_line.unwrap_or(1).max(1);
+    let end = args.end_line.unwrap_or(usize::MAX);
+    let lines: Vec<&str> = text
+        .lines()
+        .enumerate()
+        .skip(start - 1)
+        .take_while(|(index, _)| *index < end)
+        .map(|(_, line)| line)
+        .collect();
+    Ok(truncate(
+        lines.join("\n"),
+        "Pass a later start_line to read further.",
+    ))
+}
+
+#[async_trait]
+impl Tool for ReadFile {
+    fn name(&self) -> &str {
+        "read_file"
+    }
+
+    fn description(&self) -> &str {
+        "Reads a text file from a folder the user has shared. Large files can be read in parts with start_line and end_line."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "path": {
+                    "type": "string",
+                    "description": "File to read, absolute or relative to a shared folder"
+                },
+                "start_line": {
+                    "type": "integer",
+                    "description": "First line to return, counting from 1"
+                },
+                "end_line": {
+                    "type": "integer",
+                    "description": "Last line to return"
+                }
+            },
+            "required": ["path"]
+        })
+    }
+
+    async fn invoke(&self, arguments: Value, context: &ToolContext) -> Result<String, String> {
+        let args: ReadFileArgs = self::arguments(arguments)?;
+        let access = FilesystemAccess::load(&context.app_handle)?;
+        blocking(move || read_file(&access, &args)).await
+    }
+}
+
+pub struct SearchFiles;
+
+#[derive(Deserialize)]
+struct SearchFilesArgs {
+    pattern: String,
+    #[serde(default)]
+    path: Option<String>,
+    #[serde(default)]
+    case_sensitive: bool,
+}
+
+fn search_files(access: &FilesystemAccess, args: &SearchFilesArgs) -> Result<String, String> {
+    let pattern = RegexBuilder::new(&args.pattern)
+        .case_insensitive(!args.case_sensitive)
+        .build()
+        .map_err(|e| format!("Invalid pattern: {}", e))?;
+
+    let mut pending = match args.path.as_deref().filter(|path| !path.trim().is_empty()) {
+        Some(path) => vec![access.resolve(path, true)?],
+        None => access.canonical_roots()?,
+    };
+
+    let mut matches = Vec::new();
+    let mut searched = 0;
+    while let Some(path) = pending.pop() {
+        if matches.len() >= MAX_MATCHES || searched >= MAX_FILES_SEARCHED {
+            break;
+        }
+
+        if path.is_dir() {
+            let entries = match fs::read_dir(&path) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries.filter_map(|entry| entry.ok()) {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if name.starts_with('.') || SKIPPED_DIRS.contains(&name.as_str()) {
+                    continue;
+                }
+                // Symlinks could lead out of the shared folders
+                if matches!(entry.file_type(), Ok(kind) if !kind.is_symlink()) {
+                    pending.push(entry.path());
+                }
+            }
+            continue;
+        }
+
+        searched += 1;
+        let text = match read_text(&path) {
+            Ok(text) => text,
+            Err(_) => continue,
+        };
+        for (index, line) in text.lines().enumerate() {
+            if pattern.is_match(line) {
+                let line: String = line.trim().chars().take(MAX_MATCH_LINE_CHARS).collect();
+                matches.push(format!("{}:{}: {}", path.display(), index + 1, line));
+                if matches.len() >= MAX_MATCHES {
+                    break;
+                }
+            }
+        }
+    }
+
+    if matches.is_empty() {
+        return Ok(format!("No matches in {} files", searched));
+    }
+    let mut output = matches.join("\n");
+    if matches.len() >= MAX_MATCHES || searched >= MAX_FILES_SEARCHED {
+        output.push_str("\n[Search stopped early. Narrow the path or pattern for more.]");
+    }
+    Ok(truncate(output, "Narrow the path or pattern for more."))
+}
+
+#[async_trait]
+impl Tool for SearchFiles {
+    fn name(&self) -> &str {
+        "search_files"
+    }
+
+    fn description(&self) -> &str {
+        "Searches text files in the shared folders for lines matching a regular expression, like grep. Hidden folders, node_modules and target are skipped. Returns path:line: text for each match."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "pattern": {
+                    "type": "string",
+                    "description": "Regular expression to look for"
+                },
+                "path": {
+                    "type": "string",
+                    "description": "File or folder to search, all shared folders when left out"
+                },
+                "case_sensitive": {
+                    "type": "boolean",
+                    "description": "Match case exactly, off by default"
+                }
+            },
+            "required": ["pattern"]
+        })
+    }
+
+    async fn invoke(&self, arguments: Value, context: &ToolContext) -> Result<String, String> {
+        let args: SearchFilesArgs = self::arguments(arguments)?;
+        let access = FilesystemAccess::load(&context.app_handle)?;
+        blocking(move || search_files(&access, &args)).await
+    }
+}
+
+/// Writes only happen when `allow_write` is on and the user approves each
+/// one through a `tool-approval` event.
+pub struct WriteFile {
+    approvals: Arc<ToolApprovals>,
+}
+
+#[derive(Deserialize)]
+struct WriteFileArgs {
+    path: String,
+    content: String,
+}
+
+#[async_trait]
+impl Tool for WriteFile {
+    fn name(&self) -> &str {
+        "write_file"
+    }
+
+    fn description(&self) -> &str {
+        "Creates or overwrites a text file in a folder the user has shared. The user is asked to approve every write."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "path": {
+                    "type": "string",
+                    "description": "File to write, absolute or relative to a shared folder. Its folder must already exist."
+                },
+                "content": {
+                    "type": "string",
+                    "description": "Full new contents of the file"
+                }
+            },
+            "required": ["path", "content"]
+        })
+    }
+
+    async fn invoke(&self, arguments: Value, context: &ToolContext) -> Result<String, String> {
+        let args: WriteFileArgs = self::arguments(arguments)?;
+        let access = FilesystemAccess::load(&context.app_handle)?;
+        if !access.allow_write {
+            return Err("Writing files is turned off in settings".to_string());
+        }
+        if args.content.len() > MAX_WRITE_BYTES {
+            return Err(format!(
+                "Content is too large to write ({} bytes, limit {})",
+                args.content.len(),
+                MAX_WRITE_BYTES
+            ));
+        }
+
+        let path = access.resolve(&args.path, false)?;
+        if path.is_dir() {
+            return Err(format!("{} is a folder", path.display()));
+        }
+        let summary = format!(
+            "{} {} ({} bytes)",
+            if path.exists() { "Overwrite" } else { "Create" },
+            path.display(),
+            args.content.len()
+        );
+
+        let approved = self
+            .approvals
+            .request(
+                &context.app_handle,
+                ApprovalRequest {
+                    approval_id: uuid::Uuid::new_v4().to_string(),
+                    request_id: context.request_id.clone(),
+                    tool: self.name().to_string(),
+                    summary: summary.clone(),
+                    detail: Some(args.content.clone()),
+                },
+            )
+            .await;
+        if !approved {
+            return Err(format!("The user declined: {}", summary));
+        }
+
+        let written = format!("Wrote {} bytes to {}", args.content.len(), path.display());
+        blocking(move || write_file(&path, args.content.as_bytes())).await?;
+        println!("{}", written);
+        Ok(written)
+    }
+}
+
+/// Adds the filesystem tools to `registry`.
+pub fn register(registry: &ToolRegistry, approvals: Arc<ToolApprovals>) {
+    registry.register(Arc::new(ListDir));
+    registry.register(Arc::new(ReadFile));
+    registry.register(Arc::new(SearchFiles));
+    registry.register(Arc::new(WriteFile { approvals }));
+}
+
+#[tauri::command]
+pub fn get_filesystem_access(app_handle: AppHandle) -> Result<FilesystemAccess, String> {
+    Ok(FilesystemAccess::from_config(&config::load_stored_keys(
+        &app_handle,
+    )?))
+}
+
+/// Saves the shared folders. Each root has to be an existing folder and is
+/// stored with symlinks resolved.
+#[tauri::command]
+pub fn set_filesystem_access(
+    app_handle: AppHandle,
+    mut access: FilesystemAccess,
+) -> Result<(), String> {
+    access.roots = access
+        .roots
+        .iter()
+        .filter(|root| !root.trim().is_empty())
+        .map(|root| {
+            let path = fs::canonicalize(root.trim()).map_err(|e| format!("{}: {}", root, e))?;
+            if !path.is_dir() {
+                return Err(format!("{} is not a folder", root));
+            }
+            Ok(path.to_string_lossy().into_owned())
+        })
+        .collect::<Result<_, String>>()?;
+
+    let mut config = config::load_stored_keys(&app_handle)?;
+    let config_obj = config.as_object_mut().ok_or("Invalid stored keys format")?;
+
+    if access.roots.is_empty() && !access.allow_write {
+        config_obj.remove("filesystem_access");
+    } else {
+        config_obj.insert(
+            "filesystem_access".to_string(),
+            serde_json::to_value(&access).map_err(|e| e.to_string())?,
+        );
+    }
+    config::save_keys(&app_handle, &config)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    // A shared root next to a folder the tools must not reach
+    struct Scratch {
+        dir: PathBuf,
+    }
+
+    impl Scratch {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("corner-fs-{}", uuid::Uuid::new_v4()));
+            fs::create_dir_all(dir.join("root")).unwrap();
+            fs::create_dir_all(dir.join("outside")).unwrap();
+            Self {
+                dir: fs::canonicalize(dir).unwrap(),
+            }
+        }
+
+        fn root(&self) -> PathBuf {
+            self.dir.join("root")
+        }
+
+        fn outside(&self) -> PathBuf {
+            self.dir.join("outside")
+        }
+
+        fn access(&self) -> FilesystemAccess {
+            FilesystemAccess {
+                roots: vec![self.root().display().to_string()],
+                allow_write: true,
+            }
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn resolves_paths_inside_the_roots() {
+        let scratch = Scratch::new();
+        let access = scratch.access();
+        fs::write(scratch.root().join("notes.txt"), "hi").unwrap();
+
+        assert_eq!(
+            access.resolve("notes.txt", true).unwrap(),
+            scratch.root().join("notes.txt")
+        );
+        assert_eq!(
+            access.resolve("new.txt", false).unwrap(),
+            scratch.root().join("new.txt")
+        );
+        assert!(access.resolve("../outside/x.txt", false).is_err());
+        assert!(access
+            .resolve(
+                &scratch.outside().join("x.txt").display().to_string(),
+                false
+            )
+            .is_err());
+        assert!(access.resolve("missing.txt", true).is_err());
+    }
+
+    #[test]
+    fn refuses_dangling_symlinks_out_of_the_root() {
+        let scratch = Scratch::new();
+        let target = scratch.outside().join("escaped.txt");
+        symlink(&target, scratch.root().join("link.txt")).unwrap();
+
+        let error = scratch.access().resolve("link.txt", false).unwrap_err();
+        assert!(error.contains("symlink"), "{}", error);
+        assert!(!target.exists());
+    }
+
+    #[test]
+    fn refuses_symlinks_to_files_out_of_the_root() {
+        let scratch = Scratch::new();
+        let target = scratch.outside().join("secret.txt");
+        fs::write(&target, "secret").unwrap();
+        symlink(&target, scratch.root().join("link.txt")).unwrap();
+
+        assert!(scratch.access().resolve("link.txt", false).is_err());
+    }
+
+    #[test]
+    fn writes_never_follow_a_symlink() {
+        let scratch = Scratch::new();
+        let existing = scratch.outside().join("secret.txt");
+        fs::write(&existing, "secret").unwrap();
+        let missing = scratch.outside().join("escaped.txt");
+        // As if the links were swapped in after the path was resolved
+        symlink(&existing, scratch.root().join("existing.txt")).unwrap();
+        symlink(&missing, scratch.root().join("missing.txt")).unwrap();
+
+        assert!(write_file(&scratch.root().join("existing.txt"), b"changed").is_err());
+        assert!(write_file(&scratch.root().join("missing.txt"), b"changed").is_err());
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "secret");
+        assert!(!missing.exists());
+    }
+
+    #[test]
+    fn writes_create_and_overwrite_files() {
+        let scratch = Scratch::new();
+        let path = scratch.root().join("out.txt");
+
+        write_file(&path, b"first version").unwrap();
+        write_file(&path, b"second").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+    }
+}
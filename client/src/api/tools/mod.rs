@@ -0,0 +1,527 @@
+use crate::api::providers::{merge_consecutive, ChunkSink, Provider, ProviderError, StreamChunk};
+use crate::api::usage::UsageMeter;
+use crate::models::{
+    ApiResponse, ChatMessage, Role, SendMessageRequest, StreamEvent, ToolCall, ToolResult,
+    ToolSpec, Usage,
+};
+use approval::ToolApprovals;
+use async_trait::async_trait;
+use reqwest::Client;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use tauri::AppHandle;
+
+pub mod approval;
+pub mod filesystem;
+
+/// Model round trips allowed before the model has to answer without tools.
+pub const MAX_TOOL_STEPS: usize = 8;
+
+/// What a tool gets to know about the request that called it.
+pub struct ToolContext {
+    pub request_id: String,
+    pub app_handle: AppHandle,
+}
+
+/// Something a model can call while answering, such as reading a file.
+#[async_trait]
+pub trait Tool: Send + Sync {
+    /// Name the model calls the tool by. Letters, digits, `_` and `-` only.
+    fn name(&self) -> &str;
+
+    /// Tells the model what the tool does and when to use it.
+    fn description(&self) -> &str;
+
+    /// JSON Schema of the arguments object.
+    fn parameters(&self) -> Value;
+
+    /// Runs the tool. Errors are handed back to the model as the result so
+    /// it can correct itself.
+    async fn invoke(&self, arguments: Value, context: &ToolContext) -> Result<String, String>;
+
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: self.name().to_string(),
+            description: self.description().to_string(),
+            parameters: self.parameters(),
+        }
+    }
+}
+
+pub struct ToolRegistry {
+    tools: RwLock<HashMap<String, Arc<dyn Tool>>>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self {
+            tools: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// A registry with the built-in tools. Tools that need the user's
+    /// go-ahead ask through `approvals`.
+    pub fn with_builtin(approvals: Arc<ToolApprovals>) -> Self {
+        let registry = Self::new();
+        filesystem::register(&registry, approvals);
+        registry
+    }
+
+    pub fn register(&self, tool: Arc<dyn Tool>) {
+        if let Ok(mut tools) = self.tools.write() {
+            tools.insert(tool.name().to_string(), tool);
+        }
+    }
+
+    pub fn unregister(&self, name: &str) {
+        if let Ok(mut tools) = self.tools.write() {
+            tools.remove(name);
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn Tool>> {
+        self.tools.read().ok()?.get(name).cloned()
+    }
+
+    /// Specs of the registered tools named in `enabled`.
+    pub fn specs(&self, enabled: &[String]) -> Vec<ToolSpec> {
+        let tools = match self.tools.read() {
+            Ok(tools) => tools,
+            Err(_) => return Vec::new(),
+        };
+
+        let mut specs: Vec<ToolSpec> = tools
+            .values()
+            .filter(|tool| enabled.iter().any(|name| name == tool.name()))
+            .map(|tool| tool.spec())
+            .collect();
+        specs.sort_by(|a, b| a.name.cmp(&b.name));
+        specs
+    }
+
+    pub async fn invoke(&self, call: &ToolCall, context: &ToolContext) -> ToolResult {
+        let outcome = match self.get(&call.name) {
+            Some(tool) => tool.invoke(call.arguments.clone(), context).await,
+            None => Err(format!("Unknown tool: {}", call.name)),
+        };
+
+        let (content, is_error) = match outcome {
+            Ok(content) => (content, false),
+            Err(e) => (e, true),
+        };
+        ToolResult {
+            call_id: call.id.clone(),
+            name: call.name.clone(),
+            content,
+            is_error,
+        }
+    }
+}
+
+impl Default for ToolRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Sends `request`, running the tools the model calls and sending their
+/// results back until it answers. Only the tools named in `enabled_tools`
+/// are offered. After `MAX_TOOL_STEPS` round trips the tools are taken away
+/// so the model has to answer with what it has.
+///
+/// The text of every step ends up in the returned content, and usage is
+/// summed across steps on `spent`. Pass `on_chunk` to stream.
+#[allow(clippy::too_many_arguments)]
+pub async fn run(
+    provider: &dyn Provider,
+    client: &Client,
+    api_key: &str,
+    mut request: SendMessageRequest,
+    registry: &ToolRegistry,
+    context: &ToolContext,
+    spent: &UsageMeter,
+    on_chunk: Option<ChunkSink<'_>>,
+    on_event: &(dyn Fn(StreamEvent) + Send + Sync),
+) -> Result<ApiResponse, ProviderError> {
+    if provider.supports_tools() && request.offers_tools() {
+        request.tools = registry.specs(request.enabled_tools.as_deref().unwrap_or_default());
+    }
+    run_loop(
+        provider,
+        client,
+        api_key,
+        request,
+        |call| async move { registry.invoke(&call, context).await },
+        &context.request_id,
+        spent,
+        on_chunk,
+        on_event,
+    )
+    .await
+}
+
+// The loop behind `run`, with the tools called through `invoke`
+#[allow(clippy::too_many_arguments)]
+async fn run_loop<F, Fut>(
+    provider: &dyn Provider,
+    client: &Client,
+    api_key: &str,
+    mut request: SendMessageRequest,
+    invoke: F,
+    request_id: &str,
+    spent: &UsageMeter,
+    on_chunk: Option<ChunkSink<'_>>,
+    on_event: &(dyn Fn(StreamEvent) + Send + Sync),
+) -> Result<ApiResponse, ProviderError>
+where
+    F: Fn(ToolCall) -> Fut,
+    Fut: Future<Output = ToolResult>,
+{
+    if request.tools.is_empty() {
+        let response = match on_chunk {
+            Some(on_chunk) => provider.stream(client, api_key, &request, on_chunk).await?,
+            None => provider.complete(client, api_key, &request).await?,
+        };
+        if let Some(usage) = &response.usage {
+            spent.add(usage);
+        }
+        return Ok(response);
+    }
+
+    // Tool turns are appended to the history, so fix it in place first
+    request.messages = request.conversation();
+    request.params.system_prompt = None;
+
+    // Later steps start on a new paragraph, and usage is only sent once the
+    // total is known
+    let needs_break = AtomicBool::new(false);
+    let forward = |chunk: StreamChunk| {
+        let on_chunk = match on_chunk {
+            Some(on_chunk) => on_chunk,
+            None => return,
+        };
+        match chunk {
+            StreamChunk::Text(_) => {
+                if needs_break.swap(false, Ordering::SeqCst) {
+                    on_chunk(StreamChunk::Text("\n\n".to_string()));
+                }
+                on_chunk(chunk);
+            }
+            StreamChunk::Reasoning(_) => on_chunk(chunk),
+            StreamChunk::Usage(_) => {}
+        }
+    };
+
+    let mut texts: Vec<String> = Vec::new();
+    let mut reasoning: Vec<String> = Vec::new();
+    let mut step = 0;
+
+    loop {
+        step += 1;
+        if step > MAX_TOOL_STEPS {
+            println!("Tool step limit reached, asking for a final answer");
+            request.tools.clear();
+            request.messages = without_tool_turns(std::mem::take(&mut request.messages));
+        }
+
+        let mut response = match on_chunk {
+            Some(_) => provider.stream(client, api_key, &request, &forward).await?,
+            None => provider.complete(client, api_key, &request).await?,
+        };
+
+        if let Some(usage) = &response.usage {
+            spent.add(usage);
+        }
+        let content = response.content.take().unwrap_or_default();
+        if !content.is_empty() {
+            texts.push(content.clone());
+            needs_break.store(true, Ordering::SeqCst);
+        }
+        reasoning.extend(response.reasoning.take());
+
+        let calls = response.tool_calls.take().unwrap_or_default();
+        if calls.is_empty() || request.tools.is_empty() {
+            let usage = spent.total();
+            if let (Some(on_chunk), Some(usage)) = (on_chunk, &usage) {
+                on_chunk(StreamChunk::Usage(usage.clone()));
+            }
+            response.content = Some(texts.join("\n\n"));
+            response.usage = usage;
+            return Ok(response.with_reasoning(reasoning.join("\n\n")));
+        }
+
+        request.messages.push(ChatMessage {
+            thinking_blocks: std::mem::take(&mut response.thinking_blocks),
+            ..ChatMessage::tool_calls(content, calls.clone())
+        });
+        for call in calls {
+            println!("Calling tool {} with {}", call.name, call.arguments);
+            on_event(StreamEvent::ToolCall {
+                request_id: request_id.to_string(),
+                step,
+                call: call.clone(),
+            });
+
+            let result = invoke(call).await;
+            on_event(StreamEvent::ToolResult {
+                request_id: request_id.to_string(),
+                step,
+                result: result.clone(),
+            });
+            request.messages.push(ChatMessage::tool_result(result));
+        }
+    }
+}
+
+// Rewrites tool calls and results as plain turns for the last step, since
+// providers refuse tool turns in a request that doesn't declare any tools
+fn without_tool_turns(messages: Vec<ChatMessage>) -> Vec<ChatMessage> {
+    let mut messages: Vec<ChatMessage> = messages
+        .into_iter()
+        .map(|message| {
+            if let Some(result) = &message.tool_result {
+                let outcome = if result.is_error {
+                    "failed"
+                } else {
+                    "returned"
+                };
+                return ChatMessage::user(format!(
+                    "[Tool {} {}]\n{}",
+                    result.name, outcome, result.content
+                ));
+            }
+            if message.tool_calls.is_empty() {
+                return message;
+            }
+
+            let mut content = message.content.clone();
+            for call in &message.tool_calls {
+                if !content.is_empty() {
+                    content.push_str("\n\n");
+                }
+                content.push_str(&format!("[Called {} with {}]", call.name, call.arguments));
+            }
+            ChatMessage::new(Role::Assistant, content)
+        })
+        .collect();
+
+    messages.push(ChatMessage::user(
+        "No more tools can be called. Answer with what you have found so far.",
+    ));
+    merge_consecutive(messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::providers::anthropic;
+    use crate::models::ModelInfo;
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    // Calls a tool whenever it's offered one and keeps every request it got
+    #[derive(Default)]
+    struct LoopingProvider {
+        requests: Mutex<Vec<SendMessageRequest>>,
+        // Step that fails as if the server went quiet
+        fail_at: Option<usize>,
+    }
+
+    #[async_trait]
+    impl Provider for LoopingProvider {
+        fn id(&self) -> &str {
+            "looping"
+        }
+
+        fn display_name(&self) -> &str {
+            "Looping"
+        }
+
+        fn env_var(&self) -> &str {
+            "LOOPING_API_KEY"
+        }
+
+        fn supports_tools(&self) -> bool {
+            true
+        }
+
+        async fn complete(
+            &self,
+            _client: &Client,
+            _api_key: &str,
+            request: &SendMessageRequest,
+        ) -> Result<ApiResponse, ProviderError> {
+            let mut requests = self.requests.lock().unwrap();
+            requests.push(request.clone());
+            let step = requests.len();
+            if self.fail_at == Some(step) {
+                return Err(ProviderError::timed_out(
+                    "Looping",
+                    std::time::Duration::from_secs(1),
+                ));
+            }
+
+            let tool_calls = request.tools.first().map(|tool| {
+                vec![ToolCall {
+                    id: format!("call_{}", step),
+                    name: tool.name.clone(),
+                    arguments: json!({ "step": step }),
+                }]
+            });
+            Ok(ApiResponse {
+                content: Some(format!("Step {}", step)),
+                tool_calls,
+                usage: Some(Usage {
+                    input_tokens: Some(10),
+                    output_tokens: Some(1),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+        }
+
+        async fn list_models(
+            &self,
+            _client: &Client,
+            _api_key: &str,
+        ) -> Result<Vec<ModelInfo>, ProviderError> {
+            Ok(Vec::new())
+        }
+
+        async fn verify_key(&self, _client: &Client, _api_key: &str) -> Result<(), ProviderError> {
+            Ok(())
+        }
+    }
+
+    fn request() -> SendMessageRequest {
+        let mut request: SendMessageRequest = serde_json::from_value(json!({
+            "message": "Find it",
+            "model": "claude-sonnet-4",
+            "provider": "looping",
+            "enabled_tools": ["lookup"]
+        }))
+        .unwrap();
+        request.tools = vec![ToolSpec {
+            name: "lookup".to_string(),
+            description: "Looks something up".to_string(),
+            parameters: json!({ "type": "object" }),
+        }];
+        request
+    }
+
+    #[tokio::test]
+    async fn the_last_step_is_sent_without_tool_turns() {
+        let provider = LoopingProvider::default();
+        let invoked = Mutex::new(0);
+        let invoke = |call: ToolCall| {
+            *invoked.lock().unwrap() += 1;
+            async move {
+                ToolResult {
+                    call_id: call.id,
+                    name: call.name,
+                    content: "nothing yet".to_string(),
+                    is_error: false,
+                }
+            }
+        };
+
+        let response = run_loop(
+            &provider,
+            &Client::new(),
+            "",
+            request(),
+            invoke,
+            "request",
+            &UsageMeter::default(),
+            None,
+            &|_| {},
+        )
+        .await
+        .unwrap();
+
+        let requests = provider.requests.lock().unwrap();
+        assert_eq!(requests.len(), MAX_TOOL_STEPS + 1);
+        assert_eq!(*invoked.lock().unwrap(), MAX_TOOL_STEPS);
+        assert_eq!(
+            response.usage.unwrap().input_tokens,
+            Some(10 * (MAX_TOOL_STEPS as u64 + 1))
+        );
+        assert!(response
+            .content
+            .unwrap()
+            .ends_with(&format!("Step {}", MAX_TOOL_STEPS + 1)));
+
+        let last = requests.last().unwrap();
+        assert!(last.tools.is_empty());
+        assert!(last.messages.iter().all(|message| !message.has_tool_data()));
+        assert!(last
+            .messages
+            .last()
+            .unwrap()
+            .content
+            .contains("[Tool lookup returned]"));
+
+        // Anthropic rejects tool_use blocks in a request without tools
+        let body = anthropic::request_body(last, false).unwrap();
+        let roles: Vec<&str> = body["messages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|message| message["role"].as_str().unwrap())
+            .collect();
+        assert!(roles.windows(2).all(|pair| pair[0] != pair[1]));
+        assert!(!body.to_string().contains("tool_use"));
+        assert!(body.get("tools").is_none());
+    }
+
+    #[test]
+    fn tools_are_opt_in() {
+        let registry = ToolRegistry::new();
+        assert!(registry.specs(&[]).is_empty());
+
+        let mut request = request();
+        assert!(request.offers_tools());
+        request.enabled_tools = None;
+        assert!(!request.offers_tools());
+        request.enabled_tools = Some(Vec::new());
+        assert!(!request.offers_tools());
+    }
+
+    #[tokio::test]
+    async fn steps_already_paid_for_are_kept_when_a_later_one_fails() {
+        let provider = LoopingProvider {
+            fail_at: Some(3),
+            ..Default::default()
+        };
+        let invoke = |call: ToolCall| async move {
+            ToolResult {
+                call_id: call.id,
+                name: call.name,
+                content: "nothing yet".to_string(),
+                is_error: false,
+            }
+        };
+
+        let spent = UsageMeter::default();
+        let result = run_loop(
+            &provider,
+            &Client::new(),
+            "",
+            request(),
+            invoke,
+            "request",
+            &spent,
+            None,
+            &|_| {},
+        )
+        .await;
+
+        assert!(result.is_err());
+        let usage = spent.total().unwrap();
+        assert_eq!(usage.input_tokens, Some(20));
+        assert_eq!(usage.output_tokens, Some(2));
+    }
+}
@@ -0,0 +1,497 @@
+use crate::api::ApiState;
+use crate::config;
+use crate::models::Usage;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, State};
+
+// (provider, model prefix, input, output, cached input) in USD per million
+// tokens. Overridden by the `pricing` entries in `config.json`.
+const DEFAULT_PRICES: &[(&str, &str, f64, f64, Option<f64>)] = &[
+    ("anthropic", "claude-3-haiku", 0.25, 1.25, Some(0.03)),
+    ("anthropic", "claude-3-5-haiku", 0.8, 4.0, Some(0.08)),
+    ("anthropic", "claude-haiku-4-5", 1.0, 5.0, Some(0.1)),
+    ("anthropic", "claude-3-5-sonnet", 3.0, 15.0, Some(0.3)),
+    ("anthropic", "claude-3-7-sonnet", 3.0, 15.0, Some(0.3)),
+    ("anthropic", "claude-sonnet-4", 3.0, 15.0, Some(0.3)),
+    ("anthropic", "claude-3-opus", 15.0, 75.0, Some(1.5)),
+    ("anthropic", "claude-opus-4", 15.0, 75.0, Some(1.5)),
+    ("anthropic", "claude-opus-4-5", 5.0, 25.0, Some(0.5)),
+    ("openai", "gpt-3.5-turbo", 0.5, 1.5, None),
+    ("openai", "gpt-4", 30.0, 60.0, None),
+    ("openai", "gpt-4-32k", 60.0, 120.0, None),
+    ("openai", "gpt-4-turbo", 10.0, 30.0, None),
+    ("openai", "gpt-4-1106", 10.0, 30.0, None),
+    ("openai", "gpt-4-0125", 10.0, 30.0, None),
+    ("openai", "gpt-4.5", 75.0, 150.0, Some(37.5)),
+    ("openai", "gpt-4o", 2.5, 10.0, Some(1.25)),
+    ("openai", "gpt-4o-mini", 0.15, 0.6, Some(0.075)),
+    ("openai", "gpt-4.1", 2.0, 8.0, Some(0.5)),
+    ("openai", "gpt-4.1-mini", 0.4, 1.6, Some(0.1)),
+    ("openai", "gpt-4.1-nano", 0.1, 0.4, Some(0.025)),
+    ("openai", "gpt-5", 1.25, 10.0, Some(0.125)),
+    ("openai", "gpt-5-mini", 0.25, 2.0, Some(0.025)),
+    ("openai", "gpt-5-nano", 0.05, 0.4, Some(0.005)),
+    ("openai", "gpt-5-pro", 15.0, 120.0, None),
+    ("openai", "o1", 15.0, 60.0, Some(7.5)),
+    ("openai", "o1-mini", 1.1, 4.4, Some(0.55)),
+    ("openai", "o1-pro", 150.0, 600.0, None),
+    ("openai", "o3", 2.0, 8.0, Some(0.5)),
+    ("openai", "o3-mini", 1.1, 4.4, Some(0.55)),
+    ("openai", "o3-pro", 20.0, 80.0, None),
+    ("openai", "o4-mini", 1.1, 4.4, Some(0.275)),
+    ("google", "gemini-1.5-flash", 0.075, 0.3, None),
+    ("google", "gemini-1.5-flash-8b", 0.0375, 0.15, None),
+    ("google", "gemini-1.5-pro", 1.25, 5.0, None),
+    ("google", "gemini-2.0-flash", 0.1, 0.4, Some(0.025)),
+    ("google", "gemini-2.0-flash-lite", 0.075, 0.3, None),
+    ("google", "gemini-2.5-flash", 0.3, 2.5, Some(0.075)),
+    ("google", "gemini-2.5-flash-lite", 0.1, 0.4, Some(0.025)),
+    ("google", "gemini-2.5-pro", 1.25, 10.0, Some(0.31)),
+    ("google", "gemini-3-pro", 2.0, 12.0, Some(0.2)),
+    ("xai", "grok-beta", 5.0, 15.0, None),
+    ("xai", "grok-2", 2.0, 10.0, None),
+    ("xai", "grok-2-mini", 0.2, 1.0, None),
+    ("xai", "grok-3", 3.0, 15.0, Some(0.75)),
+    ("xai", "grok-3-mini", 0.3, 0.5, Some(0.075)),
+    ("xai", "grok-4", 3.0, 15.0, Some(0.75)),
+    ("xai", "grok-4-fast", 0.2, 0.5, Some(0.05)),
+    ("xai", "grok-code-fast", 0.2, 1.5, Some(0.02)),
+    ("perplexity", "llama-3.1-sonar-small", 0.2, 0.2, None),
+    ("perplexity", "llama-3.1-sonar-large", 1.0, 1.0, None),
+    ("perplexity", "llama-3.1-sonar-huge", 5.0, 5.0, None),
+    ("perplexity", "sonar", 1.0, 1.0, None),
+    ("perplexity", "sonar-pro", 3.0, 15.0, None),
+    ("perplexity", "sonar-reasoning", 1.0, 5.0, None),
+    ("perplexity", "sonar-reasoning-pro", 2.0, 8.0, None),
+    ("perplexity", "sonar-deep-research", 2.0, 8.0, None),
+    // Self-hosted models cost nothing per token
+    ("local", "", 0.0, 0.0, None),
+];
+
+// Anthropic bills prompt cache writes at a premium over plain input
+const ANTHROPIC_CACHE_WRITE_MULTIPLIER: f64 = 1.25;
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Price of a model in USD per million tokens.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ModelPrice {
+    pub provider: String,
+    // Applies to every model id starting with this, the longest match wins
+    pub model: String,
+    pub input: f64,
+    pub output: f64,
+    // Price of prompt cache hits, `input` when not set
+    #[serde(default)]
+    pub cached_input: Option<f64>,
+    // Price of prompt cache writes, `input` when not set
+    #[serde(default)]
+    pub cache_write: Option<f64>,
+}
+
+impl ModelPrice {
+    pub fn cost(&self, usage: &Usage) -> f64 {
+        let input = usage.input_tokens.unwrap_or(0);
+        let cached = usage.cached_tokens.unwrap_or(0).min(input);
+        let cache_write = usage.cache_write_tokens.unwrap_or(0).min(input - cached);
+        let output = usage.output_tokens.unwrap_or(0);
+
+        ((input - cached - cache_write) as f64 * self.input
+            + cached as f64 * self.cached_input.unwrap_or(self.input)
+            + cache_write as f64 * self.cache_write.unwrap_or(self.input)
+            + output as f64 * self.output)
+            / 1_000_000.0
+    }
+}
+
+/// The built-in prices with the user's `pricing` entries from `config.json`
+/// laid over them.
+pub fn pricing(config: &Value) -> Vec<ModelPrice> {
+    let mut prices: Vec<ModelPrice> = DEFAULT_PRICES
+        .iter()
+        .map(
+            |(provider, model, input, output, cached_input)| ModelPrice {
+                provider: provider.to_string(),
+                model: model.to_string(),
+                input: *input,
+                output: *output,
+                cached_input: *cached_input,
+                cache_write: (*provider == "anthropic")
+                    .then(|| input * ANTHROPIC_CACHE_WRITE_MULTIPLIER),
+            },
+        )
+        .collect();
+
+    let overrides = config["pricing"].as_array().cloned().unwrap_or_default();
+    for entry in overrides {
+        let price: ModelPrice = match serde_json::from_value(entry.clone()) {
+            Ok(price) => price,
+            Err(e) => {
+                println!("Skipping invalid price {}: {}", entry, e);
+                continue;
+            }
+        };
+        prices.retain(|existing| {
+            existing.provider != price.provider || existing.model != price.model
+        });
+        prices.push(price);
+    }
+
+    prices
+}
+
+pub fn price_for<'a>(
+    prices: &'a [ModelPrice],
+    provider: &str,
+    model: &str,
+) -> Option<&'a ModelPrice> {
+    prices
+        .iter()
+        .filter(|price| price.provider == provider && model.starts_with(&price.model))
+        .max_by_key(|price| price.model.len())
+}
+
+/// One provider call as stored in the ledger.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct UsageRecord {
+    // Unix time in seconds
+    pub timestamp: u64,
+    pub provider: String,
+    pub model: String,
+    pub request_id: String,
+    pub thread_id: Option<String>,
+    #[serde(flatten)]
+    pub usage: Usage,
+    // Missing when the model had no price at the time
+    pub cost: Option<f64>,
+}
+
+impl UsageRecord {
+    pub fn new(provider: &str, model: &str, request_id: &str, usage: Usage) -> Self {
+        Self {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|now| now.as_secs())
+                .unwrap_or_default(),
+            provider: provider.to_string(),
+            model: model.to_string(),
+            request_id: request_id.to_string(),
+            thread_id: None,
+            usage,
+            cost: None,
+        }
+    }
+}
+
+/// Usage of a request summed as each of its calls finishes, so what was
+/// spent is still known when a later call fails or the request is cancelled.
+#[derive(Default)]
+pub struct UsageMeter {
+    total: Mutex<Option<Usage>>,
+}
+
+impl UsageMeter {
+    pub fn add(&self, usage: &Usage) {
+        if let Ok(mut total) = self.total.lock() {
+            total.get_or_insert_with(Usage::default).add(usage);
+        }
+    }
+
+    pub fn total(&self) -> Option<Usage> {
+        self.total.lock().ok().and_then(|total| total.clone())
+    }
+}
+
+/// Append-only log of every provider call, one JSON record per line in
+/// `~/.corner/usage.jsonl`.
+#[derive(Default)]
+pub struct UsageLedger {
+    // Keeps concurrent requests from interleaving their lines
+    lock: Mutex<()>,
+}
+
+impl UsageLedger {
+    fn path() -> Result<PathBuf, String> {
+        let home_dir = dirs::home_dir().ok_or("Could not find home directory")?;
+        Ok(home_dir.join(".corner").join("usage.jsonl"))
+    }
+
+    pub fn append(&self, record: &UsageRecord) -> Result<(), String> {
+        let path = Self::path()?;
+        let line = serde_json::to_string(record).map_err(|e| e.to_string())?;
+        let _guard = self.lock.lock().map_err(|e| e.to_string())?;
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| e.to_string())?;
+        writeln!(file, "{}", line).map_err(|e| e.to_string())
+    }
+
+    /// Records with a timestamp in `since..until`. Lines that don't parse
+    /// are skipped.
+    pub fn records(
+        &self,
+        since: Option<u64>,
+        until: Option<u64>,
+    ) -> Result<Vec<UsageRecord>, String> {
+        let path = Self::path()?;
+        let _guard = self.lock.lock().map_err(|e| e.to_string())?;
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let content = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+        Ok(content
+            .lines()
+            .filter_map(|line| serde_json::from_str::<UsageRecord>(line).ok())
+            .filter(|record| since.map_or(true, |since| record.timestamp >= since))
+            .filter(|record| until.map_or(true, |until| record.timestamp < until))
+            .collect())
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum UsageGrouping {
+    Day,
+    Model,
+    Thread,
+}
+
+/// Totals for one day, model or thread.
+#[derive(Serialize, Default, Debug)]
+pub struct UsageTotal {
+    // `YYYY-MM-DD`, `provider/model`, or the thread id. Requests sent
+    // outside a thread are grouped under no key.
+    pub key: Option<String>,
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cached_tokens: u64,
+    pub cache_write_tokens: u64,
+    pub reasoning_tokens: u64,
+    pub cost: f64,
+    // Requests left out of `cost` because their model had no price
+    pub unpriced_requests: u64,
+}
+
+// Civil date for a count of days since 1970-01-01
+pub(crate) fn date_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// `YYYY-MM-DD` of a unix timestamp, shifted by `utc_offset` minutes.
+pub fn day_key(timestamp: u64, utc_offset: i64) -> String {
+    let local = timestamp as i64 + utc_offset * 60;
+    let (year, month, day) = date_from_days(local.div_euclid(SECONDS_PER_DAY));
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+pub fn summarize(
+    records: &[UsageRecord],
+    group_by: UsageGrouping,
+    utc_offset: i64,
+) -> Vec<UsageTotal> {
+    let mut totals: BTreeMap<Option<String>, UsageTotal> = BTreeMap::new();
+
+    for record in records {
+        let key = match group_by {
+            UsageGrouping::Day => Some(day_key(record.timestamp, utc_offset)),
+            UsageGrouping::Model => Some(format!("{}/{}", record.provider, record.model)),
+            UsageGrouping::Thread => record.thread_id.clone(),
+        };
+
+        let total = totals.entry(key.clone()).or_insert_with(|| UsageTotal {
+            key,
+            ..Default::default()
+        });
+        total.requests += 1;
+        total.input_tokens += record.usage.input_tokens.unwrap_or(0);
+        total.output_tokens += record.usage.output_tokens.unwrap_or(0);
+        total.cached_tokens += record.usage.cached_tokens.unwrap_or(0);
+        total.cache_write_tokens += record.usage.cache_write_tokens.unwrap_or(0);
+        total.reasoning_tokens += record.usage.reasoning_tokens.unwrap_or(0);
+        match record.cost {
+            Some(cost) => total.cost += cost,
+            None => total.unpriced_requests += 1,
+        }
+    }
+
+    totals.into_values().collect()
+}
+
+/// Aggregates the ledger by day, model or thread. `since` and `until` are
+/// unix timestamps in seconds, `utc_offset` the local time zone in minutes
+/// east of UTC so days match the user's calendar.
+#[tauri::command]
+pub fn usage_summary(
+    group_by: UsageGrouping,
+    since: Option<u64>,
+    until: Option<u64>,
+    utc_offset: Option<i64>,
+    state: State<'_, ApiState>,
+) -> Result<Vec<UsageTotal>, String> {
+    let records = state.usage.records(since, until)?;
+    Ok(summarize(&records, group_by, utc_offset.unwrap_or(0)))
+}
+
+#[tauri::command]
+pub fn get_pricing(app_handle: AppHandle) -> Result<Vec<ModelPrice>, String> {
+    Ok(pricing(&config::load_stored_keys(&app_handle)?))
+}
+
+/// Stores `prices` as the user's overrides of the built-in table. Already
+/// recorded costs are left as they were.
+#[tauri::command]
+pub fn set_pricing(app_handle: AppHandle, prices: Vec<ModelPrice>) -> Result<(), String> {
+    let mut config = config::load_stored_keys(&app_handle)?;
+    let config_obj = config.as_object_mut().ok_or("Invalid stored keys format")?;
+
+    if prices.is_empty() {
+        config_obj.remove("pricing");
+    } else {
+        config_obj.insert(
+            "pricing".to_string(),
+            serde_json::to_value(prices).map_err(|e| e.to_string())?,
+        );
+    }
+    config::save_keys(&app_handle, &config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn usage(input: u64, cached: u64, cache_write: u64, output: u64) -> Usage {
+        Usage {
+            input_tokens: Some(input),
+            output_tokens: Some(output),
+            cached_tokens: Some(cached),
+            cache_write_tokens: Some(cache_write),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn the_longest_prefix_wins() {
+        let prices = pricing(&json!({}));
+        let model = |provider: &str, model: &str| {
+            price_for(&prices, provider, model).map(|price| price.model.as_str())
+        };
+        assert_eq!(model("openai", "o1-mini-2024-09-12"), Some("o1-mini"));
+        assert_eq!(model("openai", "o1-2024-12-17"), Some("o1"));
+        assert_eq!(model("openai", "gpt-4-0613"), Some("gpt-4"));
+        assert_eq!(model("openai", "gpt-4o-mini"), Some("gpt-4o-mini"));
+        assert_eq!(model("xai", "grok-3-mini-beta"), Some("grok-3-mini"));
+        assert_eq!(model("openai", "gpt-5-2025-08-07"), Some("gpt-5"));
+        assert_eq!(model("openai", "gpt-5-mini"), Some("gpt-5-mini"));
+        assert_eq!(model("xai", "grok-4-0709"), Some("grok-4"));
+        assert_eq!(
+            model("anthropic", "claude-haiku-4-5-20251001"),
+            Some("claude-haiku-4-5")
+        );
+        assert_eq!(model("local", "llama3"), Some(""));
+        assert_eq!(model("anthropic", "claude-2.1"), None);
+    }
+
+    #[test]
+    fn config_prices_replace_the_defaults() {
+        let config = json!({ "pricing": [
+            { "provider": "openai", "model": "gpt-4o", "input": 1.0, "output": 2.0 },
+            { "provider": "mistral", "model": "mistral-large", "input": 2.0, "output": 6.0 },
+            { "provider": "broken" }
+        ]});
+        let prices = pricing(&config);
+        let gpt = price_for(&prices, "openai", "gpt-4o-2024-08-06").unwrap();
+        assert_eq!((gpt.input, gpt.cached_input), (1.0, None));
+        assert!(price_for(&prices, "mistral", "mistral-large-latest").is_some());
+        assert!(price_for(&prices, "broken", "").is_none());
+    }
+
+    #[test]
+    fn cache_reads_and_writes_are_priced_apart() {
+        let prices = pricing(&json!({}));
+        let sonnet = price_for(&prices, "anthropic", "claude-sonnet-4-20250514").unwrap();
+        // 1M input of which 400k read from and 200k written to the cache
+        let cost = sonnet.cost(&usage(1_000_000, 400_000, 200_000, 100_000));
+        let expected = 0.4 * 3.0 + 0.4 * 0.3 + 0.2 * 3.75 + 0.1 * 15.0;
+        assert!((cost - expected).abs() < 1e-9);
+
+        // OpenAI has no write premium
+        let gpt = price_for(&prices, "openai", "gpt-4o").unwrap();
+        let cost = gpt.cost(&usage(1_000_000, 0, 1_000_000, 0));
+        assert!((cost - 2.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn dates_follow_the_civil_calendar() {
+        assert_eq!(date_from_days(0), (1970, 1, 1));
+        assert_eq!(date_from_days(-1), (1969, 12, 31));
+        assert_eq!(date_from_days(11_016), (2000, 2, 29));
+        assert_eq!(date_from_days(19_782), (2024, 2, 29));
+        assert_eq!(date_from_days(20_818), (2026, 12, 31));
+    }
+
+    #[test]
+    fn day_keys_use_the_local_offset() {
+        // 2024-03-01 23:30 UTC
+        let timestamp = 1_709_335_800;
+        assert_eq!(day_key(timestamp, 0), "2024-03-01");
+        assert_eq!(day_key(timestamp, 60), "2024-03-02");
+        assert_eq!(day_key(timestamp, -600), "2024-03-01");
+    }
+
+    #[test]
+    fn summaries_count_unpriced_requests() {
+        let record = |model: &str, cost: Option<f64>| UsageRecord {
+            timestamp: 1_709_335_800,
+            cost,
+            ..UsageRecord::new("openai", model, "r", usage(100, 10, 0, 20))
+        };
+        let records = [
+            record("gpt-4o", Some(0.5)),
+            record("gpt-4o", Some(0.25)),
+            record("ft:custom", None),
+        ];
+
+        let totals = summarize(&records, UsageGrouping::Model, 0);
+        assert_eq!(totals.len(), 2);
+        assert_eq!(totals[0].key.as_deref(), Some("openai/ft:custom"));
+        assert_eq!(totals[0].unpriced_requests, 1);
+        assert_eq!(totals[1].requests, 2);
+        assert_eq!(totals[1].input_tokens, 200);
+        assert_eq!(totals[1].cached_tokens, 20);
+        assert!((totals[1].cost - 0.75).abs() < 1e-9);
+
+        let days = summarize(&records, UsageGrouping::Day, 0);
+        assert_eq!(days.len(), 1);
+        assert_eq!(days[0].requests, 3);
+    }
+}
@@ -1,9 +1,14 @@
+use crate::api::providers::custom::{CustomProvider, CustomProviderConfig};
+use crate::api::providers::local::{LocalProvider, DEFAULT_LOCAL_BASE_URL};
+use crate::api::ApiState;
 use dirs;
 use serde::{Deserialize, Serialize};
 use serde_json;
+use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
-use tauri::AppHandle;
+use std::sync::Arc;
+use tauri::{AppHandle, State};
 
 pub fn get_config_path(_app: &AppHandle) -> Result<PathBuf, String> {
     let app_dir = match dirs::config_dir() {
@@ -86,6 +91,8 @@ pub struct StoredApiKeys {
     pub xai: Option<String>,
     pub google: Option<String>,
     pub elevenlabs: Option<String>,
+    // Keys for the providers declared in `custom_providers`, by id
+    pub custom: HashMap<String, Option<String>>,
 }
 
 #[tauri::command]
@@ -117,6 +124,13 @@ pub fn get_stored_api_keys(app_handle: AppHandle) -> Result<StoredApiKeys, Strin
             .get("elevenlabs")
             .and_then(|v| v.as_str())
             .map(String::from),
+        custom: custom_providers(&stored_json)
+            .into_iter()
+            .map(|provider| {
+                let key = stored_key(&stored_json, &provider.id);
+                (provider.id, key)
+            })
+            .collect(),
     };
 
     Ok(stored_keys)
@@ -128,10 +142,21 @@ pub fn store_api_key(app_handle: AppHandle, request: StoreApiKeyRequest) -> Resu
     let key = request.key;
 
     let mut stored_keys = load_stored_keys(&app_handle)?;
+    let is_custom = is_custom_provider(&stored_keys, &provider);
     let stored_obj = stored_keys
         .as_object_mut()
         .ok_or("Invalid stored keys format")?;
-    stored_obj.insert(provider, serde_json::Value::String(key));
+
+    if is_custom {
+        let custom_keys = stored_obj
+            .entry("custom_keys")
+            .or_insert_with(|| serde_json::json!({}))
+            .as_object_mut()
+            .ok_or("Invalid custom keys format")?;
+        custom_keys.insert(provider, serde_json::Value::String(key));
+    } else {
+        stored_obj.insert(provider, serde_json::Value::String(key));
+    }
 
     save_keys(&app_handle, &stored_keys)
 }
@@ -208,3 +233,168 @@ pub async fn get_config(app_handle: AppHandle) -> Result<serde_json::Value, Stri
 pub async fn set_config(app_handle: AppHandle, config: serde_json::Value) -> Result<(), String> {
     save_keys(&app_handle, &config)
 }
+
+/// Base URL of the local model server, stored as `local_base_url`.
+pub fn local_base_url(config: &serde_json::Value) -> String {
+    config["local_base_url"]
+        .as_str()
+        .filter(|url| !url.trim().is_empty())
+        .unwrap_or(DEFAULT_LOCAL_BASE_URL)
+        .to_string()
+}
+
+#[tauri::command]
+pub fn get_local_base_url(app_handle: AppHandle) -> Result<String, String> {
+    Ok(local_base_url(&load_stored_keys(&app_handle)?))
+}
+
+#[tauri::command]
+pub fn set_local_base_url(
+    app_handle: AppHandle,
+    state: State<'_, ApiState>,
+    base_url: String,
+) -> Result<(), String> {
+    let mut config = load_stored_keys(&app_handle)?;
+    let config_obj = config.as_object_mut().ok_or("Invalid stored keys format")?;
+
+    if base_url.trim().is_empty() {
+        config_obj.remove("local_base_url");
+    } else {
+        config_obj.insert(
+            "local_base_url".to_string(),
+            serde_json::Value::String(base_url.trim().to_string()),
+        );
+    }
+    save_keys(&app_handle, &config)?;
+
+    let provider = LocalProvider::new(&local_base_url(&config));
+    println!("Local model server set to {}", provider.base_url());
+    state.providers.register(Arc::new(provider));
+    state.models.invalidate("local");
+    Ok(())
+}
+
+// Ids a custom provider can't take: the built-in providers, the names older
+// frontends use for them and the other top level sections of config.json
+const RESERVED_PROVIDER_IDS: &[&str] = &[
+    "anthropic",
+    "perplexity",
+    "openai",
+    "xai",
+    "google",
+    "elevenlabs",
+    "local",
+    "grok",
+    "gemini",
+    "ollama",
+    "http",
+    "budgets",
+    "router",
+    "pricing",
+    "fallback_chains",
+    "filesystem_access",
+    "mcp_servers",
+    "local_base_url",
+    "custom_providers",
+    "custom_keys",
+];
+
+/// The key saved for `provider`. Built-in providers keep theirs at the top
+/// level of `config.json`, custom ones under `custom_keys`.
+pub fn stored_key(config: &serde_json::Value, provider: &str) -> Option<String> {
+    let key = if is_custom_provider(config, provider) {
+        &config["custom_keys"][provider]
+    } else {
+        &config[provider]
+    };
+    key.as_str().filter(|key| !key.is_empty()).map(String::from)
+}
+
+fn is_custom_provider(config: &serde_json::Value, id: &str) -> bool {
+    custom_providers(config)
+        .iter()
+        .any(|provider| provider.id == id)
+}
+
+/// Providers declared under `custom_providers`. Entries that don't parse or
+/// use a reserved id are logged and skipped so one typo doesn't hide the
+/// rest.
+pub fn custom_providers(config: &serde_json::Value) -> Vec<CustomProviderConfig> {
+    let entries = match config["custom_providers"].as_array() {
+        Some(entries) => entries,
+        None => return Vec::new(),
+    };
+
+    entries
+        .iter()
+        .filter_map(
+            |entry| match serde_json::from_value::<CustomProviderConfig>(entry.clone()) {
+                Ok(provider) if RESERVED_PROVIDER_IDS.contains(&provider.id.as_str()) => {
+                    println!("Skipping custom provider with reserved id {}", provider.id);
+                    None
+                }
+                Ok(provider) => Some(provider),
+                Err(e) => {
+                    println!("Skipping invalid custom provider {}: {}", entry, e);
+                    None
+                }
+            },
+        )
+        .collect()
+}
+
+/// Adds the custom providers from `config` to the registry. Ids that are
+/// reserved or already taken by another custom provider are skipped.
+pub fn register_custom_providers(state: &ApiState, config: &serde_json::Value) {
+    for provider in custom_providers(config) {
+        if state.providers.get(&provider.id).is_some() {
+            println!(
+                "Custom provider {} clashes with an existing provider",
+                provider.id
+            );
+            continue;
+        }
+
+        println!("Registering custom provider: {}", provider.id);
+        state
+            .providers
+            .register(Arc::new(CustomProvider::new(provider)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config() -> serde_json::Value {
+        json!({
+            "openai": "sk-openai",
+            "router": "not a key",
+            "custom_providers": [
+                { "id": "together", "base_url": "https://api.together.xyz/v1" },
+                { "id": "router", "base_url": "http://localhost:4000" },
+                { "id": "openai", "base_url": "http://localhost:4001" },
+                { "base_url": "http://localhost:4002" }
+            ],
+            "custom_keys": { "together": "tg-key", "router": "lite-key" }
+        })
+    }
+
+    #[test]
+    fn reserved_and_invalid_custom_providers_are_skipped() {
+        let ids: Vec<String> = custom_providers(&config())
+            .into_iter()
+            .map(|provider| provider.id)
+            .collect();
+        assert_eq!(ids, ["together"]);
+    }
+
+    #[test]
+    fn custom_keys_are_kept_apart_from_builtin_ones() {
+        let config = config();
+        assert_eq!(stored_key(&config, "together").as_deref(), Some("tg-key"));
+        assert_eq!(stored_key(&config, "openai").as_deref(), Some("sk-openai"));
+        assert_eq!(stored_key(&config, "anthropic"), None);
+    }
+}
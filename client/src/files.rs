@@ -1,5 +1,13 @@
 use base64;
+use base64::Engine;
 use mime_guess;
+use once_cell::sync::Lazy;
+use pdf_extract::{Document, Object, PlainTextOutput};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::panic::{self, AssertUnwindSafe};
 use std::path::PathBuf;
 use tokio::fs;
 
@@ -77,7 +85,7 @@ async fn read_and_encode_file(path: &PathBuf) -> Result<String, String> {
     }
 }
 
-fn is_binary_file(file_name: &str) -> bool {
+pub(crate) fn is_binary_file(file_name: &str) -> bool {
     is_image_file(file_name) || is_pdf_file(file_name) || is_epub_file(file_name)
 }
 
@@ -116,3 +124,647 @@ fn is_pdf_file(file_name: &str) -> bool {
 fn is_epub_file(file_name: &str) -> bool {
     file_name.to_lowercase().ends_with(".epub")
 }
+
+/// A file dropped into the chat, as sent back by the frontend in
+/// `SendMessageRequest::file_content`. Binary files arrive as the data URLs
+/// built by `read_and_encode_file`, everything else as plain text.
+pub enum Attachment {
+    Text {
+        name: String,
+        content: String,
+    },
+    Binary {
+        name: String,
+        mime_type: String,
+        data: String,
+        chapters: Option<ChapterRange>,
+        // Text of a PDF or EPUB once `extract_text` has read it
+        text: Option<Result<String, String>>,
+    },
+}
+
+impl Attachment {
+    pub fn from_content(name: &str, content: &str) -> Self {
+        if let Some((mime_type, data)) = parse_data_url(content) {
+            Attachment::Binary {
+                name: name.to_string(),
+                mime_type,
+                data: data.to_string(),
+                chapters: None,
+                text: None,
+            }
+        } else {
+            Attachment::Text {
+                name: name.to_string(),
+                content: content.to_string(),
+            }
+        }
+    }
+
+    /// Only sends part of an EPUB. Ignored for every other kind of file.
+    pub fn with_chapters(mut self, range: Option<ChapterRange>) -> Self {
+        if let Attachment::Binary { chapters, .. } = &mut self {
+            *chapters = range;
+        }
+        self
+    }
+
+    /// Uses text extracted earlier by `extract_text` instead of reading
+    /// the file again.
+    pub fn with_text(mut self, extracted: Option<Result<String, String>>) -> Self {
+        if let Attachment::Binary { text, .. } = &mut self {
+            *text = extracted;
+        }
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Attachment::Text { name, .. } | Attachment::Binary { name, .. } => name,
+        }
+    }
+
+    pub fn mime_type(&self) -> &str {
+        match self {
+            Attachment::Text { .. } => "text/plain",
+            Attachment::Binary { mime_type, .. } => mime_type,
+        }
+    }
+
+    /// Image formats every vision capable provider accepts.
+    pub fn is_image(&self) -> bool {
+        matches!(
+            self.mime_type(),
+            "image/jpeg" | "image/png" | "image/gif" | "image/webp"
+        )
+    }
+
+    pub fn is_pdf(&self) -> bool {
+        self.mime_type() == "application/pdf"
+    }
+
+    pub fn is_epub(&self) -> bool {
+        self.mime_type() == "application/epub+zip"
+    }
+
+    /// Plain text for models that can't read the file natively. `None` when
+    /// there is no way to get text out of this kind of file.
+    pub fn to_text(&self) -> Result<Option<String>, String> {
+        match self {
+            Attachment::Text { content, .. } => Ok(Some(content.clone())),
+            Attachment::Binary {
+                text: Some(text), ..
+            } => text.clone().map(Some),
+            Attachment::Binary { data, chapters, .. } if self.is_pdf() || self.is_epub() => {
+                document_text(self.name(), self.is_pdf(), data, *chapters).map(Some)
+            }
+            Attachment::Binary { .. } => Ok(None),
+        }
+    }
+
+    /// Reads the text of a PDF or EPUB on the blocking pool, so it's parsed
+    /// once per message rather than on every `to_text`. `None` for other
+    /// files and when the text is already there.
+    pub async fn extract_text(&self) -> Option<Result<String, String>> {
+        let (name, data, chapters) = match self {
+            Attachment::Binary {
+                name,
+                data,
+                chapters,
+                text: None,
+                ..
+            } if self.is_pdf() || self.is_epub() => (name.clone(), data.clone(), *chapters),
+            _ => return None,
+        };
+        let is_pdf = self.is_pdf();
+        let task =
+            tokio::task::spawn_blocking(move || document_text(&name, is_pdf, &data, chapters));
+        Some(
+            task.await
+                .unwrap_or_else(|e| Err(format!("Failed to read {}: {}", self.name(), e))),
+        )
+    }
+
+    pub fn data_url(&self) -> Option<String> {
+        match self {
+            Attachment::Text { .. } => None,
+            Attachment::Binary {
+                mime_type, data, ..
+            } => Some(format!("data:{};base64,{}", mime_type, data)),
+        }
+    }
+}
+
+fn document_text(
+    name: &str,
+    is_pdf: bool,
+    data: &str,
+    chapters: Option<ChapterRange>,
+) -> Result<String, String> {
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .map_err(|e| format!("Failed to decode {}: {}", name, e))?;
+    catch_panic(name, || {
+        if is_pdf {
+            Ok(extract_pdf_text(&bytes)?.to_text())
+        } else {
+            Ok(extract_epub(&bytes)?.to_text(chapters))
+        }
+    })
+}
+
+// The PDF and EPUB parsers can panic on malformed files instead of
+// returning an error
+fn catch_panic<T>(name: &str, extract: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(extract))
+        .unwrap_or_else(|_| Err(format!("Failed to read {}: the file is malformed", name)))
+}
+
+// Splits `data:<mime>;base64,<data>` into its mime type and payload
+fn parse_data_url(content: &str) -> Option<(String, &str)> {
+    let rest = content.strip_prefix("data:")?;
+    let (mime_type, data) = rest.split_once(";base64,")?;
+    Some((mime_type.to_string(), data))
+}
+
+/// Text pulled out of a PDF, one entry per page.
+#[derive(Serialize)]
+pub struct PdfDocument {
+    pub title: Option<String>,
+    pub page_count: usize,
+    pub pages: Vec<String>,
+}
+
+impl PdfDocument {
+    /// Joins the pages with `[Page n]` markers so answers can point back to
+    /// where they found something.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        if let Some(title) = &self.title {
+            text.push_str(&format!("Title: {}\n", title));
+        }
+        text.push_str(&format!("Pages: {}\n", self.page_count));
+
+        for (index, page) in self.pages.iter().enumerate() {
+            text.push_str(&format!("\n[Page {}]\n{}\n", index + 1, page.trim()));
+        }
+        text
+    }
+}
+
+pub async fn read_pdf(path: String) -> Result<PdfDocument, String> {
+    let bytes = fs::read(&path)
+        .await
+        .map_err(|e| format!("Failed to read file: {} (path: {})", e, path))?;
+
+    // Extraction is CPU bound and can take a while on long documents
+    tokio::task::spawn_blocking(move || catch_panic("PDF", || extract_pdf_text(&bytes)))
+        .await
+        .map_err(|e| format!("PDF extraction failed: {}", e))?
+}
+
+pub fn extract_pdf_text(bytes: &[u8]) -> Result<PdfDocument, String> {
+    let document = Document::load_mem(bytes).map_err(|e| format!("Failed to read PDF: {}", e))?;
+    if document.is_encrypted() {
+        return Err("Encrypted PDFs are not supported".to_string());
+    }
+
+    let page_count = document.get_pages().len();
+    let mut pages = Vec::with_capacity(page_count);
+    for page_num in 1..=page_count as u32 {
+        let mut text = String::new();
+        // A page we can't decode is left empty rather than failing the file
+        let mut output = PlainTextOutput::new(&mut text);
+        if let Err(e) = pdf_extract::output_doc_page(&document, &mut output, page_num) {
+            println!("Failed to extract text from page {}: {}", page_num, e);
+        }
+        pages.push(text);
+    }
+
+    Ok(PdfDocument {
+        title: pdf_title(&document),
+        page_count,
+        pages,
+    })
+}
+
+fn pdf_title(document: &Document) -> Option<String> {
+    let info = document.trailer.get(b"Info").ok()?;
+    let info = match info {
+        Object::Reference(id) => document.get_dictionary(*id).ok()?,
+        Object::Dictionary(dict) => dict,
+        _ => return None,
+    };
+    let title = decode_pdf_string(info.get(b"Title").ok()?.as_str().ok()?);
+    let title = title.trim();
+    (!title.is_empty()).then(|| title.to_string())
+}
+
+// Info strings are either UTF-16BE with a BOM or single byte PDFDocEncoding
+fn decode_pdf_string(bytes: &[u8]) -> String {
+    match bytes.strip_prefix(&[0xFE, 0xFF]) {
+        Some(utf16) => {
+            let units: Vec<u16> = utf16
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        None => bytes.iter().map(|&byte| byte as char).collect(),
+    }
+}
+
+/// A book read from an EPUB, one entry per document in the reading order.
+#[derive(Serialize)]
+pub struct EpubDocument {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub chapters: Vec<EpubChapter>,
+}
+
+#[derive(Serialize)]
+pub struct EpubChapter {
+    pub title: String,
+    pub text: String,
+}
+
+/// 1-based, inclusive range of chapters to attach.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct ChapterRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl EpubDocument {
+    /// The book as plain text with a heading per chapter, limited to `range`
+    /// when one is given.
+    pub fn to_text(&self, range: Option<ChapterRange>) -> String {
+        let total = self.chapters.len();
+        let (start, end) = match range {
+            Some(range) => (range.start.max(1), range.end.min(total)),
+            None => (1, total),
+        };
+
+        let mut text = String::new();
+        if let Some(title) = &self.title {
+            text.push_str(&format!("Title: {}\n", title));
+        }
+        if let Some(author) = &self.author {
+            text.push_str(&format!("Author: {}\n", author));
+        }
+        text.push_str(&format!("Chapters: {}-{} of {}\n", start, end, total));
+
+        for (index, chapter) in self.chapters.iter().enumerate() {
+            let number = index + 1;
+            if number < start || number > end {
+                continue;
+            }
+            text.push_str(&format!(
+                "\n## Chapter {}: {}\n\n{}\n",
+                number, chapter.title, chapter.text
+            ));
+        }
+        text
+    }
+}
+
+pub async fn read_epub(path: String) -> Result<EpubDocument, String> {
+    let bytes = fs::read(&path)
+        .await
+        .map_err(|e| format!("Failed to read file: {} (path: {})", e, path))?;
+
+    tokio::task::spawn_blocking(move || catch_panic("EPUB", || extract_epub(&bytes)))
+        .await
+        .map_err(|e| format!("EPUB extraction failed: {}", e))?
+}
+
+/// Reads the chapters of an EPUB in spine order. Documents without any text,
+/// such as cover pages, are skipped.
+pub fn extract_epub(bytes: &[u8]) -> Result<EpubDocument, String> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes))
+        .map_err(|e| format!("Failed to open EPUB: {}", e))?;
+
+    let container = read_zip_entry(&mut archive, "META-INF/container.xml")?;
+    let container = roxmltree::Document::parse(&container)
+        .map_err(|e| format!("Invalid EPUB container: {}", e))?;
+    let opf_path = container
+        .descendants()
+        .find(|node| node.has_tag_name("rootfile"))
+        .and_then(|node| node.attribute("full-path"))
+        .ok_or_else(|| "EPUB container has no package document".to_string())?
+        .to_string();
+
+    let opf = read_zip_entry(&mut archive, &opf_path)?;
+    let opf = roxmltree::Document::parse(&opf)
+        .map_err(|e| format!("Invalid EPUB package document: {}", e))?;
+
+    let metadata = |name: &str| {
+        opf.descendants()
+            .find(|node| node.tag_name().name() == name)
+            .and_then(|node| node.text())
+            .map(|text| text.trim().to_string())
+            .filter(|text| !text.is_empty())
+    };
+
+    let manifest: HashMap<&str, &str> = opf
+        .descendants()
+        .filter(|node| node.tag_name().name() == "item")
+        .filter_map(|node| Some((node.attribute("id")?, node.attribute("href")?)))
+        .collect();
+
+    let base_dir = opf_path.rsplit_once('/').map_or("", |(dir, _)| dir);
+    let mut chapters = Vec::new();
+
+    for itemref in opf
+        .descendants()
+        .filter(|node| node.tag_name().name() == "itemref")
+    {
+        let href = match itemref.attribute("idref").and_then(|id| manifest.get(id)) {
+            Some(href) => href,
+            None => continue,
+        };
+        let path = resolve_epub_path(base_dir, href);
+
+        let xhtml = match read_zip_entry(&mut archive, &path) {
+            Ok(xhtml) => xhtml,
+            Err(e) => {
+                println!("Skipping EPUB chapter {}: {}", path, e);
+                continue;
+            }
+        };
+
+        let text = html_to_text(&xhtml);
+        if text.is_empty() {
+            continue;
+        }
+
+        let title =
+            chapter_title(&xhtml).unwrap_or_else(|| format!("Chapter {}", chapters.len() + 1));
+        chapters.push(EpubChapter { title, text });
+    }
+
+    if chapters.is_empty() {
+        return Err("No readable chapters found in EPUB".to_string());
+    }
+
+    Ok(EpubDocument {
+        title: metadata("title"),
+        author: metadata("creator"),
+        chapters,
+    })
+}
+
+fn read_zip_entry(
+    archive: &mut zip::ZipArchive<Cursor<&[u8]>>,
+    path: &str,
+) -> Result<String, String> {
+    let mut file = archive
+        .by_name(path)
+        .map_err(|e| format!("Missing {} in EPUB: {}", path, e))?;
+    let mut content = String::new();
+    file.read_to_string(&mut content)
+        .map_err(|e| format!("Failed to read {} from EPUB: {}", path, e))?;
+    Ok(content)
+}
+
+// Manifest hrefs are relative to the package document and URL encoded
+fn resolve_epub_path(base_dir: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or(href);
+    let mut segments: Vec<String> = base_dir
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(String::from)
+        .collect();
+
+    for segment in href.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            _ => segments.push(percent_decode(segment)),
+        }
+    }
+    segments.join("/")
+}
+
+fn percent_decode(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+static HTML_IGNORED: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?is)<(head|script|style)\b.*?</(head|script|style)>").unwrap());
+static HTML_BLOCK_BREAK: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)<(/?(p|div|h[1-6]|li|tr|blockquote|section)\b[^>]*|br\s*/?)>").unwrap()
+});
+static HTML_TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?s)<[^>]*>").unwrap());
+static HTML_ENTITY: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"&(#[0-9]+|#[xX][0-9a-fA-F]+|[a-zA-Z]+);").unwrap());
+static HTML_HEADING: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?is)<(h[1-3]|title)\b[^>]*>(.*?)</(h[1-3]|title)>").unwrap());
+
+/// Strips XHTML down to readable text, keeping paragraph breaks.
+fn html_to_text(html: &str) -> String {
+    let html = HTML_IGNORED.replace_all(html, "");
+    let html = HTML_BLOCK_BREAK.replace_all(&html, "\n");
+    let text = decode_entities(&HTML_TAG.replace_all(&html, ""));
+
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        // Collapse runs of blank lines into a single paragraph break
+        if !line.is_empty() || lines.last().map_or(false, |last| !last.is_empty()) {
+            lines.push(line);
+        }
+    }
+    lines.join("\n").trim().to_string()
+}
+
+// Headings are preferred over <title>, which is often just the book title
+fn chapter_title(html: &str) -> Option<String> {
+    let mut headings: Vec<(bool, String)> = HTML_HEADING
+        .captures_iter(html)
+        .map(|captures| {
+            let is_title = captures[1].eq_ignore_ascii_case("title");
+            (is_title, html_to_text(&captures[2]).replace('\n', " "))
+        })
+        .filter(|(_, text)| !text.is_empty())
+        .collect();
+    headings.sort_by_key(|(is_title, _)| *is_title);
+    headings.into_iter().next().map(|(_, text)| text)
+}
+
+fn decode_entities(text: &str) -> String {
+    HTML_ENTITY
+        .replace_all(text, |captures: &regex::Captures| {
+            let entity = &captures[1];
+            let decoded = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                    .and_then(char::from_u32),
+            };
+            decoded.map_or_else(|| captures[0].to_string(), String::from)
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_urls_become_binary_attachments() {
+        let attachment = Attachment::from_content("cat.png", "data:image/png;base64,iVBORw0K");
+        assert!(matches!(attachment, Attachment::Binary { .. }));
+        assert_eq!(attachment.mime_type(), "image/png");
+        assert!(attachment.is_image());
+        assert_eq!(
+            attachment.data_url().as_deref(),
+            Some("data:image/png;base64,iVBORw0K")
+        );
+        assert_eq!(attachment.to_text(), Ok(None));
+    }
+
+    #[test]
+    fn other_content_is_text() {
+        let attachment = Attachment::from_content("notes.md", "# Notes");
+        assert_eq!(attachment.mime_type(), "text/plain");
+        assert!(!attachment.is_image());
+        assert!(attachment.data_url().is_none());
+        assert_eq!(attachment.to_text(), Ok(Some("# Notes".to_string())));
+    }
+
+    #[test]
+    fn svg_is_not_sent_as_an_image() {
+        let attachment = Attachment::from_content("logo.svg", "data:image/svg+xml;base64,PHN2Zz4=");
+        assert!(!attachment.is_image());
+    }
+
+    #[tokio::test]
+    async fn malformed_pdfs_are_an_error() {
+        // "not a pdf"
+        let attachment =
+            Attachment::from_content("broken.pdf", "data:application/pdf;base64,bm90IGEgcGRm");
+        assert!(matches!(attachment.extract_text().await, Some(Err(_))));
+        assert!(catch_panic("PDF", || -> Result<(), String> { panic!("bad xref") }).is_err());
+    }
+
+    #[tokio::test]
+    async fn extracted_text_is_reused() {
+        let attachment =
+            Attachment::from_content("book.epub", "data:application/epub+zip;base64,!!!")
+                .with_text(Some(Ok("Chapter 1".to_string())));
+        assert!(attachment.extract_text().await.is_none());
+        assert_eq!(attachment.to_text(), Ok(Some("Chapter 1".to_string())));
+
+        let image = Attachment::from_content("cat.png", "data:image/png;base64,iVBORw0K");
+        assert!(image.extract_text().await.is_none());
+    }
+
+    #[test]
+    fn html_is_reduced_to_paragraphs() {
+        let html = "<html><head><title>Book</title></head><body>\
+            <h1>One</h1><p>Fish &amp;   chips, &#8220;twice&#x201D;</p>\
+            <script>alert(1)</script><br/><br/><p>Done&hellip;</p></body></html>";
+        assert_eq!(
+            html_to_text(html),
+            "One\n\nFish & chips, \u{201c}twice\u{201d}\n\nDone&hellip;"
+        );
+    }
+
+    #[test]
+    fn headings_are_preferred_as_chapter_titles() {
+        let html = "<title>The Book</title><h2>The <em>Start</em></h2>";
+        assert_eq!(chapter_title(html).as_deref(), Some("The Start"));
+        assert_eq!(
+            chapter_title("<title>The Book</title>").as_deref(),
+            Some("The Book")
+        );
+        assert_eq!(chapter_title("<p>No heading</p>"), None);
+    }
+
+    #[test]
+    fn epub_paths_are_resolved_against_the_package() {
+        assert_eq!(
+            resolve_epub_path("OEBPS", "text/ch1.xhtml"),
+            "OEBPS/text/ch1.xhtml"
+        );
+        assert_eq!(
+            resolve_epub_path("OEBPS/content", "../ch%201.xhtml#top"),
+            "OEBPS/ch 1.xhtml"
+        );
+        assert_eq!(resolve_epub_path("", "./ch1.xhtml"), "ch1.xhtml");
+        assert_eq!(percent_decode("caf%C3%A9%zz"), "caf\u{e9}%zz");
+    }
+
+    #[test]
+    fn epubs_are_read_in_spine_order() {
+        use std::io::Write;
+        use zip::write::{FileOptions, ZipWriter};
+
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let mut add = |path: &str, content: &str| {
+            zip.start_file(path, FileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        };
+        add(
+            "META-INF/container.xml",
+            r#"<container><rootfiles><rootfile full-path="OEBPS/book.opf"/></rootfiles></container>"#,
+        );
+        add(
+            "OEBPS/book.opf",
+            r#"<package xmlns:dc="http://purl.org/dc/elements/1.1/">
+                <metadata><dc:title>Tales</dc:title><dc:creator>Anon</dc:creator></metadata>
+                <manifest>
+                    <item id="a" href="text/a.xhtml"/>
+                    <item id="b" href="text/b.xhtml"/>
+                    <item id="cover" href="cover.xhtml"/>
+                </manifest>
+                <spine><itemref idref="b"/><itemref idref="cover"/><itemref idref="a"/></spine>
+            </package>"#,
+        );
+        add("OEBPS/text/a.xhtml", "<h1>Second</h1><p>Goodbye</p>");
+        add("OEBPS/text/b.xhtml", "<p>Hello</p>");
+        add("OEBPS/cover.xhtml", "<img src=\"cover.png\"/>");
+        let bytes = zip.finish().unwrap().into_inner();
+
+        let book = extract_epub(&bytes).unwrap();
+        assert_eq!(book.title.as_deref(), Some("Tales"));
+        assert_eq!(book.author.as_deref(), Some("Anon"));
+        let titles: Vec<&str> = book.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, ["Chapter 1", "Second"]);
+
+        let text = book.to_text(Some(ChapterRange { start: 2, end: 9 }));
+        assert!(text.contains("Chapters: 2-2 of 2"));
+        assert!(text.contains("## Chapter 2: Second\n\nSecond\n\nGoodbye"));
+        assert!(!text.contains("Hello"));
+    }
+}
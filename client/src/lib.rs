@@ -1,6 +1,7 @@
 mod api;
 mod cache;
 mod config;
+mod files;
 mod keyboard_shortcuts;
 mod models;
 mod plugins;
@@ -9,7 +9,8 @@ mod models;
 mod speech;
 mod utils;
 
-use crate::api::{ApiKeys, ApiState};
+use crate::api::providers::local::LocalProvider;
+use crate::api::ApiState;
 use dotenv::dotenv;
 use std::sync::{Arc, Mutex};
 use tauri::Manager;
@@ -25,6 +26,18 @@ async fn handle_file_drop(path: String, app: tauri::AppHandle) -> Result<String,
     files::read_file_content(file_path.to_string_lossy().into_owned()).await
 }
 
+#[tauri::command]
+async fn read_pdf(path: String) -> Result<files::PdfDocument, String> {
+    println!("Extracting PDF text: {}", path);
+    files::read_pdf(path).await
+}
+
+#[tauri::command]
+async fn read_epub(path: String) -> Result<files::EpubDocument, String> {
+    println!("Extracting EPUB text: {}", path);
+    files::read_epub(path).await
+}
+
 #[tauri::command]
 async fn check_file_exists(path: String) -> bool {
     let path = std::path::PathBuf::from(path);
@@ -43,25 +56,53 @@ fn main() {
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_os::init())
         .manage(ApiState::new())
-        .manage(ApiKeys::default())
         .manage(speech::WhisperAppState::new().unwrap())
         .invoke_handler(tauri::generate_handler![
             api::get_completion,
             api::get_chat_completion,
             api::get_embeddings,
             api::get_models,
+            api::list_models,
             api::chat::send_message,
+            api::chat::stream_message,
+            api::chat::cancel_message,
+            api::compare::compare_models,
             api::chat::verify_api_key,
+            api::usage::usage_summary,
+            api::usage::get_pricing,
+            api::usage::set_pricing,
+            api::budget::budget_status,
+            api::budget::set_budgets,
+            api::fallback::get_fallback_chains,
+            api::fallback::set_fallback_chains,
+            api::router::get_router_config,
+            api::router::set_router_config,
+            api::http::get_http_config,
+            api::http::set_http_config,
+            api::tools::approval::respond_tool_approval,
+            api::tools::filesystem::get_filesystem_access,
+            api::tools::filesystem::set_filesystem_access,
+            api::mcp::list_mcp_servers,
+            api::mcp::start_mcp_server,
+            api::mcp::stop_mcp_server,
+            api::mcp::mcp_server_logs,
+            api::mcp::read_mcp_resource,
+            api::mcp::get_mcp_prompt,
+            api::mcp::set_mcp_servers,
             api::speech::text_to_speech,
             config::get_stored_api_keys,
             config::store_api_key,
             config::set_api_keys,
+            config::get_local_base_url,
+            config::set_local_base_url,
             speech::check_whisper_model,
             speech::download_whisper_model,
             speech::start_recording,
             speech::stop_recording,
             cache::init_cache_dir,
             handle_file_drop,
+            read_pdf,
+            read_epub,
             check_file_exists,
         ])
         .setup(|app| {
@@ -74,33 +115,38 @@ fn main() {
             // Load stored API keys
             if let Ok(stored_keys) = config::load_stored_keys(&app_handle) {
                 let api_state = app.state::<ApiState>();
-                let api_keys = app.state::<ApiKeys>();
 
-                // Initialize each provider's key if it exists in storage
-                if let Some(key) = stored_keys["anthropic"].as_str() {
-                    api_state.keys.set_key("anthropic", key.to_string());
-                    api_keys.set_key("anthropic", key.to_string());
-                }
-                if let Some(key) = stored_keys["perplexity"].as_str() {
-                    api_state.keys.set_key("perplexity", key.to_string());
-                    api_keys.set_key("perplexity", key.to_string());
+                // A broken proxy or certificate setting leaves the defaults
+                // in place rather than stopping the app
+                if let Err(e) = api_state
+                    .http
+                    .configure(&api::http::http_config(&stored_keys))
+                {
+                    println!("Failed to apply http config: {}", e);
                 }
-                if let Some(key) = stored_keys["openai"].as_str() {
-                    api_state.keys.set_key("openai", key.to_string());
-                    api_keys.set_key("openai", key.to_string());
-                }
-                if let Some(key) = stored_keys["xai"].as_str() {
-                    api_state.keys.set_key("xai", key.to_string());
-                    api_keys.set_key("xai", key.to_string());
-                }
-                if let Some(key) = stored_keys["google"].as_str() {
-                    api_state.keys.set_key("google", key.to_string());
-                    api_keys.set_key("google", key.to_string());
-                }
-                if let Some(key) = stored_keys["elevenlabs"].as_str() {
-                    api_state.keys.set_key("elevenlabs", key.to_string());
-                    api_keys.set_key("elevenlabs", key.to_string());
+
+                api_state.providers.register(Arc::new(LocalProvider::new(
+                    &config::local_base_url(&stored_keys),
+                )));
+                config::register_custom_providers(&api_state, &stored_keys);
+
+                // Initialize each provider's key if it exists in storage
+                for provider in api_state.providers.ids() {
+                    if let Some(key) = config::stored_key(&stored_keys, &provider) {
+                        api_state.keys.set_key(&provider, key);
+                    }
                 }
+
+                // MCP servers can take a while to launch, so the window
+                // doesn't wait for them
+                let app_handle = app_handle.clone();
+                tauri::async_runtime::spawn(async move {
+                    let api_state = app_handle.state::<ApiState>();
+                    api_state
+                        .mcp
+                        .start_all(&stored_keys, api_state.http.client(), &api_state.tools)
+                        .await;
+                });
             }
 
             Ok(())
@@ -1,9 +1,103 @@
+use crate::api::budget::BudgetAlert;
+use crate::api::providers::ProviderError;
+use crate::files::{Attachment, ChapterRange};
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+    // Results of tool calls, sent back to the model
+    Tool,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::System => "system",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+            Role::Tool => "tool",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ChatMessage {
-    pub role: String,
+    pub role: Role,
+    pub content: String,
+    // Tools the assistant called in this turn
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    // Set on `Role::Tool` turns
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_result: Option<ToolResult>,
+    // Anthropic's signed thinking blocks, which have to be sent back with
+    // tool results. Only kept for the length of a tool loop.
+    #[serde(skip)]
+    pub thinking_blocks: Vec<serde_json::Value>,
+}
+
+impl ChatMessage {
+    pub fn new(role: Role, content: impl Into<String>) -> Self {
+        Self {
+            role,
+            content: content.into(),
+            tool_calls: Vec::new(),
+            tool_result: None,
+            thinking_blocks: Vec::new(),
+        }
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self::new(Role::User, content)
+    }
+
+    pub fn tool_calls(content: impl Into<String>, calls: Vec<ToolCall>) -> Self {
+        Self {
+            tool_calls: calls,
+            ..Self::new(Role::Assistant, content)
+        }
+    }
+
+    pub fn tool_result(result: ToolResult) -> Self {
+        Self {
+            tool_result: Some(result),
+            ..Self::new(Role::Tool, "")
+        }
+    }
+
+    /// Whether the turn carries tool calls or results, which can't be
+    /// merged into neighbouring turns.
+    pub fn has_tool_data(&self) -> bool {
+        !self.tool_calls.is_empty() || self.tool_result.is_some()
+    }
+}
+
+/// A tool the model can call, as advertised in the request.
+#[derive(Serialize, Clone, Debug)]
+pub struct ToolSpec {
+    pub name: String,
+    pub description: String,
+    // JSON Schema of the arguments object
+    pub parameters: serde_json::Value,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    pub arguments: serde_json::Value,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ToolResult {
+    pub call_id: String,
+    pub name: String,
     pub content: String,
+    pub is_error: bool,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -29,36 +123,146 @@ pub struct ChatResponseContent {
     pub content_type: String,
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct PerplexityResponse {
-    pub choices: Vec<PerplexityChoice>,
-}
-
-#[derive(Serialize, Deserialize)]
-pub struct PerplexityChoice {
-    pub message: PerplexityMessage,
+/// Source metadata Perplexity returns next to the usual chat completion
+/// fields. Streamed responses repeat it on every chunk.
+#[derive(Deserialize, Default)]
+pub struct PerplexitySources {
+    // URLs in the order the `[n]` markers refer to them
+    #[serde(default)]
+    pub citations: Vec<String>,
+    #[serde(default)]
+    pub search_results: Vec<PerplexitySearchResult>,
+    #[serde(default)]
+    pub images: Vec<PerplexityImage>,
+    #[serde(default)]
+    pub related_questions: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct PerplexityMessage {
-    pub content: String,
+#[derive(Deserialize)]
+pub struct PerplexitySearchResult {
+    pub url: String,
     #[serde(default)]
-    pub citations: Vec<PerplexityCitation>,
+    pub title: Option<String>,
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct PerplexityCitation {
-    pub text: String,
-    pub url: String,
+// Older API versions return bare URLs
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum PerplexityImage {
+    Url(String),
+    Detailed { image_url: String },
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Default)]
 pub struct ApiResponse {
     pub content: Option<String>,
     pub error: Option<String>,
+    // Structured form of `error` when it came from a provider call
+    pub provider_error: Option<ProviderError>,
     pub citations: Option<Vec<Citation>>,
+    // What the model thought before answering, where the provider shares it
+    pub reasoning: Option<String>,
     pub images: Option<Vec<String>>,
     pub related_questions: Option<Vec<String>>,
+    pub usage: Option<Usage>,
+    // In USD, when the model has a price in the pricing table
+    pub cost: Option<f64>,
+    // Spending budgets the request went over, or would have
+    pub budget_alerts: Option<Vec<BudgetAlert>>,
+    // Tools the model asked to have called before it can answer
+    pub tool_calls: Option<Vec<ToolCall>>,
+    // The answer parsed as JSON, for requests with a `response_schema`
+    pub structured: Option<serde_json::Value>,
+    // Set when the request was stopped through `cancel_message`
+    pub cancelled: bool,
+    // Generation parameters the provider has no equivalent for
+    pub ignored_params: Option<Vec<String>>,
+    // The model that answered, for requests with a `fallback_chain`
+    pub answered_by: Option<ModelTarget>,
+    // Models of the chain that failed before one answered
+    pub skipped_models: Option<Vec<SkippedModel>>,
+    // How the model was picked, for requests to the `auto` model
+    pub route: Option<RouteDecision>,
+    // See `ChatMessage::thinking_blocks`
+    #[serde(skip)]
+    pub thinking_blocks: Vec<serde_json::Value>,
+}
+
+impl ApiResponse {
+    pub fn text(content: impl Into<String>) -> Self {
+        Self {
+            content: Some(content.into()),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_usage(mut self, usage: Option<Usage>) -> Self {
+        self.usage = usage;
+        self
+    }
+
+    pub fn with_reasoning(mut self, reasoning: String) -> Self {
+        self.reasoning = if reasoning.is_empty() {
+            None
+        } else {
+            Some(reasoning)
+        };
+        self
+    }
+
+    pub fn with_tool_calls(mut self, calls: Vec<ToolCall>) -> Self {
+        self.tool_calls = if calls.is_empty() { None } else { Some(calls) };
+        self
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            error: Some(message.into()),
+            ..Default::default()
+        }
+    }
+
+    pub fn failed(error: ProviderError) -> Self {
+        Self {
+            error: Some(error.to_string()),
+            provider_error: Some(error),
+            ..Default::default()
+        }
+    }
+
+    pub fn cancelled(provider: &str) -> Self {
+        Self {
+            cancelled: true,
+            provider_error: Some(ProviderError::cancelled(provider)),
+            ..Default::default()
+        }
+    }
+}
+
+/// A model on a given provider, as named in fallback chains and comparisons.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ModelTarget {
+    pub provider: String,
+    pub model: String,
+}
+
+/// The router's pick for a request to the `auto` model.
+#[derive(Serialize, Clone, Debug)]
+pub struct RouteDecision {
+    pub provider: String,
+    pub model: String,
+    // Name of the rule that picked the model
+    pub rule: String,
+    pub reason: String,
+    // Earlier rules that matched but whose model couldn't take the request
+    pub passed_over: Vec<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SkippedModel {
+    pub provider: String,
+    pub model: String,
+    pub error: ProviderError,
 }
 
 #[derive(Deserialize)]
@@ -67,17 +271,330 @@ pub struct VerifyRequest {
     pub provider: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub struct SendMessageRequest {
+    // Single prompt sent by older frontend builds, used when `messages` is empty
+    #[serde(default)]
     pub message: String,
+    // Full thread history, oldest first
+    #[serde(default)]
+    pub messages: Vec<ChatMessage>,
     pub model: String,
     pub provider: String,
     pub file_content: Option<String>,
     pub file_name: Option<String>,
+    // Limits an EPUB attachment to these chapters, the whole book otherwise
+    #[serde(default)]
+    pub chapters: Option<ChapterRange>,
+    // Tags stream events and lets the frontend cancel the request
+    #[serde(default)]
+    pub request_id: Option<String>,
+    // Thread the message belongs to, recorded in the usage ledger
+    #[serde(default)]
+    pub thread_id: Option<String>,
+    // Names of the registered tools to offer. None are offered when not set.
+    #[serde(default)]
+    pub enabled_tools: Option<Vec<String>>,
+    // Filled in from the tool registry for providers that support tools
+    #[serde(skip)]
+    pub tools: Vec<ToolSpec>,
+    // JSON Schema the answer must match. The parsed answer comes back in
+    // `ApiResponse::structured`.
+    #[serde(default)]
+    pub response_schema: Option<serde_json::Value>,
+    // Named chain from `fallback_chains` to move along when the model is
+    // rate limited or unavailable
+    #[serde(default)]
+    pub fallback_chain: Option<String>,
+    // Text of a PDF or EPUB attachment, read once before sending
+    #[serde(skip)]
+    pub attachment_text: Option<Result<String, String>>,
+    #[serde(flatten)]
+    pub params: GenerationParams,
+}
+
+/// Optional generation settings. Anything left unset falls back to the
+/// provider's own defaults.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct GenerationParams {
+    pub system_prompt: Option<String>,
+    pub temperature: Option<f64>,
+    pub top_p: Option<f64>,
+    pub max_tokens: Option<u32>,
+    pub stop: Option<Vec<String>>,
+    pub seed: Option<i64>,
+    // Perplexity search options: "hour", "day", "week" or "month"
+    pub search_recency_filter: Option<String>,
+    pub return_images: Option<bool>,
+    pub return_related_questions: Option<bool>,
+    // Tokens Claude and Gemini may spend thinking before they answer
+    pub thinking_budget: Option<u32>,
+    // "low", "medium" or "high", for OpenAI o-series and Grok mini models
+    pub reasoning_effort: Option<String>,
+}
+
+impl GenerationParams {
+    /// Names of the sampling parameters that were set, as used by
+    /// `Provider::supported_params`. The system prompt is always supported.
+    pub fn requested(&self) -> Vec<&'static str> {
+        let mut names = Vec::new();
+        if self.temperature.is_some() {
+            names.push("temperature");
+        }
+        if self.top_p.is_some() {
+            names.push("top_p");
+        }
+        if self.max_tokens.is_some() {
+            names.push("max_tokens");
+        }
+        if self.stop.as_ref().map_or(false, |stop| !stop.is_empty()) {
+            names.push("stop");
+        }
+        if self.seed.is_some() {
+            names.push("seed");
+        }
+        if self.search_recency_filter.is_some() {
+            names.push("search_recency_filter");
+        }
+        if self.return_images.is_some() {
+            names.push("return_images");
+        }
+        if self.return_related_questions.is_some() {
+            names.push("return_related_questions");
+        }
+        if self.thinking_budget.is_some() {
+            names.push("thinking_budget");
+        }
+        if self.reasoning_effort.is_some() {
+            names.push("reasoning_effort");
+        }
+        names
+    }
+}
+
+impl SendMessageRequest {
+    pub fn request_id(&self) -> String {
+        self.request_id
+            .clone()
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+    }
+
+    pub fn attachment(&self) -> Option<Attachment> {
+        let content = self.file_content.as_deref().filter(|c| !c.is_empty())?;
+        let name = self.file_name.as_deref().unwrap_or("attachment");
+        Some(
+            Attachment::from_content(name, content)
+                .with_chapters(self.chapters)
+                .with_text(self.attachment_text.clone()),
+        )
+    }
+
+    /// Whether the request asks for any of the registered tools.
+    pub fn offers_tools(&self) -> bool {
+        self.response_schema.is_none()
+            && self
+                .enabled_tools
+                .as_ref()
+                .map_or(false, |names| !names.is_empty())
+    }
+
+    /// The ordered turns to send to the model, starting with the request's
+    /// system prompt when one was given.
+    pub fn conversation(&self) -> Vec<ChatMessage> {
+        let mut conversation = Vec::with_capacity(self.messages.len() + 1);
+        if let Some(system_prompt) = &self.params.system_prompt {
+            conversation.push(ChatMessage::new(Role::System, system_prompt.clone()));
+        }
+
+        if self.messages.is_empty() {
+            conversation.push(ChatMessage::user(self.message.clone()));
+        } else {
+            conversation.extend(self.messages.iter().cloned());
+        }
+        conversation
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct Citation {
+    // The `n` of the `[n]` markers in the response text that point here
+    pub index: usize,
     pub url: String,
     pub title: Option<String>,
 }
+
+/// A model as reported by `list_models`, normalized across providers.
+/// Fields a provider doesn't report are left empty or false.
+#[derive(Serialize, Clone, Debug)]
+pub struct ModelInfo {
+    pub id: String,
+    pub display_name: String,
+    pub context_window: Option<u64>,
+    // Input the model accepts besides text
+    pub vision: bool,
+    pub documents: bool,
+    pub audio: bool,
+}
+
+impl ModelInfo {
+    pub fn new(id: impl Into<String>) -> Self {
+        let id = id.into();
+        Self {
+            display_name: id.clone(),
+            id,
+            context_window: None,
+            vision: false,
+            documents: false,
+            audio: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Usage {
+    pub input_tokens: Option<u64>,
+    pub output_tokens: Option<u64>,
+    // Part of `input_tokens` read from the provider's prompt cache
+    #[serde(default)]
+    pub cached_tokens: Option<u64>,
+    // Part of `input_tokens` written to the prompt cache, which some
+    // providers bill above the input price
+    #[serde(default)]
+    pub cache_write_tokens: Option<u64>,
+    // Part of `output_tokens` spent on reasoning the user never sees
+    #[serde(default)]
+    pub reasoning_tokens: Option<u64>,
+}
+
+impl Usage {
+    /// Adds the counts of another call made for the same request.
+    pub fn add(&mut self, other: &Usage) {
+        fn sum(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+            match (a, b) {
+                (None, None) => None,
+                _ => Some(a.unwrap_or(0) + b.unwrap_or(0)),
+            }
+        }
+
+        self.input_tokens = sum(self.input_tokens, other.input_tokens);
+        self.output_tokens = sum(self.output_tokens, other.output_tokens);
+        self.cached_tokens = sum(self.cached_tokens, other.cached_tokens);
+        self.cache_write_tokens = sum(self.cache_write_tokens, other.cache_write_tokens);
+        self.reasoning_tokens = sum(self.reasoning_tokens, other.reasoning_tokens);
+    }
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase", tag = "event", content = "data")]
+pub enum StreamEvent {
+    #[serde(rename_all = "camelCase")]
+    Delta {
+        request_id: String,
+        text: String,
+    },
+    // Streamed apart from the answer's text
+    #[serde(rename_all = "camelCase")]
+    Reasoning {
+        request_id: String,
+        text: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    Usage {
+        request_id: String,
+        usage: Usage,
+    },
+    #[serde(rename_all = "camelCase")]
+    Done {
+        request_id: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    Error {
+        request_id: String,
+        message: String,
+        error: Option<ProviderError>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Cancelled {
+        request_id: String,
+    },
+    Retry(RetryProgress),
+    // Anything streamed so far came from the failed model and should be
+    // discarded
+    Fallback(FallbackNotice),
+    #[serde(rename_all = "camelCase")]
+    ToolCall {
+        request_id: String,
+        step: usize,
+        call: ToolCall,
+    },
+    #[serde(rename_all = "camelCase")]
+    ToolResult {
+        request_id: String,
+        step: usize,
+        result: ToolResult,
+    },
+}
+
+/// Reported when a model of a fallback chain fails and the next one takes
+/// over, on the stream channel or as a `model-fallback` event for
+/// `send_message`.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FallbackNotice {
+    pub request_id: String,
+    pub skipped: SkippedModel,
+    pub next: ModelTarget,
+}
+
+/// Reported while a provider call waits to be retried, on the stream channel
+/// or as a `retry-progress` event for `send_message`.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RetryProgress {
+    pub request_id: String,
+    pub attempt: u32,
+    pub max_attempts: u32,
+    pub delay_ms: u64,
+    pub reason: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request(body: serde_json::Value) -> SendMessageRequest {
+        serde_json::from_value(body).unwrap()
+    }
+
+    #[test]
+    fn conversation_puts_system_prompt_first() {
+        let request = request(json!({
+            "model": "m",
+            "provider": "p",
+            "system_prompt": "Be brief",
+            "messages": [
+                {"role": "user", "content": "Hi"},
+                {"role": "assistant", "content": "Hello"},
+                {"role": "user", "content": "Bye"}
+            ]
+        }));
+        let conversation = request.conversation();
+        let roles: Vec<_> = conversation.iter().map(|message| message.role).collect();
+        assert_eq!(
+            roles,
+            [Role::System, Role::User, Role::Assistant, Role::User]
+        );
+        assert_eq!(conversation[0].content, "Be brief");
+        assert_eq!(conversation[3].content, "Bye");
+    }
+
+    #[test]
+    fn conversation_falls_back_to_single_message() {
+        let request = request(json!({"model": "m", "provider": "p", "message": "Hi"}));
+        let conversation = request.conversation();
+        assert_eq!(conversation.len(), 1);
+        assert_eq!(conversation[0].role, Role::User);
+        assert_eq!(conversation[0].content, "Hi");
+    }
+}
@@ -1,9 +1,9 @@
+use crate::api::ApiState;
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::{Sample, SampleFormat};
 use futures_util::StreamExt;
 use once_cell::sync::Lazy;
 use regex::Regex;
-use reqwest::Client;
 use serde::Serialize;
 use std::os::unix::fs::PermissionsExt;
 use std::path::PathBuf;
@@ -235,7 +235,7 @@ pub async fn download_whisper_model<R: Runtime>(window: Window<R>) -> Result<(),
     }
 
     println!("Downloading model from {}", MODEL_URL);
-    let client = Client::new();
+    let client = window.state::<ApiState>().http.client();
     let response = client
         .get(MODEL_URL)
         .send()
@@ -1,26 +1,9 @@
-mod models;
-mod ai;
 mod profile;
 mod plugin;
 
-use models::CompletionRequest;
 use tauri::{Manager, State};
 use plugin::PreferencesState;
 
-#[tauri::command]
-async fn verify_api_key(provider: String, key: String) -> Result<bool, String> {
-    ai::verify_api_key(&provider, &key)
-        .await
-        .map_err(|e| e.to_string())
-}
-
-#[tauri::command]
-async fn generate_completion(request: CompletionRequest) -> Result<String, String> {
-    ai::generate_completion(request)
-        .await
-        .map_err(|e| e.to_string())
-}
-
 #[tauri::command]
 async fn get_api_keys() -> Result<String, String> {
     Ok("".to_string())
@@ -51,8 +34,6 @@ async fn main() {
     tauri::Builder::default()
         .plugin(tauri::plugins::shell::init())
         .invoke_handler(tauri::generate_handler![
-            generate_completion,
-            verify_api_key,
             get_api_keys,
             set_api_keys,
             profile::load_profile_settings,
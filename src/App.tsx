@@ -41,6 +41,9 @@ import { Toaster } from '@/components/ui/sonner';
 interface ApiResponse {
   content?: string;
   error?: string;
+  citations?: Array<{ index: number; url: string; title?: string }>;
+  images?: string[];
+  related_questions?: string[];
 }
 
 // Add type definition at the top
@@ -438,11 +441,19 @@ function App() {
           provider: model?.provider
         });
 
+        // Send the thread history so the model sees earlier turns
+        const activeThread = threads.find(t => t.id === activeThreadId && !t.isNote) as ChatThread | undefined;
+        const conversation = [...(activeThread?.messages ?? []), userMessage]
+          .filter(m => m.role === 'user' || m.role === 'assistant' || m.role === 'system')
+          .map(({ role, content }) => ({ role, content }));
+
         const response = await invoke<ApiResponse>('send_message', {
           request: {
             message,
+            messages: conversation,
             model: modelToUse,
-            provider: model?.provider
+            provider: model?.provider,
+            thread_id: activeThreadId
           }
         });
 
@@ -486,7 +497,10 @@ function App() {
                   role: 'assistant',
                   content: response.content,
                   timestamp: Date.now(),
-                  modelId: modelToUse
+                  modelId: modelToUse,
+                  citations: response.citations,
+                  images: response.images,
+                  relatedQuestions: response.related_questions
                 }],
                 updatedAt: Date.now()
               };
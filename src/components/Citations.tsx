@@ -3,6 +3,7 @@ import { ExternalLink } from 'lucide-react';
 import { cn } from '@/lib/utils';
 
 interface Citation {
+  index?: number;
   url: string;
   title?: string;
 }
@@ -30,6 +31,9 @@ export const Citations: React.FC<CitationsProps> = ({ citations, className }) =>
                      focus-visible:outline-none focus-visible:ring-1 focus-visible:ring-ring"
           >
             <ExternalLink className="h-3 w-3 flex-shrink-0" />
+            {citation.index !== undefined && (
+              <span className="text-muted-foreground">[{citation.index}]</span>
+            )}
             <span className="truncate underline underline-offset-2 decoration-blue-500/50 dark:decoration-blue-400/50">
               {citation.title || citation.url}
             </span>
@@ -4,6 +4,7 @@ export interface Message {
   timestamp?: number;
   modelId?: string;
   citations?: Array<{
+    index?: number;
     url: string;
     title?: string;
   }>;
@@ -95,6 +96,8 @@ export interface ApiKeys {
   xai?: string;
   google?: string;
   elevenlabs?: string;
+  // Keys for providers declared under `custom_providers` in config.json
+  custom?: Record<string, string | null>;
 }
 
 export interface PluginModification {
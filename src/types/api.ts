@@ -1,25 +1,324 @@
+export type MessageRole = 'system' | 'user' | 'assistant' | 'tool';
+
 export type SendMessageRequest = {
-  message: string;
+  message?: string;
+  messages?: ChatMessage[];
   model: string;
   provider: string;
   file_content?: string;
   file_name?: string;
+  chapters?: ChapterRange;
+  request_id?: string;
+  thread_id?: string;
+  system_prompt?: string;
+  temperature?: number;
+  top_p?: number;
+  max_tokens?: number;
+  stop?: string[];
+  seed?: number;
+  search_recency_filter?: 'hour' | 'day' | 'week' | 'month';
+  return_images?: boolean;
+  return_related_questions?: boolean;
+  // Tokens Claude 3.7 and Gemini 2.5 or later may spend thinking before answering
+  thinking_budget?: number;
+  // OpenAI o-series and Grok mini models
+  reasoning_effort?: 'low' | 'medium' | 'high';
+  // Registered tools to offer the model, none when missing
+  enabled_tools?: string[];
+  // JSON Schema the answer must match; the parsed answer is `structured`
+  response_schema?: Record<string, unknown>;
+  // Name of a chain in `fallback_chains` to fall back along
+  fallback_chain?: string;
 }
 
 export type ApiResponse = {
   content?: string;
   error?: string;
+  provider_error?: ProviderError;
   citations?: Array<{
+    index: number;
     url: string;
     title?: string;
   }>;
   images?: string[];
   related_questions?: string[];
+  // The model's thinking, where the provider shares it
+  reasoning?: string;
+  usage?: Usage;
+  // USD, missing when the model has no price
+  cost?: number;
+  budget_alerts?: BudgetAlert[];
+  // Calls the model made that were not run
+  tool_calls?: ToolCall[];
+  structured?: unknown;
+  cancelled?: boolean;
+  ignored_params?: string[];
+  // Set for requests with a `fallback_chain`
+  answered_by?: ModelTarget;
+  skipped_models?: SkippedModel[];
+  // Set for requests to the `auto` model
+  route?: RouteDecision;
+}
+
+// Lets the UI offer a fix for the failure instead of showing the raw body
+export type ProviderErrorKind =
+  | 'auth'
+  | 'rate_limited'
+  | 'context_length_exceeded'
+  | 'content_filtered'
+  | 'quota_exhausted'
+  | 'unavailable'
+  | 'network'
+  | 'invalid_request'
+  | 'bad_response'
+  | 'cancelled';
+
+export type ProviderError = {
+  kind: ProviderErrorKind;
+  provider: string;
+  status?: number;
+  message: string;
+  // API keys are masked before this leaves the backend
+  raw_body?: string;
+  // Only set for `rate_limited`, in seconds
+  retry_after?: number;
+}
+
+export type Usage = {
+  input_tokens?: number;
+  output_tokens?: number;
+  // Subset of input_tokens served from the prompt cache
+  cached_tokens?: number;
+  // Subset of input_tokens written to the prompt cache
+  cache_write_tokens?: number;
+  // Subset of output_tokens spent on hidden reasoning
+  reasoning_tokens?: number;
+}
+
+// USD per million tokens; `model` matches ids by prefix
+export type ModelPrice = {
+  provider: string;
+  model: string;
+  input: number;
+  output: number;
+  cached_input?: number;
+  cache_write?: number;
+}
+
+// Stored under `budgets` in config.json; periods are UTC days and months
+export type Budget = {
+  // All providers combined when missing
+  provider?: string;
+  period: 'daily' | 'monthly';
+  limit: number;
+  action?: 'block' | 'warn';
+  // Refuse models without a price too; they only get a warning otherwise
+  block_unpriced?: boolean;
+}
+
+export type BudgetAlert = {
+  budget: Budget;
+  spent: number;
+  estimate: number;
+  // The model has no price, so `estimate` is 0 and the budget can't be
+  // checked. Only blocks with `block_unpriced`.
+  unpriced: boolean;
+  blocked: boolean;
+}
+
+// Row returned by `budget_status`
+export type BudgetStatus = {
+  budget: Budget;
+  spent: number;
+  remaining: number;
+  // Unix seconds
+  resets_at: number;
+}
+
+export type UsageGrouping = 'day' | 'model' | 'thread';
+
+// Row returned by `usage_summary`
+export type UsageTotal = {
+  key?: string;
+  requests: number;
+  input_tokens: number;
+  output_tokens: number;
+  cached_tokens: number;
+  cache_write_tokens: number;
+  reasoning_tokens: number;
+  cost: number;
+  unpriced_requests: number;
+}
+
+// Events sent on the channel passed to `stream_message`
+export type StreamEvent =
+  | { event: 'delta'; data: { requestId: string; text: string } }
+  | { event: 'reasoning'; data: { requestId: string; text: string } }
+  | { event: 'usage'; data: { requestId: string; usage: Usage } }
+  | { event: 'done'; data: { requestId: string } }
+  | { event: 'error'; data: { requestId: string; message: string; error?: ProviderError } }
+  | { event: 'cancelled'; data: { requestId: string } }
+  | { event: 'retry'; data: RetryProgress }
+  | { event: 'fallback'; data: FallbackNotice }
+  | { event: 'toolCall'; data: { requestId: string; step: number; call: ToolCall } }
+  | { event: 'toolResult'; data: { requestId: string; step: number; result: ToolResult } };
+
+// A model to send the prompt to, in `compare_models` and fallback chains
+export type ModelTarget = {
+  provider: string;
+  model: string;
+}
+
+// Stored under `fallback_chains` in config.json, keyed by chain name
+export type FallbackChains = Record<string, ModelTarget[]>;
+
+export type SkippedModel = ModelTarget & {
+  error: ProviderError;
+}
+
+// Also emitted as the `model-fallback` event during `send_message`. Text
+// streamed before it came from the skipped model and should be dropped.
+export type FallbackNotice = {
+  requestId: string;
+  skipped: SkippedModel;
+  next: ModelTarget;
+}
+
+// How the router picked the model for a request to the `auto` model
+export type RouteDecision = {
+  provider: string;
+  model: string;
+  rule: string;
+  reason: string;
+  // Earlier rules that matched but whose model couldn't take the request
+  passed_over: string[];
+}
+
+// An entry of `router.rules` in config.json. Every condition that is set
+// has to hold; a rule with none matches everything.
+export type RouteRule = ModelTarget & {
+  name: string;
+  // Removed from the prompt when it matches, e.g. "search:"
+  prefixes?: string[];
+  keywords?: string[];
+  attachment?: 'image' | 'pdf' | 'epub' | 'text' | 'any';
+  min_prompt_tokens?: number;
+}
+
+// Stored under `router`; the built-in rules apply while `rules` is empty
+export type RouterConfig = {
+  rules: RouteRule[];
+  // USD per request
+  max_cost?: number;
+}
+
+// Returned by `compare_models` in the order of its targets. Each model
+// streams under `requestId`, which is the comparison's id plus `-<index>`.
+// Cancelling the comparison's id cancels every model.
+export type CompareResult = {
+  provider: string;
+  model: string;
+  requestId: string;
+  latencyMs: number;
+  response: ApiResponse;
+}
+
+// Tool events are also emitted as `tool-event` during `send_message`
+export type ToolCall = {
+  id: string;
+  name: string;
+  arguments: unknown;
+}
+
+export type ToolResult = {
+  call_id: string;
+  name: string;
+  content: string;
+  is_error: boolean;
+}
+
+// Emitted as `tool-approval`; answer with `respond_tool_approval`
+export type ApprovalRequest = {
+  approvalId: string;
+  requestId: string;
+  tool: string;
+  summary: string;
+  detail?: string;
+}
+
+// An entry of `mcp_servers` in config.json; set `command` or `url`
+export type McpServerConfig = {
+  // Up to 32 characters, unique once anything but letters, digits, _ and -
+  // becomes _, and without __ or a trailing _
+  id: string;
+  command?: string;
+  args?: string[];
+  env?: Record<string, string>;
+  cwd?: string;
+  url?: string;
+  headers?: Record<string, string>;
+  enabled?: boolean;
+  // Runs the server's tools without asking first, off by default
+  auto_approve?: boolean;
+}
+
+export type McpServerStatus =
+  | { state: 'stopped' }
+  | { state: 'starting' }
+  | { state: 'running' }
+  | { state: 'failed'; error: string };
+
+export type McpResource = {
+  uri: string;
+  name: string;
+  description?: string;
+  mimeType?: string;
+}
+
+export type McpPrompt = {
+  name: string;
+  description?: string;
+  arguments: Array<{ name: string; description?: string; required: boolean }>;
+}
+
+// Row returned by `list_mcp_servers`
+export type McpServerInfo = {
+  config: McpServerConfig;
+  status: McpServerStatus;
+  server_info?: { name: string; version?: string };
+  // Registered as `<server>__<tool>`
+  tools: string[];
+  resources: McpResource[];
+  prompts: McpPrompt[];
+}
+
+// Returned by `mcp_server_logs`
+export type McpLogEntry = {
+  timestamp: number;
+  level: string;
+  message: string;
+}
+
+// Stored under `filesystem_access` in config.json
+export type FilesystemAccess = {
+  roots: string[];
+  allow_write: boolean;
+}
+
+// Also emitted as the `retry-progress` event during `send_message`
+export type RetryProgress = {
+  requestId: string;
+  attempt: number;
+  maxAttempts: number;
+  delayMs: number;
+  reason: string;
 }
 
 export type ChatMessage = {
-  role: string;
+  role: MessageRole;
   content: string;
+  tool_calls?: ToolCall[];
+  tool_result?: ToolResult;
 }
 
 export type ChatRequest = {
@@ -37,4 +336,68 @@ export type ChatResponse = {
   }>;
   model: string;
   role: string;
-} 
\ No newline at end of file
+} 
+// Returned by `read_pdf`
+export type PdfDocument = {
+  title?: string;
+  page_count: number;
+  pages: string[];
+}
+
+// 1-based, inclusive
+export type ChapterRange = {
+  start: number;
+  end: number;
+}
+
+// Returned by `read_epub`
+export type EpubDocument = {
+  title?: string;
+  author?: string;
+  chapters: Array<{
+    title: string;
+    text: string;
+  }>;
+}
+
+// An entry of `custom_providers` in config.json
+export type CustomProviderConfig = {
+  id: string;
+  name?: string;
+  base_url: string;
+  auth?: 'bearer' | 'none' | { header: string } | { query: string };
+  headers?: Record<string, string>;
+  format?: 'openai' | 'anthropic' | 'gemini';
+  models?: string[];
+}
+
+// Returned by `list_models`
+export type ModelInfo = {
+  id: string;
+  display_name: string;
+  context_window?: number;
+  vision: boolean;
+  documents: boolean;
+  audio: boolean;
+}
+
+// Seconds; unset ones fall back to the general timeouts, then the defaults
+export type Timeouts = {
+  connect_secs?: number;
+  // Longest wait for the response or the next chunk of a stream
+  read_secs?: number;
+}
+
+// Stored under `http` in config.json
+export type HttpConfig = {
+  // http://, https://, socks5:// or socks5h://, with user:password@ if needed
+  proxy?: string;
+  // Comma separated hosts that skip the proxy, localhost by default
+  no_proxy?: string;
+  timeouts?: Timeouts;
+  // Keyed by provider id
+  provider_timeouts?: Record<string, Timeouts>;
+  // Paths of PEM files trusted on top of the system roots
+  ca_certificates?: string[];
+  user_agent?: string;
+}